@@ -0,0 +1,45 @@
+//! Builds every puzzle in static/puzzles into the game, so a new file only needs to be added
+//! there. The list is written to `$OUT_DIR/puzzle_files.rs` and included by `puzzle.rs`.
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::Path;
+
+fn main() {
+    let manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+    let puzzle_dir = Path::new(&manifest_dir).join("static").join("puzzles");
+    println!("cargo:rerun-if-changed={}", puzzle_dir.display());
+
+    // Sorted so the order doesn't depend on the file system
+    let mut paths: Vec<_> = fs::read_dir(&puzzle_dir)
+        .expect("Can't read static/puzzles")
+        .map(|entry| entry.expect("Can't read static/puzzles").path())
+        .filter(|path| path.extension() == Some(OsStr::new("txt")))
+        .collect();
+    paths.sort();
+
+    let mut code = format!(
+        "pub const PUZZLE_FILES: [PuzzleFile; {}] = [\n",
+        paths.len()
+    );
+    for path in paths.iter() {
+        println!("cargo:rerun-if-changed={}", path.display());
+        // The name is shown in menus, which shouldn't need to parse the whole puzzle
+        let text = fs::read_to_string(path).expect("Can't read a puzzle file");
+        let name = text
+            .lines()
+            .find_map(|line| line.trim().strip_prefix("name "))
+            .unwrap_or_else(|| panic!("{} has no name line", path.display()));
+        code += &format!(
+            "    PuzzleFile {{\n        code: {:?},\n        name: {:?},\n        text: include_str!({:?}),\n    }},\n",
+            path.file_stem().unwrap().to_str().unwrap(),
+            name.trim(),
+            path.to_str().unwrap(),
+        );
+    }
+    code += "];\n";
+
+    let out_dir = env::var("OUT_DIR").unwrap();
+    fs::write(Path::new(&out_dir).join("puzzle_files.rs"), code)
+        .expect("Can't write the puzzle list");
+}
@@ -0,0 +1,419 @@
+use crate::field::{CheckableField, Field};
+use crate::gamestate::GameState;
+use crate::keybindings::Trigger;
+use crate::movegen::{placements, placements_from, Placement};
+use crate::position::{p, Coord};
+use crate::shapes::Shape;
+use crate::tetromino::Tetromino;
+use crate::time::GameTime;
+use alloc::collections::vec_deque::VecDeque;
+use alloc::vec::Vec;
+use core::time::Duration;
+
+/// How quickly and how far ahead the AI plays
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+    Expert,
+}
+
+/// How much each feature of a board counts towards the AI choosing it. Negative weights are
+/// penalties.
+#[derive(Copy, Clone, Debug)]
+pub struct Weights {
+    /// The sum of every column's height
+    pub aggregate_height: f32,
+    /// Empty blocks with a block somewhere above them
+    pub holes: f32,
+    /// The sum of height differences between neighbouring columns
+    pub bumpiness: f32,
+    /// The sum of depths of columns lower than both neighbours
+    pub wells: f32,
+    /// Rewards for clearing 0 to 4 lines with one piece
+    pub line_clears: [f32; 5],
+    /// Added for each line cleared with a T-spin
+    pub t_spin: f32,
+    /// Slots a T piece could spin into for a double
+    pub t_slots: f32,
+}
+
+/// Plays a game through the same inputs as a player, choosing where each piece goes by
+/// evaluating the boards it would leave
+pub struct AiPlayer {
+    difficulty: Difficulty,
+    weights: Weights,
+    plan: VecDeque<Trigger>,
+    /// When the next planned input is due, or `None` while waiting for a new piece
+    next_input_time: Option<GameTime>,
+    last_drop_time: Option<GameTime>,
+}
+
+/// The score of a board the AI must avoid, such as one where it has lost
+const LOSING_SCORE: f32 = -1.0e9;
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 4] = [
+        Difficulty::Easy,
+        Difficulty::Normal,
+        Difficulty::Hard,
+        Difficulty::Expert,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Normal => "Normal",
+            Difficulty::Hard => "Hard",
+            Difficulty::Expert => "Expert",
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Difficulty::Easy => "easy",
+            Difficulty::Normal => "normal",
+            Difficulty::Hard => "hard",
+            Difficulty::Expert => "expert",
+        }
+    }
+
+    pub fn from_code(code: &str) -> Option<Difficulty> {
+        Difficulty::ALL.iter().copied().find(|d| d.code() == code)
+    }
+
+    /// How long a new piece is looked at before it starts moving
+    fn think_time(&self) -> Duration {
+        Duration::from_millis(match self {
+            Difficulty::Easy => 600,
+            Difficulty::Normal => 300,
+            Difficulty::Hard => 120,
+            Difficulty::Expert => 0,
+        })
+    }
+
+    /// The time between inputs while a piece moves
+    fn input_interval(&self) -> Duration {
+        Duration::from_millis(match self {
+            Difficulty::Easy => 120,
+            Difficulty::Normal => 60,
+            Difficulty::Hard => 30,
+            Difficulty::Expert => 0,
+        })
+    }
+
+    /// The most pieces placed per second
+    fn max_pps(&self) -> f32 {
+        match self {
+            Difficulty::Easy => 0.8,
+            Difficulty::Normal => 1.5,
+            Difficulty::Hard => 2.5,
+            Difficulty::Expert => 5.0,
+        }
+    }
+
+    /// How many preview pieces are taken into account
+    fn lookahead(&self) -> usize {
+        match self {
+            Difficulty::Easy => 0,
+            _ => 1,
+        }
+    }
+}
+
+impl Weights {
+    pub fn new() -> Weights {
+        Weights {
+            aggregate_height: -0.51,
+            holes: -3.6,
+            bumpiness: -0.18,
+            wells: -0.3,
+            line_clears: [0.0, -0.5, 0.5, 1.5, 8.0],
+            t_spin: 3.0,
+            t_slots: 1.0,
+        }
+    }
+
+    /// How good a board is to play on, without any reward for how it was reached
+    pub fn evaluate(&self, field: &Field) -> f32 {
+        let width = field.width();
+        let heights: Vec<Coord> = (0..width).map(|x| column_height(field, x)).collect();
+        let aggregate_height: Coord = heights.iter().sum();
+        let holes = (0..width)
+            .map(|x| {
+                (0..heights[x as usize])
+                    .filter(|y| field.is_open(p(x, *y)))
+                    .count()
+            })
+            .sum::<usize>();
+        let bumpiness: Coord = heights.windows(2).map(|h| (h[0] - h[1]).abs()).sum();
+        // Walls count as tall neighbours
+        let wells: Coord = (0..width as usize)
+            .map(|x| {
+                let left = if x == 0 { Coord::MAX } else { heights[x - 1] };
+                let right = heights.get(x + 1).copied().unwrap_or(Coord::MAX);
+                (left.min(right) - heights[x]).max(0)
+            })
+            .sum();
+
+        self.aggregate_height * aggregate_height as f32
+            + self.holes * holes as f32
+            + self.bumpiness * bumpiness as f32
+            + self.wells * wells as f32
+            + self.t_slots * t_slots(field, &heights) as f32
+    }
+}
+
+impl AiPlayer {
+    pub fn new(difficulty: Difficulty) -> AiPlayer {
+        AiPlayer::with_weights(difficulty, Weights::new())
+    }
+
+    pub fn with_weights(difficulty: Difficulty, weights: Weights) -> AiPlayer {
+        AiPlayer {
+            difficulty: difficulty,
+            weights: weights,
+            plan: VecDeque::new(),
+            next_input_time: None,
+            last_drop_time: None,
+        }
+    }
+
+    /// The inputs to play now, to pass to `GameState::update_with`
+    pub fn update(&mut self, game: &GameState, now: GameTime) -> Vec<Trigger> {
+        if game.current_piece().is_none() {
+            return vec![];
+        }
+        if self.next_input_time.is_none() {
+            self.plan = self.choose(game).into_iter().collect();
+            self.next_input_time = Some(now + self.difficulty.think_time());
+        }
+
+        let mut result = vec![];
+        while let (Some(time), Some(input)) = (self.next_input_time, self.plan.front().copied()) {
+            if time > now {
+                break;
+            }
+            if input == Trigger::HardDrop {
+                let min_piece_time =
+                    Duration::from_millis((1000.0 / self.difficulty.max_pps()) as u64);
+                if let Some(last) = self.last_drop_time {
+                    if now < last + min_piece_time {
+                        break;
+                    }
+                }
+                self.last_drop_time = Some(now);
+            }
+            self.plan.pop_front();
+            result.push(input);
+            match input {
+                Trigger::HardDrop => {
+                    self.next_input_time = None;
+                    break;
+                }
+                // The rest of the plan is for the piece that comes out of hold next frame
+                Trigger::HoldPiece => {
+                    self.next_input_time = Some(now + self.difficulty.input_interval());
+                    break;
+                }
+                _ => self.next_input_time = Some(time + self.difficulty.input_interval()),
+            }
+        }
+        if self.plan.is_empty() && !result.contains(&Trigger::HardDrop) {
+            // Something went wrong with the plan, so look again
+            self.next_input_time = None;
+        }
+        result
+    }
+
+    /// The inputs for the best placement of the current piece, or the piece swapped in from hold
+    pub fn choose(&self, game: &GameState) -> Vec<Trigger> {
+        let piece = match game.current_piece() {
+            Some(piece) => piece,
+            None => return vec![],
+        };
+        let field = game.field();
+        let previews = game.previews();
+
+        let mut best = self
+            .best_placement(field, placements_from(field, piece), &previews)
+            .map(|(score, placement)| (score, placement.inputs));
+        if game.can_hold() {
+            let swapped = match game.hold_piece() {
+                Some(held) => Some((placements(field, held), &previews[..])),
+                None if !previews.is_empty() => {
+                    Some((placements(field, previews[0]), &previews[1..]))
+                }
+                None => None,
+            };
+            if let Some((options, queue)) = swapped {
+                if let Some((score, placement)) = self.best_placement(field, options, queue) {
+                    if best.as_ref().map(|(s, _)| score > *s).unwrap_or(true) {
+                        let mut inputs = vec![Trigger::HoldPiece];
+                        inputs.extend_from_slice(&placement.inputs);
+                        best = Some((score, inputs));
+                    }
+                }
+            }
+        }
+        best.map(|(_, inputs)| inputs)
+            .unwrap_or_else(|| vec![Trigger::HardDrop])
+    }
+
+    /// Where the current piece would best lock without holding, to show the player as a hint
+    pub fn hint(&self, game: &GameState) -> Option<Tetromino> {
+        let piece = game.current_piece()?;
+        let field = game.field();
+        self.best_placement(field, placements_from(field, piece), &game.previews())
+            .map(|(_, placement)| placement.tetromino)
+    }
+
+    /// The highest scoring of `options`, with `queue` coming after it, and its score. The first
+    /// of equally good placements wins.
+    fn best_placement(
+        &self,
+        field: &Field,
+        options: Vec<Placement>,
+        queue: &[Shape],
+    ) -> Option<(f32, Placement)> {
+        let lookahead = self.difficulty.lookahead();
+        let mut best: Option<(f32, Placement)> = None;
+        for placement in options {
+            if let Some((after, reward)) = self.place(field, &placement) {
+                let score = reward + self.best_score(&after, queue, lookahead);
+                if best.as_ref().map(|(s, _)| score > *s).unwrap_or(true) {
+                    best = Some((score, placement));
+                }
+            }
+        }
+        best
+    }
+
+    /// The best score reachable by placing the next `depth` pieces of `queue`
+    fn best_score(&self, field: &Field, queue: &[Shape], depth: usize) -> f32 {
+        if depth == 0 || queue.is_empty() {
+            return self.weights.evaluate(field);
+        }
+        placements(field, queue[0])
+            .iter()
+            .filter_map(|placement| self.place(field, placement))
+            .map(|(after, reward)| reward + self.best_score(&after, &queue[1..], depth - 1))
+            .fold(LOSING_SCORE, f32::max)
+    }
+
+    /// The field after locking a placement, with the reward for any lines it clears, or `None`
+    /// if locking there loses the game
+    fn place(&self, field: &Field, placement: &Placement) -> Option<(Field, f32)> {
+        let minos = placement.tetromino.to_minos();
+        if minos.is_above(field.playing_boundary_height()) {
+            return None;
+        }
+        let mut after = field.clone();
+        minos.apply_to_field(&mut after);
+        let lines = after.find_lines();
+        after.remove_lines(&lines);
+
+        let mut reward = self.weights.line_clears[lines.len().min(4)];
+        if placement.t_spin {
+            reward += self.weights.t_spin * lines.len() as f32;
+        }
+        Some((after, reward))
+    }
+}
+
+fn column_height(field: &Field, x: Coord) -> Coord {
+    (0..field.game_height())
+        .rev()
+        .find(|y| !field.is_open(p(x, *y)))
+        .map(|y| y + 1)
+        .unwrap_or(0)
+}
+
+/// Places a T piece pointing down would fit with three corners blocked, ready for a T-spin double
+fn t_slots(field: &Field, heights: &[Coord]) -> usize {
+    let blocked = |x: Coord, y: Coord| !field.is_open(p(x, y));
+    (1..field.width() - 1)
+        .filter(|x| {
+            let (x, y) = (*x, heights[*x as usize] + 1);
+            let fits = [p(x - 1, y), p(x, y), p(x + 1, y), p(x, y - 1)]
+                .iter()
+                .all(|pos| field.is_open(*pos));
+            fits && blocked(x - 1, y - 1)
+                && blocked(x + 1, y - 1)
+                && (blocked(x - 1, y + 1) || blocked(x + 1, y + 1))
+        })
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mode::GameMode;
+    use crate::settings::GameSettings;
+
+    #[test]
+    fn evaluate_features() {
+        let w = Weights::new();
+        let flat = Field::from_ascii("#########.").unwrap();
+        let holey = Field::from_ascii(
+            "
+            ##########
+            .#########
+            ",
+        )
+        .unwrap();
+        assert!(w.evaluate(&flat) > w.evaluate(&holey));
+
+        let slot = Field::from_ascii(
+            "
+            ##........
+            #...######
+            ##.#######
+            ",
+        )
+        .unwrap();
+        let heights: Vec<Coord> = (0..10).map(|x| column_height(&slot, x)).collect();
+        assert_eq!(vec![3, 3, 0, 1, 2, 2, 2, 2, 2, 2], heights);
+        assert_eq!(1, t_slots(&slot, &heights));
+    }
+
+    #[test]
+    fn takes_the_tetris() {
+        let field = Field::from_ascii(
+            "
+            #########.
+            #########.
+            #########.
+            #########.
+            ",
+        )
+        .unwrap();
+        let ai = AiPlayer::new(Difficulty::Easy);
+        let (_, best) = ai
+            .best_placement(&field, placements(&field, Shape::I), &[])
+            .unwrap();
+        assert!(best.tetromino.to_minos().contains(p(9, 0)));
+    }
+
+    #[test]
+    fn plays_a_game() {
+        let mut settings = GameSettings::new();
+        settings.start_countdown = Duration::from_millis(0);
+        let (mut game, clock) = GameState::new(GameMode::Sprint { lines: 20 }, 7, &settings);
+        let mut ai = AiPlayer::new(Difficulty::Expert);
+        let mut now = clock.now();
+        for _ in 0..20_000 {
+            now += Duration::from_millis(10);
+            let inputs = ai.update(&game, now);
+            match game.update_with(inputs, now) {
+                crate::gamestate::GameCondition::Playing => (),
+                crate::gamestate::GameCondition::Won => return,
+                crate::gamestate::GameCondition::Lost(reason) => {
+                    panic!("Lost: {:?}\n{:?}", reason, game.field())
+                }
+            }
+        }
+        panic!("Didn't clear 20 lines: {:?}", game.summary().stats);
+    }
+}
@@ -0,0 +1,451 @@
+//! Plays seeded versus games between AIs and Tetris Bot Protocol bots with no window, as fast as
+//! they can play, and reports how each player did.
+//!
+//!     blocks-arena [--games N] [--seed N] [--minutes N] [--format csv|json] PLAYER PLAYER
+//!
+//! A player is `ai:<difficulty>`, optionally followed by weight overrides such as
+//! `ai:expert,holes=-4,bumpiness=-0.2`, or `bot:<command>` to launch a TBP bot.
+
+extern crate blocks;
+
+use blocks::ai::{AiPlayer, Difficulty, Weights};
+use blocks::gamestate::{GameCondition, GameState};
+use blocks::json::Json;
+use blocks::keybindings::Trigger;
+use blocks::mode::GameMode;
+use blocks::settings::GameSettings;
+use blocks::stats::Statistics;
+use blocks::tbp::{BotPlayer, BotProcess};
+use blocks::time::GameTime;
+use std::time::{Duration, Instant};
+
+/// Simulated time between updates, the same as one frame at 60Hz
+const TICK: Duration = Duration::from_micros(16_667);
+/// How long a bot may take to answer, in real time, before it forfeits the game
+const BOT_TIMEOUT: Duration = Duration::from_secs(10);
+
+const USAGE: &str = "\
+usage: blocks-arena [--games N] [--seed N] [--minutes N] [--format csv|json] PLAYER PLAYER
+  PLAYER is ai:<easy|normal|hard|expert>[,<weight>=<value>...] or bot:<command>";
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum Format {
+    Csv,
+    Json,
+}
+
+#[derive(Clone, Debug)]
+enum PlayerSpec {
+    Ai {
+        difficulty: Difficulty,
+        weights: Weights,
+    },
+    Bot {
+        command: String,
+    },
+}
+
+struct Options {
+    games: u32,
+    seed: u64,
+    /// Games still going after this much simulated time are draws
+    time_limit: Duration,
+    format: Format,
+    players: Vec<(String, PlayerSpec)>,
+}
+
+enum Player {
+    Ai(AiPlayer),
+    Bot(BotPlayer),
+}
+
+/// How one game went for both sides
+struct GameResult {
+    /// The side that won, or `None` for a draw
+    winner: Option<usize>,
+    stats: [Statistics; 2],
+    elapsed: Duration,
+}
+
+/// A player's results over every game
+#[derive(Default)]
+struct Record {
+    wins: u32,
+    losses: u32,
+    draws: u32,
+    pieces: u64,
+    attack: u64,
+    played: Duration,
+}
+
+impl Player {
+    fn new(spec: &PlayerSpec) -> Option<Player> {
+        match spec {
+            PlayerSpec::Ai {
+                difficulty,
+                weights,
+            } => Some(Player::Ai(AiPlayer::with_weights(*difficulty, *weights))),
+            PlayerSpec::Bot { command } => {
+                let process = BotProcess::launch(command)?;
+                Some(Player::Bot(BotPlayer::new(Box::new(process))))
+            }
+        }
+    }
+
+    fn inputs(&mut self, game: &GameState, now: GameTime) -> Vec<Trigger> {
+        match self {
+            Player::Ai(ai) => ai.update(game, now),
+            Player::Bot(bot) => bot.update(game),
+        }
+    }
+
+    /// Whether simulated time has to wait for the player to decide
+    fn is_thinking(&self) -> bool {
+        match self {
+            Player::Ai(_) => false,
+            Player::Bot(bot) => bot.is_waiting(),
+        }
+    }
+}
+
+impl Record {
+    fn add(&mut self, result: &GameResult, side: usize) {
+        match result.winner {
+            Some(winner) if winner == side => self.wins += 1,
+            Some(_) => self.losses += 1,
+            None => self.draws += 1,
+        }
+        self.pieces += result.stats[side].pieces as u64;
+        self.attack += result.stats[side].attack as u64;
+        self.played += result.elapsed;
+    }
+
+    fn games(&self) -> u32 {
+        self.wins + self.losses + self.draws
+    }
+
+    /// Draws count as half a win
+    fn win_rate(&self) -> f64 {
+        if self.games() == 0 {
+            return 0.0;
+        }
+        (self.wins as f64 + 0.5 * self.draws as f64) / self.games() as f64
+    }
+
+    fn attack_per_minute(&self) -> f64 {
+        per_second(self.attack, self.played) * 60.0
+    }
+
+    fn pieces_per_second(&self) -> f64 {
+        per_second(self.pieces, self.played)
+    }
+
+    /// The average game length in seconds
+    fn average_survival(&self) -> f64 {
+        if self.games() == 0 {
+            return 0.0;
+        }
+        seconds(self.played) / self.games() as f64
+    }
+}
+
+fn per_second(count: u64, time: Duration) -> f64 {
+    if time > Duration::from_millis(0) {
+        count as f64 / seconds(time)
+    } else {
+        0.0
+    }
+}
+
+fn seconds(time: Duration) -> f64 {
+    time.as_millis() as f64 / 1000.0
+}
+
+fn parse_options(args: &[String]) -> Option<Options> {
+    let mut options = Options {
+        games: 100,
+        seed: 1,
+        time_limit: Duration::from_secs(300),
+        format: Format::Csv,
+        players: vec![],
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--games" => options.games = args.next()?.parse().ok()?,
+            "--seed" => options.seed = args.next()?.parse().ok()?,
+            "--minutes" => {
+                let minutes: u64 = args.next()?.parse().ok()?;
+                options.time_limit = Duration::from_secs(minutes * 60);
+            }
+            "--format" => {
+                options.format = match args.next()?.as_str() {
+                    "csv" => Format::Csv,
+                    "json" => Format::Json,
+                    _ => return None,
+                }
+            }
+            spec => options
+                .players
+                .push((String::from(spec), parse_player(spec)?)),
+        }
+    }
+    if options.players.len() == 2 {
+        Some(options)
+    } else {
+        None
+    }
+}
+
+fn parse_player(spec: &str) -> Option<PlayerSpec> {
+    if spec.starts_with("bot:") {
+        return Some(PlayerSpec::Bot {
+            command: String::from(&spec["bot:".len()..]),
+        });
+    }
+    if !spec.starts_with("ai:") {
+        return None;
+    }
+    let mut parts = spec["ai:".len()..].split(',');
+    let difficulty = Difficulty::from_code(parts.next()?)?;
+    let mut weights = Weights::new();
+    for part in parts {
+        let mut assignment = part.splitn(2, '=');
+        let name = assignment.next()?;
+        let value = assignment.next()?.parse().ok()?;
+        set_weight(&mut weights, name, value)?;
+    }
+    Some(PlayerSpec::Ai {
+        difficulty: difficulty,
+        weights: weights,
+    })
+}
+
+fn set_weight(weights: &mut Weights, name: &str, value: f32) -> Option<()> {
+    let weight = match name {
+        "aggregate_height" => &mut weights.aggregate_height,
+        "holes" => &mut weights.holes,
+        "bumpiness" => &mut weights.bumpiness,
+        "wells" => &mut weights.wells,
+        "single" => &mut weights.line_clears[1],
+        "double" => &mut weights.line_clears[2],
+        "triple" => &mut weights.line_clears[3],
+        "tetris" => &mut weights.line_clears[4],
+        "t_spin" => &mut weights.t_spin,
+        "t_slots" => &mut weights.t_slots,
+        _ => return None,
+    };
+    *weight = value;
+    Some(())
+}
+
+/// Plays one game where both sides get the same pieces, trading garbage until one tops out
+fn play_game(players: &mut [Player], seed: u64, time_limit: Duration) -> GameResult {
+    let mut settings = GameSettings::new();
+    settings.start_countdown = Duration::from_millis(0);
+    // The difficulty is for a built-in opponent, which isn't used here
+    let mode = GameMode::Versus {
+        difficulty: Difficulty::Normal,
+    };
+    let (first, clock) = GameState::new_without_opponent(mode, seed, &settings);
+    let (second, _) = GameState::new_without_opponent(mode, seed, &settings);
+    let mut games = [first, second];
+    let start = clock.now();
+    let mut now = start;
+    let mut thinking_since: Option<Instant> = None;
+
+    let winner = loop {
+        let mut lost = [false, false];
+        for side in 0..2 {
+            let inputs = players[side].inputs(&games[side], now);
+            let condition = games[side].update_with(inputs, now);
+            games[side].take_sound_effects();
+            lost[side] = match condition {
+                GameCondition::Lost(_) => true,
+                _ => false,
+            };
+        }
+        let sent = [
+            games[0].take_outgoing_garbage(),
+            games[1].take_outgoing_garbage(),
+        ];
+        games[0].receive_garbage(sent[1]);
+        games[1].receive_garbage(sent[0]);
+
+        match lost {
+            [true, true] => break None,
+            [true, false] => break Some(1),
+            [false, true] => break Some(0),
+            [false, false] if now - start >= time_limit => break None,
+            _ => (),
+        }
+
+        // Bots get as long as they need to think, without the game moving on
+        if players.iter().any(|player| player.is_thinking()) {
+            let since = *thinking_since.get_or_insert_with(Instant::now);
+            if since.elapsed() > BOT_TIMEOUT {
+                break players.iter().position(|player| !player.is_thinking());
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        } else {
+            thinking_since = None;
+            now += TICK;
+        }
+    };
+
+    GameResult {
+        winner: winner,
+        stats: [games[0].summary().stats, games[1].summary().stats],
+        elapsed: now - start,
+    }
+}
+
+fn report(options: &Options, records: &[Record]) -> String {
+    match options.format {
+        Format::Csv => {
+            let mut text =
+                String::from("player,games,wins,losses,draws,win_rate,apm,pps,average_survival\n");
+            for ((name, _), record) in options.players.iter().zip(records) {
+                text += &format!(
+                    "{},{},{},{},{},{:.3},{:.2},{:.3},{:.1}\n",
+                    csv_field(name),
+                    record.games(),
+                    record.wins,
+                    record.losses,
+                    record.draws,
+                    record.win_rate(),
+                    record.attack_per_minute(),
+                    record.pieces_per_second(),
+                    record.average_survival(),
+                );
+            }
+            text
+        }
+        Format::Json => {
+            let players = options
+                .players
+                .iter()
+                .zip(records)
+                .map(|((name, _), record)| {
+                    Json::object(vec![
+                        ("player", Json::string(name)),
+                        ("games", Json::Number(record.games() as f64)),
+                        ("wins", Json::Number(record.wins as f64)),
+                        ("losses", Json::Number(record.losses as f64)),
+                        ("draws", Json::Number(record.draws as f64)),
+                        ("win_rate", Json::Number(record.win_rate())),
+                        ("apm", Json::Number(record.attack_per_minute())),
+                        ("pps", Json::Number(record.pieces_per_second())),
+                        ("average_survival", Json::Number(record.average_survival())),
+                    ])
+                })
+                .collect();
+            format!(
+                "{}\n",
+                Json::object(vec![
+                    ("seed", Json::Number(options.seed as f64)),
+                    ("players", Json::Array(players)),
+                ])
+            )
+        }
+    }
+}
+
+/// Quotes a field if it would otherwise break the row, as bot commands might
+fn csv_field(text: &str) -> String {
+    if text.contains(|c| c == ',' || c == '"' || c == '\n') {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        String::from(text)
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let options = match parse_options(&args) {
+        Some(options) => options,
+        None => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    let mut players = vec![];
+    for (name, spec) in &options.players {
+        match Player::new(spec) {
+            Some(player) => players.push(player),
+            None => {
+                eprintln!("Could not start {}", name);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let mut records = [Record::default(), Record::default()];
+    for game in 0..options.games {
+        // Each game starts AIs afresh, while bots keep running and are sent a new start
+        for (player, (_, spec)) in players.iter_mut().zip(&options.players) {
+            if let Player::Ai(_) = player {
+                *player = Player::new(spec).unwrap();
+            }
+        }
+        let result = play_game(&mut players, options.seed + game as u64, options.time_limit);
+        records[0].add(&result, 0);
+        records[1].add(&result, 1);
+    }
+
+    print!("{}", report(&options, &records));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(text: &str) -> Vec<String> {
+        text.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn options() {
+        let options = parse_options(&args(
+            "--games 10 --format json ai:hard,holes=-4,tetris=9 bot:./cold-clear",
+        ))
+        .unwrap();
+        assert_eq!(10, options.games);
+        assert_eq!(Format::Json, options.format);
+        match &options.players[0].1 {
+            PlayerSpec::Ai {
+                difficulty,
+                weights,
+            } => {
+                assert_eq!(Difficulty::Hard, *difficulty);
+                assert_eq!(-4.0, weights.holes);
+                assert_eq!(9.0, weights.line_clears[4]);
+            }
+            other => panic!("Unexpected player {:?}", other),
+        }
+        match &options.players[1].1 {
+            PlayerSpec::Bot { command } => assert_eq!("./cold-clear", command),
+            other => panic!("Unexpected player {:?}", other),
+        }
+
+        assert!(parse_options(&args("ai:hard")).is_none());
+        assert!(parse_options(&args("ai:hard ai:impossible")).is_none());
+        assert!(parse_options(&args("ai:hard ai:easy,gravity=1")).is_none());
+        assert!(parse_options(&args("--games ai:hard ai:easy")).is_none());
+    }
+
+    #[test]
+    fn short_game() {
+        let spec = parse_player("ai:expert").unwrap();
+        let mut players = vec![Player::new(&spec).unwrap(), Player::new(&spec).unwrap()];
+        let result = play_game(&mut players, 3, Duration::from_secs(10));
+        assert!(result.elapsed <= Duration::from_secs(10) + TICK);
+        assert!(result.stats[0].pieces > 10);
+
+        let mut record = Record::default();
+        record.add(&result, 0);
+        assert_eq!(1, record.games());
+        assert!(record.pieces_per_second() > 1.0);
+    }
+}
@@ -0,0 +1,32 @@
+use crate::position::{Coord, Pos};
+use crate::shapes::Shape;
+
+pub enum DrawBlockType {
+    Empty,
+    Occupied(Shape),
+    /// A locked block part way to being hidden, with how much of it still shows
+    Faded(Shape, f32),
+    OutOfPlay,
+    GhostPiece(Shape),
+    /// Where the current piece is suggested to go
+    Hint(Shape),
+    /// Part of a setup being built, not filled yet
+    Target(Shape),
+    ClearingLine,
+    Garbage,
+}
+
+pub struct RenderBlockInfo {
+    pub pos: Pos,
+    pub block_type: DrawBlockType,
+}
+
+pub trait BlockRenderInstructions<I>
+where
+    I: Iterator<Item = RenderBlockInfo>,
+{
+    fn blocks(&self) -> I;
+
+    fn height_blocks(&self) -> Coord;
+    fn width_blocks(&self) -> Coord;
+}
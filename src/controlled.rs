@@ -1,6 +1,7 @@
 use crate::field::CheckableField;
 use crate::lockdelay::LockDelay;
 use crate::position::{RotateDir, ShiftDir};
+use crate::savegame::{SaveReader, SaveWriter};
 use crate::shapes::MinoSet;
 use crate::tetromino::Tetromino;
 use crate::time::GameTime;
@@ -11,6 +12,7 @@ pub struct ControlledBlocks {
     next_drop_time: GameTime,
     drop_period: Duration,
     lock_delay: LockDelay,
+    rotated_last: bool,
 }
 
 #[derive(PartialEq, Debug)]
@@ -30,6 +32,7 @@ impl ControlledBlocks {
             next_drop_time: start_time + drop_period,
             drop_period: drop_period,
             lock_delay: LockDelay::new(),
+            rotated_last: false,
         }
     }
 
@@ -38,15 +41,24 @@ impl ControlledBlocks {
     }
 
     pub fn shift(&mut self, field: &dyn CheckableField, dir: ShiftDir) {
-        self.manual_movement(self.tetromino.try_shift(dir, field));
+        self.manual_movement(self.tetromino.try_shift(dir, field), false);
     }
 
     pub fn rotate(&mut self, field: &dyn CheckableField, dir: RotateDir) {
-        self.manual_movement(self.tetromino.try_rotate(dir, field));
+        self.manual_movement(self.tetromino.try_rotate(dir, field), true);
     }
 
     pub fn hard_drop(&mut self, field: &dyn CheckableField) {
-        self.tetromino = self.tetromino.hard_drop(field);
+        let dropped = self.tetromino.hard_drop(field);
+        if dropped != self.tetromino {
+            self.rotated_last = false;
+        }
+        self.tetromino = dropped;
+    }
+
+    /// A T piece whose last successful movement was a rotation into a three-corner position
+    pub fn is_t_spin(&self, field: &dyn CheckableField) -> bool {
+        self.rotated_last && self.tetromino.is_t_spin_position(field)
     }
 
     pub fn periodic_drop(&mut self, field: &dyn CheckableField, now: GameTime) -> DropResult {
@@ -59,6 +71,7 @@ impl ControlledBlocks {
                     self.lock_delay.reset();
                     self.next_drop_time += self.drop_period;
                     self.tetromino = dropped;
+                    self.rotated_last = false;
                 }
             }
         }
@@ -72,15 +85,35 @@ impl ControlledBlocks {
                 self.lock_delay.reset();
                 self.next_drop_time = now + self.drop_period;
                 self.tetromino = dropped;
+                self.rotated_last = false;
                 DropResult::Continue
             }
         }
     }
 
-    fn manual_movement(&mut self, new_tetromino: Option<Tetromino>) {
+    pub fn save(&self, w: &mut SaveWriter) {
+        self.tetromino.save(w);
+        w.put_time(self.next_drop_time);
+        w.put_duration(self.drop_period);
+        self.lock_delay.save(w);
+        w.put(self.rotated_last);
+    }
+
+    pub fn load(r: &mut SaveReader) -> Option<ControlledBlocks> {
+        Some(ControlledBlocks {
+            tetromino: Tetromino::load(r)?,
+            next_drop_time: r.get_time()?,
+            drop_period: r.get_duration()?,
+            lock_delay: LockDelay::load(r)?,
+            rotated_last: r.get()?,
+        })
+    }
+
+    fn manual_movement(&mut self, new_tetromino: Option<Tetromino>, is_rotation: bool) {
         if let Some(tet) = new_tetromino {
             self.tetromino = tet;
             self.lock_delay.reset();
+            self.rotated_last = is_rotation;
         }
     }
 }
@@ -88,7 +121,8 @@ impl ControlledBlocks {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::position::{p, Pos};
+    use crate::field::Field;
+    use crate::position::{p, Pos, Rotations};
     use crate::shapes::Shape;
     use crate::time::GameClock;
 
@@ -114,4 +148,33 @@ mod tests {
 
         b.periodic_drop(&mock_field, start_time + Duration::from_millis(1010));
     }
+
+    #[test]
+    fn t_spin_double() {
+        let mut field = Field::from_ascii(
+            "
+            ##........
+            #...######
+            ##.#######
+            ",
+        )
+        .unwrap();
+        let start_time = GameClock::new().now();
+        let mut b = ControlledBlocks::new(
+            start_time,
+            Tetromino::with_rotation(p(1, 10), Shape::T, Rotations::One),
+            Duration::from_secs(1),
+        );
+
+        b.hard_drop(&field);
+        assert!(!b.is_t_spin(&field));
+        b.rotate(&field, RotateDir::CW);
+        assert!(b.is_t_spin(&field));
+
+        b.minos().apply_to_field(&mut field);
+        let lines = field.find_lines();
+        assert_eq!(vec![1, 0], lines);
+        field.remove_lines(&lines);
+        assert_eq!("##........\n", field.to_ascii());
+    }
 }
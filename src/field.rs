@@ -1,31 +1,58 @@
+use crate::block_render::{BlockRenderInstructions, DrawBlockType, RenderBlockInfo};
 use crate::position::{p, Coord, Pos};
-use crate::render::{BlockRenderInstructions, DrawBlockType, RenderBlockInfo};
+use crate::savegame::{SaveReader, SaveWriter};
 use crate::shapes::{MinoSet, Shape};
 use crate::tetromino::Tetromino;
+use crate::time::GameTime;
+use crate::visibility::StackVisibility;
+use alloc::string::String;
 use alloc::vec::Vec;
+use core::fmt;
 
 #[derive(Copy, Clone, PartialEq, Debug)]
-enum FieldBlock {
+pub enum FieldBlock {
     Empty,
     Occupied(Shape),
+    Garbage,
 }
 
+#[derive(Clone)]
 pub struct Field {
-    blocks: [[FieldBlock; Self::GAME_HEIGHT as usize]; Self::WIDTH as usize],
+    width: Coord,
+    /// Pieces locking entirely above this height top out the game
+    playing_boundary_height: Coord,
+    /// Rows of blocks from the bottom up, for their colours
+    blocks: Vec<Vec<FieldBlock>>,
+    /// The same rows as bitmasks with bit `x` set for each occupied block, for collision checks
+    rows: Vec<RowMask>,
+    /// When each block locked, only kept by `with_lock_times` for modes which hide the stack.
+    /// It's just for drawing, so it isn't saved or compared.
+    lock_times: Option<Vec<Vec<Option<GameTime>>>>,
 }
 
+type RowMask = u64;
+
 pub trait CheckableField {
     fn is_open(&self, pos: Pos) -> bool;
+
+    /// True if every mino of `minos` is open
+    fn has_room_for(&self, minos: &MinoSet) -> bool {
+        minos.positions().iter().all(|pos| self.is_open(*pos))
+    }
 }
 
 pub struct PlayingFieldRenderBlocksIterator<'a> {
     field: &'a Field,
+    target: Option<&'a Field>,
+    visibility: Option<(StackVisibility, GameTime)>,
     game_minos: GameMinos,
     next_pos: Pos,
 }
 
 pub struct PlayingFieldRenderBlocksInstructions<'a> {
     field: &'a Field,
+    target: Option<&'a Field>,
+    visibility: Option<(StackVisibility, GameTime)>,
     game_minos: GameMinos,
 }
 
@@ -39,76 +66,318 @@ enum GameMinos {
 struct ControlMinos {
     controlled: MinoSet,
     ghost: MinoSet,
+    hint: Option<MinoSet>,
 }
 
 impl Field {
-    pub const WIDTH: Coord = 10;
-    pub const GAME_HEIGHT: Coord = 40;
-    pub const VISIBLE_HEIGHT: Coord = 22;
-    pub const PLAYING_BOUNDARY_HEIGHT: Coord = 20;
+    pub const STANDARD_WIDTH: Coord = 10;
+    pub const STANDARD_HEIGHT: Coord = 20;
+    /// Narrow enough for a vertical I piece at either wall to still rotate
+    pub const MIN_WIDTH: Coord = 4;
+    /// Every row has to fit in a `RowMask`
+    pub const MAX_WIDTH: Coord = 40;
+    pub const MIN_HEIGHT: Coord = 4;
+    pub const MAX_HEIGHT: Coord = 40;
 
     pub fn new() -> Field {
+        Field::with_size(Self::STANDARD_WIDTH, Self::STANDARD_HEIGHT)
+    }
+
+    /// An empty field `width` blocks wide with a playing area `height` blocks high, clamped to the
+    /// supported sizes. Blocks can be stacked as high again above the playing area.
+    pub fn with_size(width: Coord, height: Coord) -> Field {
+        let width = width.max(Self::MIN_WIDTH).min(Self::MAX_WIDTH);
+        let height = height.max(Self::MIN_HEIGHT).min(Self::MAX_HEIGHT);
         Field {
-            blocks: [[FieldBlock::Empty; Self::GAME_HEIGHT as usize]; Self::WIDTH as usize],
+            width: width,
+            playing_boundary_height: height,
+            blocks: vec![vec![FieldBlock::Empty; width as usize]; (2 * height) as usize],
+            rows: vec![0; (2 * height) as usize],
+            lock_times: None,
         }
     }
 
+    /// The same field, keeping track of when blocks lock from now on
+    pub fn with_lock_times(mut self) -> Field {
+        let times = vec![vec![None; self.width as usize]; self.game_height() as usize];
+        self.lock_times = Some(times);
+        self
+    }
+
+    pub fn width(&self) -> Coord {
+        self.width
+    }
+
+    pub fn playing_boundary_height(&self) -> Coord {
+        self.playing_boundary_height
+    }
+
+    /// The playing area and the two rows above it, where pieces spawn
+    pub fn visible_height(&self) -> Coord {
+        self.playing_boundary_height + 2
+    }
+
+    /// Including the space above the playing area that pieces can be stacked into
+    pub fn game_height(&self) -> Coord {
+        2 * self.playing_boundary_height
+    }
+
+    /// Where new pieces appear, centred and just inside the top of the playing area
+    pub fn spawn_pos(&self) -> Pos {
+        p((self.width - 4) / 2, self.playing_boundary_height - 2)
+    }
+
     pub fn occupy(&mut self, pos: Pos, shape: Shape) {
-        *self.bp_mut(pos) = FieldBlock::Occupied(shape);
+        self.set(pos.x, pos.y, FieldBlock::Occupied(shape));
     }
 
-    pub fn find_lines(&self) -> Vec<Coord> {
-        let mut result = vec![];
-        'row_loop: for y in (0..Self::GAME_HEIGHT).rev() {
-            for x in 0..Self::WIDTH {
-                if let FieldBlock::Empty = self.b(x, y) {
-                    continue 'row_loop;
-                }
+    /// Records when the blocks of `minos` locked, after they have been applied to the field
+    pub fn mark_locked(&mut self, minos: &MinoSet, time: GameTime) {
+        if let Some(times) = &mut self.lock_times {
+            for pos in minos.positions().iter() {
+                times[pos.y as usize][pos.x as usize] = Some(time);
             }
-            result.push(y);
         }
-        result
     }
 
-    pub fn remove_lines(&mut self, lines: &Vec<Coord>) {
+    /// When the block at `pos` locked, or none if it wasn't placed by a piece during this game
+    pub fn lock_time(&self, pos: Pos) -> Option<GameTime> {
+        self.lock_times
+            .as_ref()
+            .and_then(|times| times[pos.y as usize][pos.x as usize])
+    }
+
+    pub fn block(&self, pos: Pos) -> FieldBlock {
+        self.bp(pos)
+    }
+
+    pub fn set_block(&mut self, pos: Pos, block: FieldBlock) {
+        self.set(pos.x, pos.y, block);
+    }
+
+    /// Full rows, from the top down
+    pub fn find_lines(&self) -> Vec<Coord> {
+        let full = self.full_row();
+        self.rows_where(|row| row == full)
+    }
+
+    /// Rows containing at least one block, from the top down so they can be passed to
+    /// `remove_lines`.
+    pub fn occupied_rows(&self) -> Vec<Coord> {
+        self.rows_where(|row| row != 0)
+    }
+
+    fn rows_where<F: Fn(RowMask) -> bool>(&self, predicate: F) -> Vec<Coord> {
+        (0..self.game_height())
+            .rev()
+            .filter(|y| predicate(self.rows[*y as usize]))
+            .collect()
+    }
+
+    fn full_row(&self) -> RowMask {
+        (1 << self.width) - 1
+    }
+
+    /// True if removing `lines` would leave every cell empty.
+    pub fn is_clear_except(&self, lines: &[Coord]) -> bool {
+        self.occupied_rows().iter().all(|y| lines.contains(y))
+    }
+
+    /// Removes each row in turn, dropping the rows above, so lines should be given from the top
+    /// down
+    pub fn remove_lines(&mut self, lines: &[Coord]) {
         for y in lines {
-            self.drop_lines_above(*y);
+            self.blocks.remove(*y as usize);
+            self.rows.remove(*y as usize);
+            self.blocks
+                .push(vec![FieldBlock::Empty; self.width as usize]);
+            self.rows.push(0);
+            if let Some(times) = &mut self.lock_times {
+                times.remove(*y as usize);
+                times.push(vec![None; self.width as usize]);
+            }
+        }
+    }
+
+    /// Pushes every row up by one, and fills the bottom row with garbage except for `hole_x`.
+    /// Returns false if blocks were pushed out of the top of the field.
+    pub fn push_garbage_row(&mut self, hole_x: Coord) -> bool {
+        let overflowed = self.rows.pop() != Some(0);
+        self.blocks.pop();
+        self.blocks
+            .insert(0, vec![FieldBlock::Empty; self.width as usize]);
+        self.rows.insert(0, 0);
+        if let Some(times) = &mut self.lock_times {
+            times.pop();
+            times.insert(0, vec![None; self.width as usize]);
+        }
+        for x in 0..self.width {
+            if x != hole_x {
+                self.set(x, 0, FieldBlock::Garbage);
+            }
+        }
+        !overflowed
+    }
+
+    /// Writes the size, then the occupied rows from the bottom up, one character per block
+    pub fn save(&self, w: &mut SaveWriter) {
+        let rows = self.occupied_rows();
+        let height = rows.first().map(|y| y + 1).unwrap_or(0);
+        w.label("field");
+        w.put(self.width);
+        w.put(self.playing_boundary_height);
+        w.put(height);
+        for y in 0..height {
+            let row: String = (0..self.width)
+                .map(|x| match self.b(x, y) {
+                    FieldBlock::Empty => '.',
+                    FieldBlock::Garbage => 'G',
+                    FieldBlock::Occupied(shape) => shape.letter(),
+                })
+                .collect();
+            w.put(row);
+        }
+    }
+
+    pub fn load(r: &mut SaveReader) -> Option<Field> {
+        r.label("field")?;
+        let width: Coord = r.get()?;
+        let boundary: Coord = r.get()?;
+        let mut field = Field::with_size(width, boundary);
+        if field.width != width || field.playing_boundary_height != boundary {
+            return None;
+        }
+        let height: Coord = r.get()?;
+        if height < 0 || height > field.game_height() {
+            return None;
         }
+        for y in 0..height {
+            let row = r.token()?;
+            if row.chars().count() != width as usize {
+                return None;
+            }
+            for (x, c) in row.chars().enumerate() {
+                let block = match c {
+                    '.' => FieldBlock::Empty,
+                    'G' => FieldBlock::Garbage,
+                    letter => FieldBlock::Occupied(Shape::from_letter(letter)?),
+                };
+                field.set(x as Coord, y, block);
+            }
+        }
+        Some(field)
     }
 
-    fn drop_lines_above(&mut self, row: Coord) {
-        for y in (row + 1)..Self::GAME_HEIGHT {
-            for x in 0..Self::WIDTH {
-                *self.b_mut(x, y - 1) = self.b(x, y);
+    /// Reads a board drawn as rows of `.` for empty, `#` for garbage and piece letters, with the
+    /// top row first and the last row at the bottom of the field. Blank lines and indentation are
+    /// ignored. The field is as wide as the rows, with a standard height.
+    pub fn from_ascii(text: &str) -> Option<Field> {
+        let rows: Vec<&str> = text
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty())
+            .collect();
+        let width = rows
+            .first()
+            .map(|row| row.chars().count() as Coord)
+            .unwrap_or(Self::STANDARD_WIDTH);
+        let mut field = Field::with_size(width, Self::STANDARD_HEIGHT);
+        if field.width != width || rows.len() > field.game_height() as usize {
+            return None;
+        }
+        for (y, row) in rows.iter().rev().enumerate() {
+            if row.chars().count() != width as usize {
+                return None;
+            }
+            for (x, c) in row.chars().enumerate() {
+                let block = match c {
+                    '.' => FieldBlock::Empty,
+                    '#' => FieldBlock::Garbage,
+                    letter => FieldBlock::Occupied(Shape::from_letter(letter)?),
+                };
+                field.set(x as Coord, y as Coord, block);
             }
         }
-        for x in 0..Self::WIDTH {
-            *self.b_mut(x, Self::GAME_HEIGHT - 1) = FieldBlock::Empty;
+        Some(field)
+    }
+
+    /// The occupied rows in the notation read by `from_ascii`, one line each
+    pub fn to_ascii(&self) -> String {
+        let height = self.occupied_rows().first().map(|y| y + 1).unwrap_or(0);
+        let mut result = String::new();
+        for y in (0..height).rev() {
+            for x in 0..self.width {
+                result.push(match self.b(x, y) {
+                    FieldBlock::Empty => '.',
+                    FieldBlock::Garbage => '#',
+                    FieldBlock::Occupied(shape) => shape.letter(),
+                });
+            }
+            result.push('\n');
         }
+        result
     }
 
     fn bp(&self, p: Pos) -> FieldBlock {
         self.b(p.x, p.y)
     }
     fn b(&self, x: Coord, y: Coord) -> FieldBlock {
-        self.blocks[x as usize][y as usize]
+        self.blocks[y as usize][x as usize]
     }
 
-    fn bp_mut(&mut self, p: Pos) -> &mut FieldBlock {
-        self.b_mut(p.x, p.y)
+    /// Changes a block, keeping the row's bitmask in step. The new block has no lock time.
+    fn set(&mut self, x: Coord, y: Coord, block: FieldBlock) {
+        self.blocks[y as usize][x as usize] = block;
+        if let Some(times) = &mut self.lock_times {
+            times[y as usize][x as usize] = None;
+        }
+        let bit = 1 << x;
+        if block == FieldBlock::Empty {
+            self.rows[y as usize] &= !bit;
+        } else {
+            self.rows[y as usize] |= bit;
+        }
+    }
+}
+
+impl PartialEq for Field {
+    fn eq(&self, other: &Field) -> bool {
+        self.width == other.width
+            && self.playing_boundary_height == other.playing_boundary_height
+            && self.blocks == other.blocks
     }
-    fn b_mut(&mut self, x: Coord, y: Coord) -> &mut FieldBlock {
-        &mut self.blocks[x as usize][y as usize]
+}
+
+impl fmt::Debug for Field {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "\n{}", self.to_ascii())
     }
 }
 
 impl CheckableField for Field {
     fn is_open(&self, pos: Pos) -> bool {
         pos.x >= 0
-            && pos.x < Self::WIDTH
+            && pos.x < self.width
             && pos.y >= 0
-            && pos.y < Self::GAME_HEIGHT
-            && self.bp(pos) == FieldBlock::Empty
+            && pos.y < self.game_height()
+            && self.rows[pos.y as usize] & (1 << pos.x) == 0
+    }
+
+    /// Compares the piece a row at a time, with one mask for each row it covers
+    fn has_room_for(&self, minos: &MinoSet) -> bool {
+        let positions = minos.positions();
+        let bottom = positions.iter().map(|pos| pos.y).min().unwrap_or(0);
+        let mut masks: [RowMask; 4] = [0; 4];
+        for pos in positions.iter() {
+            if pos.x < 0 || pos.x >= self.width || pos.y < 0 || pos.y >= self.game_height() {
+                return false;
+            }
+            masks[(pos.y - bottom) as usize] |= 1 << pos.x;
+        }
+        masks
+            .iter()
+            .enumerate()
+            .all(|(i, mask)| *mask == 0 || self.rows[bottom as usize + i] & mask == 0)
     }
 }
 
@@ -116,7 +385,7 @@ impl<'a> Iterator for PlayingFieldRenderBlocksIterator<'a> {
     type Item = RenderBlockInfo;
 
     fn next(&mut self) -> Option<RenderBlockInfo> {
-        if self.next_pos.y >= Field::VISIBLE_HEIGHT {
+        if self.next_pos.y >= self.field.visible_height() {
             return None;
         }
         let pos = self.next_pos;
@@ -126,7 +395,7 @@ impl<'a> Iterator for PlayingFieldRenderBlocksIterator<'a> {
         });
 
         self.next_pos = self.next_pos + p(1, 0);
-        if self.next_pos.x >= Field::WIDTH {
+        if self.next_pos.x >= self.field.width {
             self.next_pos = p(0, self.next_pos.y + 1);
         }
         result
@@ -140,18 +409,33 @@ impl<'a> PlayingFieldRenderBlocksIterator<'a> {
                 return DrawBlockType::Occupied(minos.controlled.shape());
             } else if minos.ghost.contains(pos) {
                 return DrawBlockType::GhostPiece(minos.ghost.shape());
+            } else if let Some(hint) = minos.hint.as_ref().filter(|hint| hint.contains(pos)) {
+                return DrawBlockType::Hint(hint.shape());
             }
         } else if let GameMinos::Clearing(lines) = &self.game_minos {
             if lines.contains(&pos.y) {
                 return DrawBlockType::ClearingLine;
             }
         }
-        if pos.y >= Field::PLAYING_BOUNDARY_HEIGHT {
+        if pos.y >= self.field.playing_boundary_height() {
             DrawBlockType::OutOfPlay
         } else {
             match self.field.bp(pos) {
-                FieldBlock::Empty => DrawBlockType::Empty,
-                FieldBlock::Occupied(shape) => DrawBlockType::Occupied(shape),
+                FieldBlock::Empty => match self.target.map(|target| target.bp(pos)) {
+                    Some(FieldBlock::Occupied(shape)) => DrawBlockType::Target(shape),
+                    _ => DrawBlockType::Empty,
+                },
+                FieldBlock::Occupied(shape) => match self.visibility {
+                    Some((visibility, now)) => {
+                        match visibility.opacity(self.field.lock_time(pos), now) {
+                            o if o <= 0.0 => DrawBlockType::Empty,
+                            o if o >= 1.0 => DrawBlockType::Occupied(shape),
+                            o => DrawBlockType::Faded(shape, o),
+                        }
+                    }
+                    None => DrawBlockType::Occupied(shape),
+                },
+                FieldBlock::Garbage => DrawBlockType::Garbage,
             }
         }
     }
@@ -161,34 +445,61 @@ impl<'a> PlayingFieldRenderBlocksInstructions<'a> {
     pub fn new_controlled(field: &'a Field, controlled: Tetromino) -> Self {
         Self {
             field: field,
+            target: None,
+            visibility: None,
             game_minos: GameMinos::Controlled(ControlMinos {
                 controlled: controlled.to_minos(),
                 ghost: controlled.hard_drop(field).to_minos(),
+                hint: None,
             }),
         }
     }
 
+    /// Also shows where the controlled piece is suggested to lock
+    pub fn with_hint(mut self, hint: Option<Tetromino>) -> Self {
+        if let GameMinos::Controlled(minos) = &mut self.game_minos {
+            minos.hint = hint.map(|t| t.to_minos());
+        }
+        self
+    }
+
     pub fn new_clearing(field: &'a Field, lines: Vec<Coord>) -> Self {
         Self {
             field: field,
+            target: None,
+            visibility: None,
             game_minos: GameMinos::Clearing(lines),
         }
     }
+
+    /// Also shows the empty cells of `target` which are filled in there
+    pub fn with_target(mut self, target: Option<&'a Field>) -> Self {
+        self.target = target;
+        self
+    }
+
+    /// Hides locked blocks as they would be seen at `now`
+    pub fn with_visibility(mut self, visibility: Option<StackVisibility>, now: GameTime) -> Self {
+        self.visibility = visibility.map(|v| (v, now));
+        self
+    }
 }
 
 impl<'a> BlockRenderInstructions<PlayingFieldRenderBlocksIterator<'a>>
     for PlayingFieldRenderBlocksInstructions<'a>
 {
     fn height_blocks(&self) -> Coord {
-        Field::VISIBLE_HEIGHT
+        self.field.visible_height()
     }
     fn width_blocks(&self) -> Coord {
-        Field::WIDTH
+        self.field.width
     }
 
     fn blocks(&self) -> PlayingFieldRenderBlocksIterator<'a> {
         PlayingFieldRenderBlocksIterator::<'a> {
             field: self.field,
+            target: self.target,
+            visibility: self.visibility,
             game_minos: self.game_minos.clone(),
             next_pos: p(0, 0),
         }
@@ -198,6 +509,8 @@ impl<'a> BlockRenderInstructions<PlayingFieldRenderBlocksIterator<'a>>
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::position::Rotations;
+    use crate::shapes::ShapeDef;
 
     #[test]
     fn set_and_at() {
@@ -221,7 +534,198 @@ mod tests {
         assert_eq!(false, f.is_open(Pos::new(-1, 0)));
         assert_eq!(false, f.is_open(Pos::new(0, -1)));
 
-        assert_eq!(false, f.is_open(Pos::new(Field::WIDTH, 0)));
-        assert_eq!(false, f.is_open(Pos::new(0, Field::GAME_HEIGHT)));
+        assert_eq!(false, f.is_open(Pos::new(Field::STANDARD_WIDTH, 0)));
+        assert_eq!(false, f.is_open(Pos::new(0, 2 * Field::STANDARD_HEIGHT)));
+    }
+
+    #[test]
+    fn has_room_for() {
+        let mut f = Field::new();
+        f.occupy(Pos::new(2, 3), Shape::O);
+        let at = |x, y| Shape::T.to_minos(Rotations::Zero, Pos::new(x, y));
+
+        for x in -2..f.width() + 2 {
+            for y in -2..f.game_height() + 2 {
+                let minos = at(x, y);
+                let expected = minos.positions().iter().all(|pos| f.is_open(*pos));
+                assert_eq!(expected, f.has_room_for(&minos), "{}, {}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn perfect_clear() {
+        let mut f = Field::new();
+        assert!(f.is_clear_except(&[]));
+
+        for x in 0..f.width() {
+            f.occupy(Pos::new(x, 0), Shape::I);
+        }
+        let lines = f.find_lines();
+        assert_eq!(vec![0], lines);
+        assert!(f.is_clear_except(&lines));
+
+        f.occupy(Pos::new(3, 1), Shape::T);
+        assert_eq!(vec![1, 0], f.occupied_rows());
+        assert!(!f.is_clear_except(&lines));
+    }
+
+    #[test]
+    fn save_load() {
+        let mut f = Field::new();
+        f.push_garbage_row(3);
+        f.occupy(Pos::new(0, 1), Shape::L);
+
+        let mut w = SaveWriter::new();
+        f.save(&mut w);
+        let text = w.finish();
+        assert_eq!("field 10 20 2 GGG.GGGGGG L.........\n", text);
+
+        let loaded = Field::load(&mut SaveReader::new(&text)).unwrap();
+        assert_eq!(f.occupied_rows(), loaded.occupied_rows());
+        assert_eq!(FieldBlock::Occupied(Shape::L), loaded.b(0, 1));
+        assert_eq!(FieldBlock::Garbage, loaded.b(0, 0));
+        assert!(loaded.is_open(Pos::new(3, 0)));
+    }
+
+    #[test]
+    fn ascii() {
+        let text = "\
+            ...T......
+            ..TTT.....
+            #######.##
+        ";
+        let f = Field::from_ascii(text).unwrap();
+        assert_eq!(FieldBlock::Occupied(Shape::T), f.b(3, 2));
+        assert_eq!(FieldBlock::Garbage, f.b(0, 0));
+        assert!(f.is_open(Pos::new(7, 0)));
+        assert_eq!("...T......\n..TTT.....\n#######.##\n", f.to_ascii());
+        assert_eq!("", Field::new().to_ascii());
+
+        assert!(Field::from_ascii("..........\n.........").is_none());
+        assert!(Field::from_ascii("........X.").is_none());
+    }
+
+    #[test]
+    fn clear_lines() {
+        let mut f = Field::from_ascii(
+            "
+            ..O.......
+            ##########
+            ....L.....
+            IIIIJJJOOL
+            ",
+        )
+        .unwrap();
+        let lines = f.find_lines();
+        assert_eq!(vec![2, 0], lines);
+
+        f.remove_lines(&lines);
+        assert_eq!("..O.......\n....L.....\n", f.to_ascii());
+    }
+
+    #[test]
+    fn sizes() {
+        let f = Field::with_size(4, 10);
+        assert_eq!(p(0, 8), f.spawn_pos());
+        assert_eq!(12, f.visible_height());
+        assert!(!f.is_open(Pos::new(4, 0)));
+        assert!(!f.is_open(Pos::new(0, 20)));
+        assert_eq!(p(3, 18), Field::new().spawn_pos());
+        assert_eq!(Field::MIN_WIDTH, Field::with_size(1, 20).width());
+
+        let wide = Field::from_ascii("#..............#").unwrap();
+        assert_eq!(16, wide.width());
+        let mut w = SaveWriter::new();
+        wide.save(&mut w);
+        let text = w.finish();
+        let loaded = Field::load(&mut SaveReader::new(&text)).unwrap();
+        assert_eq!("#..............#\n", loaded.to_ascii());
+    }
+
+    #[test]
+    fn masks_follow_blocks() {
+        let mut f = Field::from_ascii(
+            "
+            IIII..OO..
+            ZZ.TTTOOJJ
+            ",
+        )
+        .unwrap();
+        f.set_block(p(2, 0), FieldBlock::Garbage);
+        assert_eq!(vec![0], f.find_lines());
+        f.set_block(p(2, 0), FieldBlock::Empty);
+        assert!(f.find_lines().is_empty());
+        assert!(f.push_garbage_row(9));
+
+        for y in 0..4 {
+            for x in 0..f.width() {
+                assert_eq!(
+                    f.b(x, y) == FieldBlock::Empty,
+                    f.is_open(p(x, y)),
+                    "{:?}",
+                    f
+                );
+            }
+        }
+        assert_eq!("IIII..OO..\nZZ.TTTOOJJ\n#########.\n", f.to_ascii());
+
+        let wide = Field::with_size(Field::MAX_WIDTH, Field::STANDARD_HEIGHT);
+        assert!(wide.is_open(p(Field::MAX_WIDTH - 1, 0)));
+        assert!(!wide.is_open(p(Field::MAX_WIDTH, 0)));
+    }
+
+    #[test]
+    fn push_garbage_row() {
+        let mut f = Field::new();
+        f.occupy(Pos::new(4, 0), Shape::T);
+        assert!(f.push_garbage_row(2));
+
+        assert_eq!(false, f.is_open(Pos::new(4, 1)));
+        assert_eq!(true, f.is_open(Pos::new(2, 0)));
+        assert_eq!(false, f.is_open(Pos::new(3, 0)));
+        assert!(f.find_lines().is_empty());
+
+        f.occupy(Pos::new(4, f.game_height() - 1), Shape::T);
+        assert!(!f.push_garbage_row(2));
+    }
+
+    #[test]
+    fn lock_times() {
+        let mut f = Field::new().with_lock_times();
+        f.push_garbage_row(0);
+        let minos = Tetromino::new(f.spawn_pos(), Shape::O)
+            .hard_drop(&f)
+            .to_minos();
+        minos.apply_to_field(&mut f);
+        let unmarked = f.clone();
+        f.mark_locked(&minos, GameTime::from_millis(700));
+        assert_eq!(None, f.lock_time(p(1, 0)));
+
+        // Lock times move with their rows
+        f.remove_lines(&[0]);
+        for pos in minos.positions().iter() {
+            assert_eq!(
+                Some(GameTime::from_millis(700)),
+                f.lock_time(*pos + p(0, -1))
+            );
+        }
+        assert!(f.push_garbage_row(0));
+        for pos in minos.positions().iter() {
+            assert_eq!(Some(GameTime::from_millis(700)), f.lock_time(*pos));
+        }
+
+        // Lock times are only for drawing, so they don't make fields differ
+        let mut marked = unmarked.clone();
+        marked.mark_locked(&minos, GameTime::from_millis(900));
+        assert_eq!(unmarked, marked);
+        f.set_block(minos.positions()[0], FieldBlock::Empty);
+        assert_eq!(None, f.lock_time(minos.positions()[0]));
+
+        // Other fields don't keep them at all
+        let mut untracked = Field::new();
+        minos.apply_to_field(&mut untracked);
+        untracked.mark_locked(&minos, GameTime::from_millis(700));
+        assert_eq!(None, untracked.lock_time(minos.positions()[0]));
     }
 }
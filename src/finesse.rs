@@ -0,0 +1,177 @@
+use crate::field::Field;
+use crate::keybindings::Trigger;
+use crate::movegen::sorted_minos;
+use crate::position::{RotateDir, ShiftDir};
+use crate::tetromino::Tetromino;
+use alloc::collections::vec_deque::VecDeque;
+use alloc::vec::Vec;
+
+/// The inputs used on the piece in play, to compare with the fewest that would have put it in
+/// the same place
+pub struct PieceInputs {
+    spawned: Tetromino,
+    inputs: u32,
+    soft_dropped: bool,
+}
+
+impl PieceInputs {
+    pub fn new(spawned: Tetromino) -> PieceInputs {
+        PieceInputs {
+            spawned: spawned,
+            inputs: 0,
+            soft_dropped: false,
+        }
+    }
+
+    /// Counts a trigger handled for this piece. Auto-repeated shifts belong to the press that
+    /// started them, so only triggers from a key being `pressed` count.
+    pub fn record(&mut self, trigger: Trigger, pressed: bool) {
+        match trigger {
+            Trigger::Shift(_) | Trigger::Rotate(_) if pressed => self.inputs += 1,
+            Trigger::SoftDown => self.soft_dropped = true,
+            _ => (),
+        }
+    }
+
+    pub fn inputs(&self) -> u32 {
+        self.inputs
+    }
+
+    /// Whether the piece took more inputs than needed to lock as `locked` on `field`. Soft
+    /// dropped pieces, such as tucks and spins, aren't judged.
+    pub fn is_fault(&self, field: &Field, locked: Tetromino) -> bool {
+        !self.soft_dropped
+            && min_inputs(field, self.spawned, locked)
+                .map(|min| self.inputs > min)
+                .unwrap_or(false)
+    }
+}
+
+/// The fewest taps, shifts to the wall with DAS, and rotations that take `spawned` above where
+/// `target` locks with a hard drop, or `None` if it can't get there without soft dropping
+pub fn min_inputs(field: &Field, spawned: Tetromino, target: Tetromino) -> Option<u32> {
+    let goal = sorted_minos(target);
+    let mut seen = vec![spawned];
+    let mut queue = VecDeque::new();
+    queue.push_back((spawned, 0));
+    while let Some((t, inputs)) = queue.pop_front() {
+        if sorted_minos(t.hard_drop(field)) == goal {
+            return Some(inputs);
+        }
+        for next in moves(t, field) {
+            if !seen.contains(&next) {
+                seen.push(next);
+                queue.push_back((next, inputs + 1));
+            }
+        }
+    }
+    None
+}
+
+/// Where one input can move a piece
+fn moves(t: Tetromino, field: &Field) -> Vec<Tetromino> {
+    let mut result = vec![];
+    for dir in [ShiftDir::Left, ShiftDir::Right].iter() {
+        if let Some(shifted) = t.try_shift(*dir, field) {
+            result.push(shifted);
+            let mut wall = shifted;
+            while let Some(next) = wall.try_shift(*dir, field) {
+                wall = next;
+            }
+            result.push(wall);
+        }
+    }
+    for dir in [RotateDir::CW, RotateDir::CCW].iter() {
+        if let Some(rotated) = t.try_rotate(*dir, field) {
+            result.push(rotated);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::movegen::placements;
+    use crate::position::p;
+    use crate::shapes::Shape;
+
+    fn spawn(field: &Field, shape: Shape) -> Tetromino {
+        Tetromino::try_new(field.spawn_pos(), shape, field).unwrap()
+    }
+
+    #[test]
+    fn empty_field() {
+        let field = Field::new();
+        let t = spawn(&field, Shape::T);
+        assert_eq!(Some(0), min_inputs(&field, t, t.hard_drop(&field)));
+        let left = t.try_shift(ShiftDir::Left, &field).unwrap();
+        assert_eq!(Some(1), min_inputs(&field, t, left.hard_drop(&field)));
+
+        // Every placement on an empty field takes at most a shift, a tap back and two rotations
+        for shape in Shape::ALL.iter() {
+            let spawned = spawn(&field, *shape);
+            for placement in placements(&field, *shape) {
+                let min = min_inputs(&field, spawned, placement.tetromino).unwrap();
+                assert!(min <= 4, "{:?} takes {}", placement.tetromino, min);
+            }
+        }
+    }
+
+    #[test]
+    fn symmetric_pieces() {
+        let field = Field::new();
+        let i = spawn(&field, Shape::I);
+        let cw = i.try_rotate(RotateDir::CW, &field).unwrap();
+        let ccw = i.try_rotate(RotateDir::CCW, &field).unwrap();
+        // Either rotation reaches the vertical I in one column or the other
+        assert_eq!(Some(1), min_inputs(&field, i, cw.hard_drop(&field)));
+        assert_eq!(Some(1), min_inputs(&field, i, ccw.hard_drop(&field)));
+    }
+
+    #[test]
+    fn faults() {
+        let field = Field::new();
+        let t = spawn(&field, Shape::T);
+        let mut wall = t;
+        while let Some(next) = wall.try_shift(ShiftDir::Left, &field) {
+            wall = next;
+        }
+        let locked = wall.hard_drop(&field);
+
+        let mut das = PieceInputs::new(t);
+        das.record(Trigger::Shift(ShiftDir::Left), true);
+        for _ in 0..3 {
+            das.record(Trigger::Shift(ShiftDir::Left), false);
+        }
+        das.record(Trigger::HardDrop, true);
+        assert_eq!(1, das.inputs());
+        assert!(!das.is_fault(&field, locked));
+
+        let mut taps = PieceInputs::new(t);
+        for _ in 0..4 {
+            taps.record(Trigger::Shift(ShiftDir::Left), true);
+        }
+        assert!(taps.is_fault(&field, locked));
+
+        taps.record(Trigger::SoftDown, true);
+        assert!(!taps.is_fault(&field, locked));
+    }
+
+    #[test]
+    fn needs_soft_drop() {
+        let field = Field::from_ascii(
+            "
+            ######....
+            ..........
+            ",
+        )
+        .unwrap();
+        let i = spawn(&field, Shape::I);
+        let under = placements(&field, Shape::I)
+            .into_iter()
+            .find(|placement| placement.tetromino.to_minos().contains(p(0, 0)))
+            .unwrap();
+        assert_eq!(None, min_inputs(&field, i, under.tetromino));
+    }
+}
@@ -0,0 +1,210 @@
+use crate::field::Field;
+use crate::movegen::{placements, sorted_minos};
+use crate::rng::Rng;
+use crate::savegame::{SaveReader, SaveWriter};
+use crate::shapes::Shape;
+use crate::tetromino::Tetromino;
+use alloc::collections::vec_deque::VecDeque;
+use alloc::vec::Vec;
+
+/// Drills placing single pieces on an empty field in as few inputs as possible. Each round
+/// deals every placement of every shape once in a random order, and a missed placement comes
+/// back a few pieces later until it is placed correctly.
+pub struct FinesseDrill {
+    targets: Vec<Target>,
+    /// Indices into `targets`, starting with the one in play
+    queue: VecDeque<usize>,
+    rng: Rng,
+}
+
+struct Target {
+    tetromino: Tetromino,
+    attempts: u32,
+    correct: u32,
+}
+
+/// How many other targets come up before a missed one is tried again
+const RETRY_GAP: usize = 3;
+
+impl FinesseDrill {
+    pub fn new(field: &Field, seed: u64) -> FinesseDrill {
+        let mut drill = FinesseDrill {
+            targets: all_targets(field),
+            queue: VecDeque::new(),
+            rng: Rng::new(seed),
+        };
+        drill.deal_round();
+        drill
+    }
+
+    /// Where the piece in play should lock
+    pub fn target(&self) -> Tetromino {
+        self.targets[self.queue[0]].tetromino
+    }
+
+    /// Whether a piece locked as `locked` covers the target, whichever way it was rotated
+    pub fn is_on_target(&self, locked: Tetromino) -> bool {
+        sorted_minos(locked) == sorted_minos(self.target())
+    }
+
+    /// Records whether the target was reached in the fewest inputs, and moves on to the next one
+    pub fn record(&mut self, correct: bool) {
+        let index = self.queue.pop_front().expect("Expected a target in play");
+        let target = &mut self.targets[index];
+        target.attempts += 1;
+        if correct {
+            target.correct += 1;
+        } else {
+            self.queue.insert(RETRY_GAP.min(self.queue.len()), index);
+        }
+        if self.queue.is_empty() {
+            self.deal_round();
+        }
+    }
+
+    pub fn attempts(&self) -> u32 {
+        self.targets.iter().map(|t| t.attempts).sum()
+    }
+
+    pub fn correct(&self) -> u32 {
+        self.targets.iter().map(|t| t.correct).sum()
+    }
+
+    /// Percentage of attempts placed correctly
+    pub fn accuracy(&self) -> u32 {
+        match self.attempts() {
+            0 => 0,
+            attempts => self.correct() * 100 / attempts,
+        }
+    }
+
+    /// Correct placements and attempts for the target in play
+    pub fn target_record(&self) -> (u32, u32) {
+        let target = &self.targets[self.queue[0]];
+        (target.correct, target.attempts)
+    }
+
+    pub fn save(&self, w: &mut SaveWriter) {
+        w.put(self.targets.len());
+        for target in self.targets.iter() {
+            w.put(target.attempts);
+            w.put(target.correct);
+        }
+        w.put(self.queue.len());
+        for index in self.queue.iter() {
+            w.put(index);
+        }
+        self.rng.save(w);
+    }
+
+    /// The targets are found again from the field, which must be the size it was saved with
+    pub fn load(r: &mut SaveReader, field: &Field) -> Option<FinesseDrill> {
+        let mut targets = all_targets(field);
+        if r.get::<usize>()? != targets.len() {
+            return None;
+        }
+        for target in targets.iter_mut() {
+            target.attempts = r.get()?;
+            target.correct = r.get()?;
+        }
+        let count: usize = r.get()?;
+        let mut queue = VecDeque::new();
+        for _ in 0..count {
+            let index: usize = r.get()?;
+            if index >= targets.len() {
+                return None;
+            }
+            queue.push_back(index);
+        }
+        if queue.is_empty() {
+            return None;
+        }
+        Some(FinesseDrill {
+            targets: targets,
+            queue: queue,
+            rng: Rng::load(r)?,
+        })
+    }
+
+    fn deal_round(&mut self) {
+        let mut remaining: Vec<usize> = (0..self.targets.len()).collect();
+        while !remaining.is_empty() {
+            let index = self.rng.below(remaining.len());
+            self.queue.push_back(remaining.remove(index));
+        }
+    }
+}
+
+/// Every distinct placement of every shape on an empty field
+fn all_targets(field: &Field) -> Vec<Target> {
+    let empty = Field::with_size(field.width(), field.playing_boundary_height());
+    Shape::ALL
+        .iter()
+        .flat_map(|shape| placements(&empty, *shape))
+        .map(|placement| Target {
+            tetromino: placement.tetromino,
+            attempts: 0,
+            correct: 0,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rounds() {
+        let field = Field::new();
+        let mut drill = FinesseDrill::new(&field, 4);
+        // I, O, J, L, S, Z, T as counted by the move generator
+        let total = 17 + 9 + 34 + 34 + 17 + 17 + 34;
+        let mut seen = vec![];
+        for _ in 0..total {
+            let target = drill.target();
+            assert!(drill.is_on_target(target));
+            assert!(!seen.contains(&target));
+            seen.push(target);
+            drill.record(true);
+        }
+        assert_eq!(total, drill.attempts() as usize);
+        assert_eq!(100, drill.accuracy());
+    }
+
+    #[test]
+    fn missed_targets_return() {
+        let mut drill = FinesseDrill::new(&Field::new(), 4);
+        let missed = drill.target();
+        drill.record(false);
+        for _ in 0..RETRY_GAP {
+            assert_ne!(missed, drill.target());
+            drill.record(true);
+        }
+        assert_eq!(missed, drill.target());
+        assert_eq!((0, 1), drill.target_record());
+        drill.record(true);
+        assert_eq!(80, drill.accuracy());
+    }
+
+    #[test]
+    fn save_load() {
+        let field = Field::new();
+        let mut a = FinesseDrill::new(&field, 9);
+        a.record(false);
+        a.record(true);
+        let mut w = SaveWriter::new();
+        a.save(&mut w);
+        let text = w.finish();
+
+        let mut b = FinesseDrill::load(&mut SaveReader::new(&text), &field).unwrap();
+        assert_eq!(a.attempts(), b.attempts());
+        for _ in 0..300 {
+            assert_eq!(a.target(), b.target());
+            a.record(true);
+            b.record(true);
+        }
+        assert!(
+            FinesseDrill::load(&mut SaveReader::new(&text), &Field::with_size(4, 20)).is_none()
+        );
+    }
+}
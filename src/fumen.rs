@@ -0,0 +1,681 @@
+use crate::field::{Field, FieldBlock};
+use crate::position::{p, Coord, Pos, Rotations};
+use crate::shapes::Shape;
+use crate::tetromino::Tetromino;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// One page of a fumen: a board, the piece being placed on it, and the pieces available as given
+/// by a quiz comment (`#Q=[hold](current)next`).
+pub struct Page {
+    pub field: Field,
+    pub piece: Option<Tetromino>,
+    pub hold: Option<Shape>,
+    /// The pieces still to come after `piece`, or starting with the current piece when there is
+    /// no `piece`
+    pub queue: Vec<Shape>,
+}
+
+/// Where F11 collects positions exported while playing, one page each. Each game starts a new
+/// export in place of the last one.
+pub const EXPORT_PROFILE: &str = "fumen";
+/// Presses of F11 past this many in one game are ignored
+pub const MAX_EXPORT_PAGES: usize = 100;
+
+const PREFIX: &str = "v115@";
+const ENCODE_TABLE: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const COMMENT_TABLE: &[u8] =
+    b" !\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~";
+const COMMENT_BASE: u32 = 96;
+const QUIZ_PREFIX: &str = "#Q=";
+
+/// Fumen boards are 23 rows, plus a row below for rising garbage
+const WIDTH: Coord = 10;
+const HEIGHT: Coord = 23;
+const NUM_BLOCKS: u32 = ((HEIGHT + 1) * WIDTH) as u32;
+/// A field value meaning no change from the previous page
+const NO_CHANGE: u32 = 8;
+const GARBAGE: u32 = 8;
+
+/// Data is split by `?` after the first 42 characters, then every 47
+const FIRST_CHUNK: usize = 42;
+const CHUNK: usize = 47;
+
+/// Base 64 digits, least significant first
+struct Values {
+    digits: Vec<u32>,
+    next: usize,
+}
+
+impl Values {
+    fn push(&mut self, mut value: u32, num_digits: usize) {
+        for _ in 0..num_digits {
+            self.digits.push(value % 64);
+            value /= 64;
+        }
+    }
+
+    fn poll(&mut self, num_digits: usize) -> Option<u32> {
+        let mut value = 0;
+        for i in 0..num_digits {
+            value += *self.digits.get(self.next)? << (6 * i);
+            self.next += 1;
+        }
+        Some(value)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.next >= self.digits.len()
+    }
+}
+
+pub fn encode(pages: &[Page]) -> String {
+    let mut values = Values {
+        digits: vec![],
+        next: 0,
+    };
+    let mut prev_field = Field::new();
+    let mut repeat_index: Option<usize> = None;
+    let mut prev_quiz = false;
+
+    for (i, page) in pages.iter().enumerate() {
+        let diffs: Vec<u32> = (0..NUM_BLOCKS)
+            .map(|index| {
+                let pos = block_pos(index);
+                value_at(&page.field, pos) + NO_CHANGE - value_at(&prev_field, pos)
+            })
+            .collect();
+        let changed = diffs.iter().any(|d| *d != NO_CHANGE);
+
+        match repeat_index {
+            Some(index) if !changed && values.digits[index] < 63 => values.digits[index] += 1,
+            _ => {
+                let mut start = 0;
+                for end in 1..=diffs.len() {
+                    if end == diffs.len() || diffs[end] != diffs[start] {
+                        values.push(diffs[start] * NUM_BLOCKS + (end - start - 1) as u32, 2);
+                        start = end;
+                    }
+                }
+                repeat_index = if changed {
+                    None
+                } else {
+                    values.push(0, 1);
+                    Some(values.digits.len() - 1)
+                };
+            }
+        }
+
+        let quiz = quiz_comment(page);
+        // Every quiz page repeats its comment, rather than relying on a reader to advance the quiz
+        let has_comment = quiz.is_some() || prev_quiz;
+        prev_quiz = quiz.is_some();
+
+        let (piece_value, rotation_value, position) = match page.piece {
+            Some(t) => {
+                let (pos, rotation) = fumen_placement(t);
+                (shape_value(t.shape()), rotation, encode_position(pos))
+            }
+            None => (0, 0, 0),
+        };
+        let flags = [
+            false,       // lock flag is inverted: pieces always lock
+            has_comment, // comment
+            i == 0,      // guideline colours
+            false,       // mirror
+            false,       // rise
+        ];
+        let mut action = 0;
+        for flag in flags.iter() {
+            action = action * 2 + *flag as u32;
+        }
+        action = ((action * NUM_BLOCKS + position) * 4 + rotation_value) * 8 + piece_value;
+        values.push(action, 3);
+
+        if has_comment {
+            let text = escape(&quiz.unwrap_or_default());
+            let chars: Vec<u8> = text.bytes().take(4095).collect();
+            values.push(chars.len() as u32, 2);
+            for group in chars.chunks(4) {
+                let mut value = 0;
+                for (n, c) in group.iter().enumerate() {
+                    let code = COMMENT_TABLE.iter().position(|t| t == c).unwrap_or(0) as u32;
+                    value += code * COMMENT_BASE.pow(n as u32);
+                }
+                values.push(value, 5);
+            }
+        }
+
+        prev_field = page.field.clone();
+        if let Some(t) = page.piece {
+            lock(&mut prev_field, t);
+        }
+    }
+
+    let data: String = values
+        .digits
+        .iter()
+        .map(|d| ENCODE_TABLE[*d as usize] as char)
+        .collect();
+    let mut result = String::from(PREFIX);
+    for (i, c) in data.chars().enumerate() {
+        if i >= FIRST_CHUNK && (i - FIRST_CHUNK) % CHUNK == 0 {
+            result.push('?');
+        }
+        result.push(c);
+    }
+    result
+}
+
+/// Reads every page of a v115 fumen. Mirrored pages and rising garbage aren't supported.
+pub fn decode(fumen: &str) -> Option<Vec<Page>> {
+    let data = fumen.trim();
+    if !data.starts_with(PREFIX) {
+        return None;
+    }
+    let mut values = Values {
+        digits: vec![],
+        next: 0,
+    };
+    for c in data[PREFIX.len()..].bytes().filter(|c| *c != b'?') {
+        values
+            .digits
+            .push(ENCODE_TABLE.iter().position(|t| *t == c)? as u32);
+    }
+
+    let mut pages = vec![];
+    let mut prev_field = Field::new();
+    let mut repeat_count = 0;
+    let mut quiz: Option<(Option<Shape>, Vec<Shape>)> = None;
+
+    while !values.is_empty() {
+        let mut field = prev_field.clone();
+        if repeat_count > 0 {
+            repeat_count -= 1;
+        } else {
+            let mut index = 0;
+            let mut changed = false;
+            while index < NUM_BLOCKS {
+                let value = values.poll(2)?;
+                let diff = value / NUM_BLOCKS;
+                let count = value % NUM_BLOCKS + 1;
+                if diff != NO_CHANGE {
+                    changed = true;
+                }
+                for _ in 0..count {
+                    if index >= NUM_BLOCKS {
+                        return None;
+                    }
+                    let pos = block_pos(index);
+                    if pos.y >= 0 {
+                        let old = block_value(field.block(pos));
+                        field.set_block(pos, value_block((old + diff).checked_sub(NO_CHANGE)?)?);
+                    }
+                    index += 1;
+                }
+            }
+            if !changed {
+                repeat_count = values.poll(1)?;
+            }
+        }
+
+        let mut action = values.poll(3)?;
+        let piece_value = action % 8;
+        action /= 8;
+        let rotation_value = action % 4;
+        action /= 4;
+        let position = action % NUM_BLOCKS;
+        action /= NUM_BLOCKS;
+        let rise = action % 2 == 1;
+        let mirror = (action / 2) % 2 == 1;
+        let has_comment = (action / 8) % 2 == 1;
+        let locks = (action / 16) % 2 == 0;
+        if rise || mirror {
+            return None;
+        }
+
+        let piece = match piece_value {
+            0 => None,
+            value => {
+                let t = tetromino_at(
+                    value_shape(value)?,
+                    rotation_value,
+                    decode_position(position),
+                )?;
+                if !is_on_board(t) {
+                    return None;
+                }
+                Some(t)
+            }
+        };
+
+        if has_comment {
+            let length = values.poll(2)? as usize;
+            let mut text = String::new();
+            for _ in 0..((length + 3) / 4) {
+                let mut value = values.poll(5)?;
+                for _ in 0..4 {
+                    text.push(*COMMENT_TABLE.get((value % COMMENT_BASE) as usize)? as char);
+                    value /= COMMENT_BASE;
+                }
+            }
+            text.truncate(length);
+            quiz = parse_quiz(&unescape(&text)?);
+        }
+
+        let (hold, queue) = match &quiz {
+            Some((hold, pieces)) => match (piece, pieces.first()) {
+                (Some(t), Some(current)) if t.shape() == *current => (*hold, pieces[1..].to_vec()),
+                _ => (*hold, pieces.clone()),
+            },
+            None => (None, vec![]),
+        };
+        pages.push(Page {
+            field: field.clone(),
+            piece: piece,
+            hold: hold,
+            queue: queue,
+        });
+
+        prev_field = field;
+        if let Some(t) = piece {
+            if locks {
+                lock(&mut prev_field, t);
+                quiz = quiz.and_then(|q| advance_quiz(q, t.shape()));
+            }
+        }
+    }
+    Some(pages)
+}
+
+/// The fumen given with `--fumen` when the game was started
+#[cfg(not(target_arch = "wasm32"))]
+pub fn launch_fumen() -> Option<String> {
+    std::env::args().skip_while(|a| a != "--fumen").nth(1)
+}
+
+/// The fumen given in the page's query string as `?fumen=`
+#[cfg(target_arch = "wasm32")]
+pub fn launch_fumen() -> Option<String> {
+    let search = stdweb::web::window().location()?.search().ok()?;
+    let start = search.find("fumen=")? + "fumen=".len();
+    Some(String::from(search[start..].split('&').next()?))
+}
+
+fn lock(field: &mut Field, piece: Tetromino) {
+    piece.to_minos().apply_to_field(field);
+    let lines: Vec<Coord> = field
+        .find_lines()
+        .into_iter()
+        .filter(|y| *y < HEIGHT)
+        .collect();
+    field.remove_lines(&lines);
+}
+
+/// Fumen doesn't stop pieces being placed in the garbage row or past the edges, but they can't
+/// be put on a field
+fn is_on_board(piece: Tetromino) -> bool {
+    piece
+        .to_minos()
+        .positions()
+        .iter()
+        .all(|pos| pos.x >= 0 && pos.x < WIDTH && pos.y >= 0 && pos.y < HEIGHT)
+}
+
+/// Blocks are numbered from the top left, with the garbage row last
+fn block_pos(index: u32) -> Pos {
+    let index = index as Coord;
+    p(index % WIDTH, HEIGHT - index / WIDTH - 1)
+}
+
+/// Fumen boards are a standard size, so anything outside one is left out
+fn value_at(field: &Field, pos: Pos) -> u32 {
+    if pos.x < field.width() && pos.y >= 0 && pos.y < field.game_height() {
+        block_value(field.block(pos))
+    } else {
+        0
+    }
+}
+
+fn block_value(block: FieldBlock) -> u32 {
+    match block {
+        FieldBlock::Empty => 0,
+        FieldBlock::Occupied(shape) => shape_value(shape),
+        FieldBlock::Garbage => GARBAGE,
+    }
+}
+
+fn value_block(value: u32) -> Option<FieldBlock> {
+    match value {
+        0 => Some(FieldBlock::Empty),
+        GARBAGE => Some(FieldBlock::Garbage),
+        value => Some(FieldBlock::Occupied(value_shape(value)?)),
+    }
+}
+
+fn shape_value(shape: Shape) -> u32 {
+    match shape {
+        Shape::I => 1,
+        Shape::L => 2,
+        Shape::O => 3,
+        Shape::Z => 4,
+        Shape::T => 5,
+        Shape::J => 6,
+        Shape::S => 7,
+    }
+}
+
+fn value_shape(value: u32) -> Option<Shape> {
+    Shape::ALL
+        .iter()
+        .copied()
+        .find(|s| shape_value(*s) == value)
+}
+
+/// Fumen numbers rotations starting from upside down
+fn rotation_value(rotation: Rotations) -> u32 {
+    match rotation {
+        Rotations::Two => 0,
+        Rotations::One => 1,
+        Rotations::Zero => 2,
+        Rotations::Three => 3,
+    }
+}
+
+/// How far the position fumen stores is from the SRS centre. Fumen keeps the reference cell of
+/// its original editor, which only matches the SRS centre for some orientations; these are the
+/// same corrections tetris-fumen makes.
+fn position_offset(shape: Shape, rotation: Rotations) -> Pos {
+    match (shape, rotation) {
+        (Shape::O, Rotations::Zero) => p(0, 1),
+        (Shape::O, Rotations::Two) => p(-1, 0),
+        (Shape::O, Rotations::Three) => p(-1, 1),
+        (Shape::I, Rotations::Two) => p(-1, 0),
+        (Shape::I, Rotations::Three) => p(0, 1),
+        (Shape::S, Rotations::Zero) => p(0, 1),
+        (Shape::S, Rotations::One) => p(1, 0),
+        (Shape::Z, Rotations::Zero) => p(0, 1),
+        (Shape::Z, Rotations::Three) => p(-1, 0),
+        _ => p(0, 0),
+    }
+}
+
+/// The position and rotation fumen stores for a piece
+fn fumen_placement(t: Tetromino) -> (Pos, u32) {
+    (
+        t.center() + position_offset(t.shape(), t.rotation()),
+        rotation_value(t.rotation()),
+    )
+}
+
+fn tetromino_at(shape: Shape, value: u32, pos: Pos) -> Option<Tetromino> {
+    let rotation = [
+        Rotations::Zero,
+        Rotations::One,
+        Rotations::Two,
+        Rotations::Three,
+    ]
+    .iter()
+    .copied()
+    .find(|r| rotation_value(*r) == value)?;
+    let offset = position_offset(shape, rotation);
+    Tetromino::at_center(shape, rotation, p(pos.x - offset.x, pos.y - offset.y))
+}
+
+fn encode_position(pos: Pos) -> u32 {
+    ((HEIGHT - pos.y - 1) * WIDTH + pos.x).max(0) as u32
+}
+
+fn decode_position(position: u32) -> Pos {
+    let position = position as Coord;
+    p(position % WIDTH, HEIGHT - position / WIDTH - 1)
+}
+
+fn quiz_comment(page: &Page) -> Option<String> {
+    if page.hold.is_none() && page.queue.is_empty() {
+        return None;
+    }
+    let mut pieces = vec![];
+    if let Some(t) = page.piece {
+        pieces.push(t.shape());
+    }
+    pieces.extend_from_slice(&page.queue);
+    let letters = |shapes: &[Shape]| -> String { shapes.iter().map(|s| s.letter()).collect() };
+    Some(format!(
+        "{}[{}]({}){}",
+        QUIZ_PREFIX,
+        page.hold
+            .map(|s| s.letter())
+            .into_iter()
+            .collect::<String>(),
+        letters(pieces.get(0..1).unwrap_or(&[])),
+        letters(pieces.get(1..).unwrap_or(&[]))
+    ))
+}
+
+/// The hold piece and the current piece followed by the rest of the queue
+fn parse_quiz(comment: &str) -> Option<(Option<Shape>, Vec<Shape>)> {
+    let rest = comment.strip_prefix(QUIZ_PREFIX)?.strip_prefix('[')?;
+    let close_hold = rest.find(']')?;
+    let hold = rest[..close_hold].chars().next();
+    let rest = rest[close_hold + 1..].strip_prefix('(')?;
+    let close_current = rest.find(')')?;
+    let mut pieces = vec![];
+    for c in rest[..close_current]
+        .chars()
+        .chain(rest[close_current + 1..].chars())
+    {
+        if c.is_whitespace() {
+            break;
+        }
+        pieces.push(Shape::from_letter(c)?);
+    }
+    let hold = match hold {
+        Some(c) => Some(Shape::from_letter(c)?),
+        None => None,
+    };
+    Some((hold, pieces))
+}
+
+/// The quiz after `used` is placed, either as the current piece or by swapping with hold
+fn advance_quiz(
+    (hold, mut pieces): (Option<Shape>, Vec<Shape>),
+    used: Shape,
+) -> Option<(Option<Shape>, Vec<Shape>)> {
+    if pieces.first() == Some(&used) {
+        pieces.remove(0);
+        Some((hold, pieces))
+    } else if hold == Some(used) && !pieces.is_empty() {
+        let current = pieces.remove(0);
+        Some((Some(current), pieces))
+    } else if hold.is_none() && pieces.get(1) == Some(&used) {
+        let current = pieces.remove(0);
+        pieces.remove(0);
+        Some((Some(current), pieces))
+    } else {
+        None
+    }
+}
+
+/// Matches JavaScript's `escape`, which fumen applies to comments
+fn escape(text: &str) -> String {
+    let mut result = String::new();
+    for c in text.chars() {
+        if c.is_ascii_alphanumeric() || "@*_+-./".contains(c) {
+            result.push(c);
+        } else if (c as u32) < 0x100 {
+            result += &format!("%{:02X}", c as u32);
+        } else {
+            let mut units = [0; 2];
+            for unit in c.encode_utf16(&mut units).iter() {
+                result += &format!("%u{:04X}", unit);
+            }
+        }
+    }
+    result
+}
+
+fn unescape(text: &str) -> Option<String> {
+    let mut units: Vec<u16> = vec![];
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        if c != '%' {
+            units.push(c as u16);
+            rest = &rest[c.len_utf8()..];
+        } else if rest[1..].starts_with('u') {
+            units.push(u16::from_str_radix(rest.get(2..6)?, 16).ok()?);
+            rest = &rest[6..];
+        } else {
+            units.push(u16::from_str_radix(rest.get(1..3)?, 16).ok()?);
+            rest = &rest[3..];
+        }
+    }
+    core::char::decode_utf16(units.into_iter())
+        .collect::<Result<String, _>>()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(field: Field, piece: Option<Tetromino>) -> Page {
+        Page {
+            field: field,
+            piece: piece,
+            hold: None,
+            queue: vec![],
+        }
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!("v115@vhAAgH", encode(&[page(Field::new(), None)]));
+
+        let pages = decode("v115@vhAAgH").unwrap();
+        assert_eq!(1, pages.len());
+        assert!(pages[0].field.occupied_rows().is_empty());
+        assert!(pages[0].piece.is_none());
+    }
+
+    #[test]
+    fn pieces_round_trip() {
+        for shape in Shape::ALL.iter() {
+            for rotation in [
+                Rotations::Zero,
+                Rotations::One,
+                Rotations::Two,
+                Rotations::Three,
+            ]
+            .iter()
+            {
+                let t = Tetromino::with_rotation(p(3, 4), *shape, *rotation);
+                let (center, value) = fumen_placement(t);
+                assert_eq!(Some(t), tetromino_at(*shape, value, center));
+            }
+        }
+
+        // A T spawned flat in the bottom left corner
+        let t = tetromino_at(Shape::T, 2, p(1, 0)).unwrap();
+        let minos = t.to_minos();
+        assert!(minos.contains(p(0, 0)) && minos.contains(p(2, 0)) && minos.contains(p(1, 1)));
+    }
+
+    #[test]
+    fn piece_positions() {
+        // Single pieces on an empty board, with the cells fumen draws them in, counting rows up
+        // from the bottom. These are the orientations where fumen's stored position isn't the SRS
+        // centre, plus a T where it is.
+        let cases = [
+            ("v115@vhATLJ", [p(4, 0), p(5, 0), p(4, 1), p(5, 1)]),
+            ("v115@vhAbNJ", [p(8, 0), p(9, 0), p(8, 1), p(9, 1)]),
+            ("v115@vhABQJ", [p(3, 0), p(4, 0), p(5, 0), p(6, 0)]),
+            ("v115@vhAZEJ", [p(0, 0), p(0, 1), p(0, 2), p(0, 3)]),
+            ("v115@vhAXLJ", [p(3, 0), p(4, 0), p(4, 1), p(5, 1)]),
+            ("v115@vhAvJJ", [p(1, 0), p(1, 1), p(0, 1), p(0, 2)]),
+            ("v115@vhAULJ", [p(4, 0), p(5, 0), p(3, 1), p(4, 1)]),
+            ("v115@vhAcNJ", [p(8, 0), p(8, 1), p(9, 1), p(9, 2)]),
+            ("v115@vhAVQJ", [p(3, 0), p(4, 0), p(5, 0), p(4, 1)]),
+        ];
+        for (fumen, cells) in cases.iter() {
+            let pages = decode(fumen).unwrap();
+            let piece = pages[0].piece.unwrap();
+            let minos = piece.to_minos();
+            assert!(
+                cells.iter().all(|cell| minos.contains(*cell)),
+                "{} placed at {:?}",
+                fumen,
+                minos.positions()
+            );
+            assert_eq!(*fumen, encode(&[page(Field::new(), Some(piece))]));
+        }
+    }
+
+    #[test]
+    fn multiple_pages() {
+        let mut field = Field::new();
+        for x in 0..8 {
+            field.set_block(p(x, 0), FieldBlock::Garbage);
+        }
+        field.occupy(p(9, 1), Shape::J);
+
+        let o = Tetromino::with_rotation(p(7, -2), Shape::O, Rotations::Zero);
+        let pages = vec![
+            Page {
+                field: field.clone(),
+                piece: Some(o),
+                hold: Some(Shape::I),
+                queue: vec![Shape::T, Shape::S],
+            },
+            page(Field::new(), None),
+            page(Field::new(), None),
+        ];
+        let fumen = encode(&pages);
+        let decoded = decode(&fumen).unwrap();
+
+        assert_eq!(3, decoded.len());
+        assert_eq!(Some(o), decoded[0].piece);
+        assert_eq!(Some(Shape::I), decoded[0].hold);
+        assert_eq!(vec![Shape::T, Shape::S], decoded[0].queue);
+        assert_eq!(FieldBlock::Garbage, decoded[0].field.block(p(0, 0)));
+        assert_eq!(
+            FieldBlock::Occupied(Shape::J),
+            decoded[0].field.block(p(9, 1))
+        );
+        assert!(decoded[1].field.occupied_rows().is_empty());
+        assert!(decoded[2].hold.is_none());
+    }
+
+    #[test]
+    fn quiz_advances() {
+        let pieces = parse_quiz("#Q=[](T)IO").unwrap();
+        assert_eq!((None, vec![Shape::T, Shape::I, Shape::O]), pieces);
+        assert_eq!(
+            Some((Some(Shape::T), vec![Shape::O])),
+            advance_quiz(pieces, Shape::I)
+        );
+        assert_eq!("%23Q%3D%5B%5D%28T%29IO", escape("#Q=[](T)IO"));
+        assert_eq!(
+            Some(String::from("#Q=[](T)IO")),
+            unescape("%23Q%3D%5B%5D%28T%29IO")
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(decode("v110@vhAAgH").is_none());
+        assert!(decode("v115@vh").is_none());
+        assert!(decode("v115@!!").is_none());
+        // A comment character past the end of the table
+        assert!(decode("v115@vhAAAPBAfBAAA").is_none());
+    }
+
+    #[test]
+    fn pieces_off_the_board() {
+        assert!(decode("v115@vhAVhB").is_some());
+        // A T in the garbage row
+        assert!(decode("v115@vhAV1B").is_none());
+        // An I sticking out past the right edge
+        assert!(decode("v115@vhAxZB").is_none());
+    }
+}
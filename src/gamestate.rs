@@ -1,46 +1,164 @@
+use crate::ai::{AiPlayer, Difficulty};
 use crate::controlled::{ControlledBlocks, DropResult};
 use crate::field::{Field, PlayingFieldRenderBlocksInstructions};
-use crate::keybindings::{KeyboardStates, Trigger};
-use crate::position::{p, Coord, Pos};
+use crate::finesse::PieceInputs;
+use crate::finesse_drill::FinesseDrill;
+use crate::fumen::Page;
+use crate::garbage::RisingGarbage;
+use crate::keybindings::Trigger;
+use crate::mode::GameMode;
+use crate::perfect_clear::{AttemptResult, PerfectClearPractice};
+use crate::position::Coord;
+use crate::puzzle::{PuzzleResult, PuzzleRun};
 use crate::random_bag::RandomBag;
+use crate::replay::Replay;
+use crate::rng::Rng;
+use crate::savegame::{SaveReader, SaveWriter};
+use crate::settings::GameSettings;
+use crate::setups::{PieceCheck, SetupPractice, SETUPS};
 use crate::shapes::Shape;
+use crate::stats::{LineClear, Statistics};
 use crate::tetromino::Tetromino;
 use crate::time::{GameClock, GameTime};
+use crate::visibility::StackVisibility;
+use crate::zen::ZenTotals;
+use alloc::boxed::Box;
+use alloc::string::String;
 use alloc::vec::Vec;
-use core::ops::Index;
 use core::time::Duration;
-use quicksilver::input::{ButtonState, Key};
 
 pub struct GameState {
+    mode: GameMode,
+    seed: u64,
+    rng: Rng,
     field: Field,
     control: Control,
     random_bag: RandomBag,
     hold_piece: Option<Shape>,
     can_hold: bool,
-    keyboard_states: KeyboardStates,
-    cleared_lines: i32,
+    stats: Statistics,
+    banner: Option<(&'static str, GameTime)>,
+    rising_garbage: Option<RisingGarbage>,
+    perfect_clear_practice: Option<PerfectClearPractice>,
+    finesse_drill: Option<FinesseDrill>,
+    setup_practice: Option<SetupPractice>,
+    puzzle_run: Option<PuzzleRun>,
+    /// Zen totals from earlier games, not counting this one
+    zen_totals: Option<ZenTotals>,
+    /// Rows sent by an opponent, which rise when a piece locks without clearing lines
+    incoming_garbage: u32,
+    /// Rows this game has sent that the opponent hasn't received yet
+    outgoing_garbage: u32,
+    opponent: Option<Box<Opponent>>,
+    /// Suggests where each piece goes, when hints are on for the mode
+    hinter: Option<AiPlayer>,
+    hint: Option<Tetromino>,
+    piece_inputs: Option<PieceInputs>,
+    strict_finesse: bool,
+    /// The game as it was before each recent lock, latest last
+    undo_stack: Vec<Snapshot>,
+    /// Set once anything is undone, since the result no longer counts for records
+    used_undo: bool,
+    /// Set if hints were on at any point, which also keeps the result out of records
+    used_hints: bool,
+    replay: Replay,
+    sound_effects: Vec<SoundEffect>,
+    start_time: GameTime,
+    last_update_time: GameTime,
+}
+
+/// The AI playing against a player in versus mode
+struct Opponent {
+    game: GameState,
+    ai: AiPlayer,
+}
+
+/// Everything a lock changes, to put back when it is undone
+struct Snapshot {
+    field: Field,
+    /// The piece which locked, to play again
+    shape: Shape,
+    hold_piece: Option<Shape>,
+    can_hold: bool,
+    random_bag: RandomBag,
+    rng: Rng,
+    stats: Statistics,
+    perfect_clear_practice: Option<PerfectClearPractice>,
+    setup_practice: Option<SetupPractice>,
+    puzzle_run: Option<PuzzleRun>,
+}
+
+/// The results of a game, for the end of game screen
+#[derive(Copy, Clone)]
+pub struct Summary {
+    pub mode: GameMode,
+    pub seed: u64,
+    pub stats: Statistics,
+    pub elapsed: Duration,
 }
 
 pub struct RenderInfo<'a> {
+    pub mode: GameMode,
     pub playing_field: PlayingFieldRenderBlocksInstructions<'a>,
     pub previews: Vec<Shape>,
     pub hold_piece: Option<Shape>,
-    pub cleared_lines: i32,
+    pub stats: Statistics,
     pub level: i32,
+    pub elapsed: Duration,
+    pub banner: Option<&'static str>,
+    pub pending_garbage: Option<u32>,
+    pub perfect_clear_practice: Option<&'a PerfectClearPractice>,
+    pub finesse_drill: Option<&'a FinesseDrill>,
+    pub setup_practice: Option<&'a SetupPractice>,
+    pub puzzle_run: Option<&'a PuzzleRun>,
+    pub zen_totals: Option<ZenTotals>,
+    pub opponent: Option<Box<RenderInfo<'a>>>,
 }
 
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum SoundEffect {
+    Lock,
+    LineClear,
+}
+
+#[derive(Copy, Clone)]
 pub enum GameCondition {
     Playing,
     Won,
-    Lost,
+    Lost(LossReason),
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum LossReason {
+    /// A new piece overlapped the stack where it spawned
+    BlockOut,
+    /// A piece locked entirely above the playing boundary
+    LockOut,
+    /// Garbage pushed the stack out of the top of the field
+    TopOut,
+    /// A piece took more inputs than needed while strict finesse was on
+    FinesseFault,
 }
 
 const MAX_LEVEL: i32 = 15;
+const SAVE_FORMAT_VERSION: u32 = 10;
+const BANNER_DURATION: Duration = Duration::from_millis(2000);
+/// How long a drill piece stays where it locked before the next target
+const DRILL_FEEDBACK_DURATION: Duration = Duration::from_millis(500);
+/// How many pieces can be undone in a row
+const MAX_UNDO: usize = 100;
+/// Gravity in zen mode, when it's on
+const ZEN_DROP_PERIOD: Duration = Duration::from_millis(2000);
 
 enum Control {
+    /// "Ready" before the game starts, until the given time
+    Countdown(GameTime),
     Blocks(ControlledBlocks),
     WaitForClear(Vec<Coord>, GameTime),
     TakeHold(Shape),
+    /// A finesse drill piece shown where it locked, without joining the field, until the time
+    Placed(Tetromino, GameTime),
+    Lost(LossReason),
 }
 
 impl Control {
@@ -52,35 +170,259 @@ impl Control {
     }
 }
 
+impl Control {
+    fn save(&self, w: &mut SaveWriter) {
+        w.label("control");
+        match self {
+            Control::Countdown(go_time) => {
+                w.put("countdown");
+                w.put_time(*go_time);
+            }
+            Control::Blocks(blocks) => {
+                w.put("blocks");
+                blocks.save(w);
+            }
+            Control::WaitForClear(lines, end_time) => {
+                w.put("clearing");
+                w.put_time(*end_time);
+                w.put(lines.len());
+                for y in lines.iter() {
+                    w.put(y);
+                }
+            }
+            Control::TakeHold(shape) => {
+                w.put("hold");
+                w.put_shape(Some(*shape));
+            }
+            Control::Placed(tetromino, end_time) => {
+                w.put("placed");
+                tetromino.save(w);
+                w.put_time(*end_time);
+            }
+            Control::Lost(reason) => {
+                w.put("lost");
+                w.put(reason.code());
+            }
+        }
+    }
+
+    fn load(r: &mut SaveReader) -> Option<Control> {
+        r.label("control")?;
+        Some(match r.token()? {
+            "countdown" => Control::Countdown(r.get_time()?),
+            "blocks" => Control::Blocks(ControlledBlocks::load(r)?),
+            "clearing" => {
+                let end_time = r.get_time()?;
+                let count: usize = r.get()?;
+                let mut lines = vec![];
+                for _ in 0..count {
+                    lines.push(r.get()?);
+                }
+                Control::WaitForClear(lines, end_time)
+            }
+            "hold" => Control::TakeHold(r.get_shape()??),
+            "placed" => Control::Placed(Tetromino::load(r)?, r.get_time()?),
+            "lost" => Control::Lost(LossReason::from_code(r.token()?)?),
+            _ => return None,
+        })
+    }
+}
+
+impl LossReason {
+    pub fn description(&self) -> &'static str {
+        match self {
+            LossReason::BlockOut => "Block out",
+            LossReason::LockOut => "Lock out",
+            LossReason::TopOut => "Top out",
+            LossReason::FinesseFault => "Finesse fault",
+        }
+    }
+
+    fn code(&self) -> &'static str {
+        match self {
+            LossReason::BlockOut => "block-out",
+            LossReason::LockOut => "lock-out",
+            LossReason::TopOut => "top-out",
+            LossReason::FinesseFault => "finesse",
+        }
+    }
+
+    fn from_code(code: &str) -> Option<LossReason> {
+        [
+            LossReason::BlockOut,
+            LossReason::LockOut,
+            LossReason::TopOut,
+            LossReason::FinesseFault,
+        ]
+        .iter()
+        .copied()
+        .find(|r| r.code() == code)
+    }
+}
+
 impl GameState {
-    pub fn new() -> (GameState, GameClock) {
+    pub fn new(mode: GameMode, seed: u64, settings: &GameSettings) -> (GameState, GameClock) {
+        let (mut game, clock) = GameState::new_without_opponent(mode, seed, settings);
+        if let GameMode::Versus { difficulty } = mode {
+            // Both sides get the same pieces
+            let (mut opponent, _) = GameState::new_without_opponent(mode, seed, settings);
+            opponent.hinter = None;
+            // The AI doesn't play with minimal inputs, so strict finesse would restart it at once
+            opponent.strict_finesse = false;
+            game.opponent = Some(Box::new(Opponent {
+                game: opponent,
+                ai: AiPlayer::new(difficulty),
+            }));
+        }
+        (game, clock)
+    }
+
+    /// A game with no built-in opponent, even in versus mode where the other side is played
+    /// elsewhere and garbage is passed with `take_outgoing_garbage` and `receive_garbage`
+    pub fn new_without_opponent(
+        mode: GameMode,
+        seed: u64,
+        settings: &GameSettings,
+    ) -> (GameState, GameClock) {
         let clock = GameClock::new();
+        // Gravity, garbage and the game timer all start at "Go"
+        let go_time = clock.now() + settings.start_countdown;
+        let puzzle_run = match mode {
+            GameMode::Puzzle { puzzle } => Some(PuzzleRun::new(puzzle)),
+            _ => None,
+        };
         (
             GameState {
-                field: Field::new(),
-                control: Control::WaitForClear(vec![], clock.now()),
-                random_bag: RandomBag::new(),
+                mode: mode,
+                seed: seed,
+                // Use a different stream than the bag so garbage doesn't follow the piece order
+                rng: Rng::new(!seed),
+                field: new_field(mode, settings, &puzzle_run),
+                control: Control::Countdown(go_time),
+                random_bag: match (mode, &puzzle_run) {
+                    (GameMode::Setup { setup }, _) => {
+                        RandomBag::with_queue(seed, &SETUPS[setup].pieces())
+                    }
+                    (_, Some(run)) => RandomBag::with_queue(seed, &run.puzzle().queue),
+                    _ => RandomBag::new(seed),
+                },
                 hold_piece: None,
                 can_hold: true,
-                keyboard_states: KeyboardStates::new(),
-                cleared_lines: 0,
+                stats: Statistics::default(),
+                banner: None,
+                rising_garbage: match mode {
+                    GameMode::Survival => Some(RisingGarbage::new(go_time)),
+                    _ => None,
+                },
+                perfect_clear_practice: match mode {
+                    GameMode::PerfectClear => Some(PerfectClearPractice::new()),
+                    _ => None,
+                },
+                finesse_drill: match mode {
+                    GameMode::FinesseTrainer => {
+                        Some(FinesseDrill::new(&settings.board.new_field(), seed))
+                    }
+                    _ => None,
+                },
+                setup_practice: match mode {
+                    GameMode::Setup { setup } => Some(SetupPractice::new(setup)),
+                    _ => None,
+                },
+                puzzle_run: puzzle_run,
+                zen_totals: new_zen_totals(mode),
+                incoming_garbage: 0,
+                outgoing_garbage: 0,
+                opponent: None,
+                hinter: new_hinter(mode, settings),
+                hint: None,
+                piece_inputs: None,
+                strict_finesse: settings.strict_finesse,
+                undo_stack: vec![],
+                used_undo: false,
+                used_hints: settings.hints_for(mode),
+                replay: Replay::new(mode, seed),
+                sound_effects: vec![],
+                start_time: go_time,
+                last_update_time: go_time,
             },
             clock,
         )
     }
 
-    pub fn update<T>(&mut self, keyboard: &T, now: GameTime) -> GameCondition
-    where
-        T: Index<Key, Output = ButtonState>,
-    {
+    /// A game starting from a shared position, with its pieces dealt before the random ones
+    pub fn from_position(
+        mode: GameMode,
+        seed: u64,
+        settings: &GameSettings,
+        page: &Page,
+    ) -> (GameState, GameClock) {
+        let (mut game, clock) = GameState::new(mode, seed, settings);
+        let mut queue: Vec<Shape> = page.piece.iter().map(|t| t.shape()).collect();
+        queue.extend_from_slice(&page.queue);
+        game.field = page.field.clone();
+        game.hold_piece = page.hold;
+        game.random_bag = RandomBag::with_queue(seed, &queue);
+        (game, clock)
+    }
+
+    /// The board, falling piece, hold and previews as they are now, to share as a fumen
+    pub fn position(&self) -> Page {
+        Page {
+            field: self.field.clone(),
+            piece: match &self.control {
+                Control::Blocks(b) => Some(b.tetromino),
+                _ => None,
+            },
+            hold: self.hold_piece,
+            queue: self.random_bag.previews(),
+        }
+    }
+
+    /// Plays the triggers fired from a person's keyboard, each with whether its key was just
+    /// pressed, along with the number of key presses since the last update
+    pub fn update_from_keyboard(
+        &mut self,
+        inputs: Vec<(Trigger, bool)>,
+        presses: u32,
+        now: GameTime,
+    ) -> GameCondition {
+        // Presses before "Go" don't do anything, so they don't count against finesse either
+        let counting_down = match self.control {
+            Control::Countdown(go_time) => now < go_time,
+            _ => false,
+        };
+        if !counting_down {
+            self.stats.key_presses += presses;
+        }
+        self.play(inputs, now)
+    }
+
+    /// Plays `triggers` as if they came from the keyboard, for players other than a person.
+    /// Each one counts as a separate key press.
+    pub fn update_with(&mut self, triggers: Vec<Trigger>, now: GameTime) -> GameCondition {
+        self.play(triggers.into_iter().map(|t| (t, true)).collect(), now)
+    }
+
+    fn play(&mut self, inputs: Vec<(Trigger, bool)>, now: GameTime) -> GameCondition {
+        self.last_update_time = now;
+
+        if let Control::Countdown(go_time) = self.control {
+            if now < go_time {
+                return GameCondition::Playing;
+            }
+            self.control = Control::WaitForClear(vec![], go_time);
+            self.banner = Some(("GO!", go_time));
+        }
+
         if let Control::TakeHold(shape) = &mut self.control {
             let s = *shape;
-            self.control = match self.make_controlled_blocks(now, s) {
-                Some(t) => Control::Blocks(t),
-                None => {
-                    return GameCondition::Lost;
-                }
-            };
+            self.spawn(now, s);
+        }
+
+        if let Control::Placed(_, end_time) = self.control {
+            if end_time <= now {
+                self.control = Control::WaitForClear(vec![], end_time);
+            }
         }
 
         let level = self.level();
@@ -88,35 +430,305 @@ impl GameState {
             if *end_time <= now {
                 self.field.remove_lines(&lines);
 
-                if level > MAX_LEVEL {
+                let won = match self.mode {
+                    GameMode::Marathon { .. } | GameMode::Hidden { .. } => level > MAX_LEVEL,
+                    GameMode::Sprint { lines } => self.stats.lines >= lines,
+                    GameMode::Puzzle { .. } => {
+                        self.puzzle_run.as_ref().map_or(false, |r| r.is_solved())
+                    }
+                    _ => false,
+                };
+                if won {
                     self.control = Control::WaitForClear(vec![], *end_time);
                     return GameCondition::Won;
                 }
 
-                let shape = self.random_bag.take_next();
-                self.control = match self.make_controlled_blocks(now, shape) {
-                    Some(t) => Control::Blocks(t),
-                    None => {
-                        return GameCondition::Lost;
-                    }
-                };
+                match self.next_shape() {
+                    Some(shape) => self.spawn(now, shape),
+                    None => self.retry_puzzle("OUT OF PIECES", now),
+                }
             }
         }
 
-        for trigger in self.keyboard_states.update(keyboard, now) {
-            self.handle_input(trigger, now);
+        for (trigger, pressed) in inputs {
+            self.replay.record(now - self.start_time, trigger);
+            self.handle_input(trigger, pressed, now);
         }
 
-        if let Some(b) = self.control.as_blocks() {
+        let has_gravity = self.has_gravity();
+        if let (Some(b), true) = (self.control.as_blocks(), has_gravity) {
             let drop = b.periodic_drop(&self.field, now);
             self.handle_soft_drop(drop, now);
         }
 
-        GameCondition::Playing
+        if let Some(g) = &mut self.rising_garbage {
+            g.update(now);
+        }
+
+        // Topping out as time runs out still loses
+        if let Control::Lost(reason) = self.control {
+            return GameCondition::Lost(reason);
+        }
+
+        if let GameMode::Ultra { seconds } = self.mode {
+            if now - self.start_time >= Duration::from_secs(seconds as u64) {
+                return GameCondition::Won;
+            }
+        }
+        match self.update_opponent(now) {
+            GameCondition::Lost(_) => GameCondition::Won,
+            _ => GameCondition::Playing,
+        }
+    }
+
+    /// Lets the AI opponent move, and exchanges garbage with it
+    fn update_opponent(&mut self, now: GameTime) -> GameCondition {
+        let opponent = match &mut self.opponent {
+            Some(opponent) => opponent,
+            None => return GameCondition::Playing,
+        };
+        let inputs = opponent.ai.update(&opponent.game, now);
+        let condition = opponent.game.update_with(inputs, now);
+        opponent.game.sound_effects.clear();
+        opponent
+            .game
+            .receive_garbage(core::mem::replace(&mut self.outgoing_garbage, 0));
+        self.incoming_garbage += opponent.game.take_outgoing_garbage();
+        condition
+    }
+
+    /// Garbage rows sent since the last call, for the opponent to receive
+    pub fn take_outgoing_garbage(&mut self) -> u32 {
+        core::mem::replace(&mut self.outgoing_garbage, 0)
+    }
+
+    /// Garbage rows that rise the next time a piece locks without clearing lines
+    pub fn receive_garbage(&mut self, rows: u32) {
+        self.incoming_garbage += rows;
+    }
+
+    pub fn field(&self) -> &Field {
+        &self.field
+    }
+
+    /// The piece being moved, if there is one
+    pub fn current_piece(&self) -> Option<Tetromino> {
+        match &self.control {
+            Control::Blocks(b) => Some(b.tetromino),
+            _ => None,
+        }
+    }
+
+    pub fn hold_piece(&self) -> Option<Shape> {
+        self.hold_piece
+    }
+
+    /// Whether the current piece can still be swapped with the hold piece
+    pub fn can_hold(&self) -> bool {
+        self.can_hold
+    }
+
+    pub fn previews(&self) -> Vec<Shape> {
+        if self.finesse_drill.is_some() {
+            // Drill pieces come from the targets rather than the bag
+            return vec![];
+        }
+        let mut previews = self.random_bag.previews();
+        if let Some(run) = &self.puzzle_run {
+            // Nothing comes after a puzzle's queue
+            previews.truncate(run.remaining());
+        }
+        previews
+    }
+
+    /// The whole game as versioned text, to continue later or to attach to a bug report
+    pub fn save(&self) -> String {
+        let mut w = SaveWriter::new();
+        w.label("blocks-save");
+        w.put(SAVE_FORMAT_VERSION);
+        self.save_to(&mut w);
+        w.finish()
+    }
+
+    fn save_to(&self, w: &mut SaveWriter) {
+        w.label("game");
+        w.put(self.mode.code());
+        w.put(self.seed);
+        w.put_time(self.start_time);
+        w.put_time(self.last_update_time);
+        w.put_shape(self.hold_piece);
+        w.put(self.can_hold);
+        // Only whether undo was used is kept, so a continued game starts with nothing to undo
+        w.put(self.used_undo);
+        w.put(self.used_hints);
+        w.label("rng");
+        self.rng.save(w);
+        self.control.save(w);
+        self.field.save(w);
+        self.random_bag.save(w);
+        self.stats.save(w);
+        w.label("garbage");
+        w.put(self.rising_garbage.is_some());
+        if let Some(g) = &self.rising_garbage {
+            g.save(w);
+        }
+        w.label("practice");
+        w.put(self.perfect_clear_practice.is_some());
+        if let Some(p) = &self.perfect_clear_practice {
+            p.save(w);
+        }
+        w.put(self.finesse_drill.is_some());
+        if let Some(drill) = &self.finesse_drill {
+            drill.save(w);
+        }
+        w.put(self.setup_practice.is_some());
+        if let Some(p) = &self.setup_practice {
+            p.save(w);
+        }
+        w.put(self.puzzle_run.is_some());
+        if let Some(run) = &self.puzzle_run {
+            run.save(w);
+        }
+        w.label("versus");
+        w.put(self.incoming_garbage);
+        w.put(self.outgoing_garbage);
+        w.put(self.opponent.is_some());
+        if let Some(opponent) = &self.opponent {
+            // The AI makes a new plan for its current piece when the game continues
+            opponent.game.save_to(w);
+        }
+        self.replay.save_inputs(w);
     }
 
-    fn handle_input(&mut self, trigger: Trigger, now: GameTime) -> Option<()> {
+    /// Restores a saved game, with a clock that continues from the moment it was saved
+    pub fn load(text: &str, settings: &GameSettings) -> Option<(GameState, GameClock)> {
+        let mut r = SaveReader::new(text);
+        r.label("blocks-save")?;
+        if r.get::<u32>()? != SAVE_FORMAT_VERSION {
+            return None;
+        }
+        let game = GameState::load_from(&mut r, settings)?;
+        let clock = GameClock::resumed_at(game.last_update_time);
+        Some((game, clock))
+    }
+
+    fn load_from(r: &mut SaveReader, settings: &GameSettings) -> Option<GameState> {
+        r.label("game")?;
+        let mode = GameMode::from_code(r.token()?)?;
+        let seed = r.get()?;
+        let start_time = r.get_time()?;
+        let last_update_time = r.get_time()?;
+        let hold_piece = r.get_shape()?;
+        let can_hold = r.get()?;
+        let used_undo = r.get()?;
+        let used_hints = r.get::<bool>()? || settings.hints_for(mode);
+        r.label("rng")?;
+        let rng = Rng::load(r)?;
+        let control = Control::load(r)?;
+        let field = match mode.stack_visibility() {
+            // Blocks from before the save have no lock time, like garbage
+            Some(_) => Field::load(r)?.with_lock_times(),
+            None => Field::load(r)?,
+        };
+        let random_bag = RandomBag::load(r)?;
+        let stats = Statistics::load(r)?;
+        r.label("garbage")?;
+        let rising_garbage = match r.get()? {
+            true => Some(RisingGarbage::load(r)?),
+            false => None,
+        };
+        r.label("practice")?;
+        let perfect_clear_practice = match r.get()? {
+            true => Some(PerfectClearPractice::load(r)?),
+            false => None,
+        };
+        let finesse_drill = match r.get()? {
+            true => Some(FinesseDrill::load(r, &field)?),
+            false => None,
+        };
+        let setup_practice = match (r.get()?, mode) {
+            (true, GameMode::Setup { setup }) => Some(SetupPractice::load(r, setup)?),
+            (false, _) => None,
+            (true, _) => return None,
+        };
+        let puzzle_run = match (r.get()?, mode) {
+            (true, GameMode::Puzzle { puzzle }) => Some(PuzzleRun::load(r, puzzle)?),
+            (false, _) => None,
+            (true, _) => return None,
+        };
+        r.label("versus")?;
+        let incoming_garbage = r.get()?;
+        let outgoing_garbage = r.get()?;
+        let opponent = match (r.get()?, mode) {
+            (true, GameMode::Versus { difficulty }) => {
+                let mut game = GameState::load_from(r, settings)?;
+                game.hinter = None;
+                game.hint = None;
+                game.strict_finesse = false;
+                Some(Box::new(Opponent {
+                    game: game,
+                    ai: AiPlayer::new(difficulty),
+                }))
+            }
+            (false, _) => None,
+            (true, _) => return None,
+        };
+        let replay = Replay::load_inputs(mode, seed, r)?;
+
+        let mut game = GameState {
+            mode: mode,
+            seed: seed,
+            rng: rng,
+            field: field,
+            control: control,
+            random_bag: random_bag,
+            hold_piece: hold_piece,
+            can_hold: can_hold,
+            stats: stats,
+            banner: None,
+            rising_garbage: rising_garbage,
+            perfect_clear_practice: perfect_clear_practice,
+            finesse_drill: finesse_drill,
+            setup_practice: setup_practice,
+            puzzle_run: puzzle_run,
+            zen_totals: new_zen_totals(mode),
+            incoming_garbage: incoming_garbage,
+            outgoing_garbage: outgoing_garbage,
+            opponent: opponent,
+            hinter: new_hinter(mode, settings),
+            hint: None,
+            piece_inputs: None,
+            strict_finesse: settings.strict_finesse,
+            undo_stack: vec![],
+            used_undo: used_undo,
+            used_hints: used_hints,
+            replay: replay,
+            sound_effects: vec![],
+            start_time: start_time,
+            last_update_time: last_update_time,
+        };
+        game.update_hint();
+        Some(game)
+    }
+
+    /// Picks up settings changes made while the game was paused
+    pub fn apply_settings(&mut self, settings: &GameSettings) {
+        self.strict_finesse = settings.strict_finesse;
+    }
+
+    /// Sounds triggered since the last call
+    pub fn take_sound_effects(&mut self) -> Vec<SoundEffect> {
+        core::mem::replace(&mut self.sound_effects, vec![])
+    }
+
+    fn handle_input(&mut self, trigger: Trigger, pressed: bool, now: GameTime) -> Option<()> {
+        if trigger == Trigger::Undo {
+            return self.undo(now);
+        }
         let blocks = self.control.as_blocks()?;
+        if let Some(inputs) = &mut self.piece_inputs {
+            inputs.record(trigger, pressed);
+        }
         match trigger {
             Trigger::Shift(dir) => blocks.shift(&self.field, dir),
             Trigger::SoftDown => {
@@ -128,8 +740,12 @@ impl GameState {
                 blocks.hard_drop(&self.field);
                 self.replace_controlled_piece(now);
             }
+            Trigger::Undo => (),
             Trigger::HoldPiece => {
-                if self.can_hold {
+                // Drills have no hold, and puzzles say whether theirs can be used
+                let hold_allowed = self.finesse_drill.is_none()
+                    && self.puzzle_run.as_ref().map_or(true, |r| r.puzzle().hold);
+                if self.can_hold && hold_allowed {
                     let new_hold_shape = blocks.minos().shape();
                     self.control = match self.hold_piece {
                         Some(s) => Control::TakeHold(s),
@@ -143,70 +759,800 @@ impl GameState {
         None
     }
 
+    pub fn summary(&self) -> Summary {
+        Summary {
+            mode: self.mode,
+            seed: self.seed,
+            stats: self.stats,
+            elapsed: self.elapsed(),
+        }
+    }
+
+    /// The zen totals including this game so far, to keep as it's played
+    pub fn zen_totals(&self) -> Option<ZenTotals> {
+        self.zen_totals
+            .map(|totals| totals.with_game(&self.summary()))
+    }
+
+    /// The zen totals from games before this one
+    pub fn earlier_zen_totals(&self) -> Option<ZenTotals> {
+        self.zen_totals
+    }
+
+    /// Counts this game on top of `earlier` totals, in zen mode
+    pub fn set_zen_totals(&mut self, earlier: ZenTotals) {
+        if self.zen_totals.is_some() {
+            self.zen_totals = Some(earlier);
+        }
+    }
+
+    /// Only games on a standard board without undo or hints are comparable for high scores
+    pub fn is_record_eligible(&self) -> bool {
+        self.field.width() == Field::STANDARD_WIDTH
+            && self.field.playing_boundary_height() == Field::STANDARD_HEIGHT
+            && !self.used_undo
+            && !self.used_hints
+    }
+
+    pub fn replay(&self) -> &Replay {
+        &self.replay
+    }
+
     pub fn render_info(&self) -> RenderInfo {
         RenderInfo {
+            mode: self.mode,
             playing_field: match &self.control {
                 Control::Blocks(b) => {
                     PlayingFieldRenderBlocksInstructions::new_controlled(&self.field, b.tetromino)
+                        .with_hint(self.hint)
                 }
                 Control::WaitForClear(lines, _) => {
                     PlayingFieldRenderBlocksInstructions::new_clearing(&self.field, lines.clone())
                 }
-                Control::TakeHold(_) => {
+                Control::Placed(tetromino, _) => {
+                    PlayingFieldRenderBlocksInstructions::new_controlled(&self.field, *tetromino)
+                        .with_hint(self.hint)
+                }
+                Control::Countdown(_) | Control::TakeHold(_) | Control::Lost(_) => {
                     PlayingFieldRenderBlocksInstructions::new_clearing(&self.field, vec![])
                 }
-            },
-            previews: self.random_bag.previews(),
+            }
+            .with_target(self.setup_practice.as_ref().map(|p| p.target()))
+            .with_visibility(self.stack_visibility(), self.last_update_time),
+            previews: self.previews(),
             hold_piece: self.hold_piece,
-            cleared_lines: self.cleared_lines,
+            stats: self.stats,
             level: self.level(),
+            elapsed: self.elapsed(),
+            banner: match self.banner {
+                _ if self.is_counting_down() => Some("READY"),
+                Some((text, time)) if self.last_update_time < time + BANNER_DURATION => Some(text),
+                _ => None,
+            },
+            pending_garbage: match self.mode {
+                GameMode::Versus { .. } => Some(self.incoming_garbage),
+                _ => self.rising_garbage.as_ref().map(|g| g.pending()),
+            },
+            perfect_clear_practice: self.perfect_clear_practice.as_ref(),
+            finesse_drill: self.finesse_drill.as_ref(),
+            setup_practice: self.setup_practice.as_ref(),
+            puzzle_run: self.puzzle_run.as_ref(),
+            zen_totals: self.zen_totals(),
+            opponent: self
+                .opponent
+                .as_ref()
+                .map(|opponent| Box::new(opponent.game.render_info())),
+        }
+    }
+
+    /// How the stack is hidden, or none while it's shown: as lines clear and once the game is lost
+    fn stack_visibility(&self) -> Option<StackVisibility> {
+        match &self.control {
+            Control::WaitForClear(lines, _) if !lines.is_empty() => None,
+            Control::Lost(_) => None,
+            _ => self.mode.stack_visibility(),
+        }
+    }
+
+    fn is_counting_down(&self) -> bool {
+        match self.control {
+            Control::Countdown(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Time since "Go"
+    fn elapsed(&self) -> Duration {
+        if self.is_counting_down() {
+            Duration::from_millis(0)
+        } else {
+            self.last_update_time - self.start_time
         }
     }
 
     fn handle_soft_drop(&mut self, drop_result: DropResult, now: GameTime) {
-        if let DropResult::Stop = drop_result {
+        // Zen pieces wait on the stack for as long as it takes, until they're hard dropped
+        if let (DropResult::Stop, false) = (drop_result, self.mode.is_endless()) {
             self.replace_controlled_piece(now);
         }
     }
 
     fn replace_controlled_piece(&mut self, now: GameTime) -> Option<()> {
-        self.control
-            .as_blocks()?
-            .minos()
-            .apply_to_field(&mut self.field);
+        let blocks = self.control.as_blocks()?;
+        let t_spin = blocks.is_t_spin(&self.field);
+        let minos = blocks.minos();
+        let fault = match self.piece_inputs.take() {
+            Some(inputs) => inputs.is_fault(&self.field, blocks.tetromino),
+            None => false,
+        };
+        if let Some(drill) = &mut self.finesse_drill {
+            let locked = blocks.tetromino;
+            let correct = drill.is_on_target(locked) && !fault;
+            drill.record(correct);
+            self.stats.pieces += 1;
+            if fault {
+                self.stats.finesse_faults += 1;
+            }
+            self.sound_effects.push(SoundEffect::Lock);
+            self.banner = Some((if correct { "CORRECT" } else { "MISS" }, now));
+            self.control = Control::Placed(locked, now + DRILL_FEEDBACK_DURATION);
+            return None;
+        }
+        if self.mode.allows_undo() {
+            if self.undo_stack.len() == MAX_UNDO {
+                self.undo_stack.remove(0);
+            }
+            self.undo_stack.push(Snapshot {
+                field: self.field.clone(),
+                shape: minos.shape(),
+                hold_piece: self.hold_piece,
+                can_hold: self.can_hold,
+                random_bag: self.random_bag.clone(),
+                rng: self.rng.clone(),
+                stats: self.stats,
+                perfect_clear_practice: self.perfect_clear_practice.clone(),
+                setup_practice: self.setup_practice.clone(),
+                puzzle_run: self.puzzle_run.clone(),
+            });
+        }
+        minos.apply_to_field(&mut self.field);
+        self.field.mark_locked(&minos, now);
         self.can_hold = true;
+        self.stats.pieces += 1;
+        self.sound_effects.push(SoundEffect::Lock);
+
+        if minos.is_above(self.field.playing_boundary_height()) && !self.mode.is_endless() {
+            self.control = Control::Lost(LossReason::LockOut);
+            return None;
+        }
+        if fault {
+            self.stats.finesse_faults += 1;
+            if self.strict_finesse && !self.mode.is_endless() {
+                self.control = Control::Lost(LossReason::FinesseFault);
+                return None;
+            }
+        }
+        if let Some(practice) = &mut self.setup_practice {
+            let banner = match practice.check(&self.field, &minos) {
+                PieceCheck::Correct => None,
+                PieceCheck::Wrong => Some("WRONG PIECE"),
+                PieceCheck::Built => Some("SETUP BUILT"),
+            };
+            if let Some(text) = banner {
+                // Start the next attempt from an empty board with the same pieces
+                let pieces = practice.setup().pieces();
+                self.banner = Some((text, now));
+                self.field = Field::new();
+                self.random_bag = RandomBag::with_queue(self.rng.next_u64(), &pieces);
+                self.hold_piece = None;
+                self.control = Control::WaitForClear(vec![], now);
+                return None;
+            }
+        }
+
+        let mut lines = self.field.find_lines();
+        let perfect_clear = !lines.is_empty() && self.field.is_clear_except(&lines);
+        if !lines.is_empty() {
+            self.sound_effects.push(SoundEffect::LineClear);
+        }
+        let clear = LineClear {
+            lines: lines.len() as u32,
+            t_spin: t_spin,
+            perfect_clear: perfect_clear,
+        };
+        let mut attack = 0;
+        if !lines.is_empty() || t_spin {
+            let level = self.level();
+            self.stats.record_clear(clear, level);
+            if let Some(text) = clear.banner() {
+                self.banner = Some((text, now));
+            }
+            attack = clear.attack();
+        }
+
+        if let GameMode::Versus { .. } = self.mode {
+            // An attack cancels garbage on its way in before any is sent back
+            let cancelled = attack.min(self.incoming_garbage);
+            self.incoming_garbage -= cancelled;
+            self.outgoing_garbage += attack - cancelled;
+            if lines.is_empty() && self.incoming_garbage > 0 {
+                // Each attack's rows share a hole, like a player would see it arrive
+                let hole = self.rng.below(self.field.width() as usize) as Coord;
+                for _ in 0..core::mem::replace(&mut self.incoming_garbage, 0) {
+                    if !self.field.push_garbage_row(hole) {
+                        self.control = Control::Lost(LossReason::TopOut);
+                        return None;
+                    }
+                }
+            }
+        }
+
+        if let Some(g) = &mut self.rising_garbage {
+            // Cleared lines hold back the rising garbage, otherwise it rises under the locked piece
+            g.cancel(lines.len() as u32);
+            if lines.is_empty() {
+                for _ in 0..g.take_pending() {
+                    let hole = self.rng.below(self.field.width() as usize) as Coord;
+                    if !self.field.push_garbage_row(hole) {
+                        self.control = Control::Lost(LossReason::TopOut);
+                        return None;
+                    }
+                }
+            }
+        }
+
+        if let Some(practice) = &mut self.perfect_clear_practice {
+            let stack_height = self
+                .field
+                .occupied_rows()
+                .iter()
+                .filter(|y| !lines.contains(y))
+                .count();
+            if practice.record_lock(perfect_clear, stack_height) == AttemptResult::Failed {
+                // Clear away the whole stack to start the next attempt on an empty board
+                lines = self.field.occupied_rows();
+            }
+        }
+
+        if let Some(run) = &mut self.puzzle_run {
+            match run.record_lock(&self.field, &lines, clear) {
+                PuzzleResult::Continue => (),
+                PuzzleResult::Solved => self.banner = Some(("SOLVED", now)),
+                PuzzleResult::Failed => {
+                    self.retry_puzzle("TRY AGAIN", now);
+                    return None;
+                }
+            }
+        }
 
-        let lines = self.field.find_lines();
         if lines.is_empty() {
             // Replace the stopped blocks with new ones
             self.control = Control::WaitForClear(vec![], now);
         } else {
-            self.cleared_lines += lines.len() as i32;
             self.control = Control::WaitForClear(lines, now + Duration::from_millis(500));
         }
         None
     }
 
+    /// The shape of the next piece to play, or none once a puzzle's queue has run out
+    fn next_shape(&mut self) -> Option<Shape> {
+        if let Some(drill) = &self.finesse_drill {
+            return Some(drill.target().shape());
+        }
+        if let Some(run) = &mut self.puzzle_run {
+            run.deal()?;
+        }
+        Some(self.random_bag.take_next())
+    }
+
+    /// Puts a puzzle back to its starting field and queue after a failed attempt
+    fn retry_puzzle(&mut self, banner: &'static str, now: GameTime) {
+        if let Some(run) = &mut self.puzzle_run {
+            run.retry();
+            self.field = run.puzzle().field.clone();
+            self.random_bag = RandomBag::with_queue(self.rng.next_u64(), &run.puzzle().queue);
+        }
+        self.hold_piece = None;
+        self.can_hold = true;
+        self.banner = Some((banner, now));
+        self.control = Control::WaitForClear(vec![], now);
+    }
+
+    /// Puts the game back to before the last lock, with the piece that locked in play again
+    fn undo(&mut self, now: GameTime) -> Option<()> {
+        match self.control {
+            Control::Blocks(_) | Control::WaitForClear(..) => (),
+            _ => return None,
+        }
+        let snapshot = self.undo_stack.pop()?;
+        self.field = snapshot.field;
+        self.hold_piece = snapshot.hold_piece;
+        self.can_hold = snapshot.can_hold;
+        self.random_bag = snapshot.random_bag;
+        self.rng = snapshot.rng;
+        self.stats = snapshot.stats;
+        self.perfect_clear_practice = snapshot.perfect_clear_practice;
+        self.setup_practice = snapshot.setup_practice;
+        self.puzzle_run = snapshot.puzzle_run;
+        self.used_undo = true;
+        self.banner = None;
+        self.spawn(now, snapshot.shape);
+        None
+    }
+
     fn level(&self) -> i32 {
         const LINES_PER_LEVEL: i32 = 10;
-        self.cleared_lines / LINES_PER_LEVEL + 1
+        let start_level = match self.mode {
+            GameMode::Marathon { start_level } => start_level,
+            _ => 1,
+        };
+        start_level + self.stats.lines / LINES_PER_LEVEL
+    }
+
+    fn spawn(&mut self, now: GameTime, shape: Shape) {
+        let mut blocks = self.make_controlled_blocks(now, shape);
+        if self.mode.is_endless() {
+            // Shift the stack down out of the way rather than ending the game
+            while blocks.is_none() && !self.field.occupied_rows().is_empty() {
+                self.field.remove_lines(&[0]);
+                blocks = self.make_controlled_blocks(now, shape);
+            }
+        }
+        self.control = match blocks {
+            Some(t) => Control::Blocks(t),
+            None => Control::Lost(LossReason::BlockOut),
+        };
+        self.piece_inputs = match &self.control {
+            Control::Blocks(b) => Some(PieceInputs::new(b.tetromino)),
+            _ => None,
+        };
+        self.update_hint();
+    }
+
+    /// Searches for the best placement once per piece, rather than every frame
+    fn update_hint(&mut self) {
+        self.hint = match &self.finesse_drill {
+            Some(drill) => Some(drill.target()),
+            None => self.hinter.as_ref().and_then(|hinter| hinter.hint(self)),
+        };
     }
 
     fn make_controlled_blocks(&mut self, now: GameTime, shape: Shape) -> Option<ControlledBlocks> {
-        let new_tetromino = Tetromino::try_new(start_pos(), shape, &self.field)?;
-        Some(ControlledBlocks::new(
-            now,
-            new_tetromino,
-            level_drop_period(self.level()),
-        ))
+        let new_tetromino = Tetromino::try_new(self.field.spawn_pos(), shape, &self.field)?;
+        let drop_period = match self.mode {
+            GameMode::Zen { .. } => ZEN_DROP_PERIOD,
+            _ => level_drop_period(self.level()),
+        };
+        Some(ControlledBlocks::new(now, new_tetromino, drop_period))
+    }
+
+    fn has_gravity(&self) -> bool {
+        match self.mode {
+            GameMode::Zen { gravity } => gravity,
+            _ => true,
+        }
+    }
+}
+
+fn new_field(mode: GameMode, settings: &GameSettings, puzzle_run: &Option<PuzzleRun>) -> Field {
+    let field = match (mode, puzzle_run) {
+        // Setups are laid out for the standard board
+        (GameMode::Setup { .. }, _) => Field::new(),
+        (_, Some(run)) => run.puzzle().field.clone(),
+        _ => settings.board.new_field(),
+    };
+    // Only a hidden stack needs to know when each block locked
+    match mode.stack_visibility() {
+        Some(_) => field.with_lock_times(),
+        None => field,
+    }
+}
+
+/// Zen games count from nothing until totals from earlier games are given with `set_zen_totals`
+fn new_zen_totals(mode: GameMode) -> Option<ZenTotals> {
+    match mode {
+        GameMode::Zen { .. } => Some(ZenTotals::new()),
+        _ => None,
     }
 }
 
-fn start_pos() -> Pos {
-    p(3, Field::PLAYING_BOUNDARY_HEIGHT - 2)
+fn new_hinter(mode: GameMode, settings: &GameSettings) -> Option<AiPlayer> {
+    if settings.hints_for(mode) {
+        Some(AiPlayer::new(Difficulty::Expert))
+    } else {
+        None
+    }
 }
 
 fn level_drop_period(level: i32) -> Duration {
     let time_seconds = (0.8 - ((level - 1) as f32 * 0.007)).powi(level - 1);
     Duration::from_millis((time_seconds * 1000.0) as u64)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block_render::{BlockRenderInstructions, DrawBlockType};
+    use crate::field::{CheckableField, FieldBlock};
+    use crate::position::{p, Pos};
+
+    #[test]
+    fn finesse_faults() {
+        use crate::position::ShiftDir;
+        let mut settings = GameSettings::new();
+        settings.start_countdown = Duration::from_millis(0);
+        let wasted = vec![
+            Trigger::Shift(ShiftDir::Right),
+            Trigger::Shift(ShiftDir::Left),
+            Trigger::HardDrop,
+        ];
+
+        let (mut game, _) = GameState::new(GameMode::Survival, 3, &settings);
+        let now = game.start_time;
+        game.update_with(vec![], now);
+        game.update_with(vec![Trigger::HardDrop], now);
+        assert_eq!(0, game.stats.finesse_faults);
+        game.update_with(vec![], now);
+        game.update_with(wasted.clone(), now);
+        assert_eq!(1, game.stats.finesse_faults);
+
+        settings.strict_finesse = true;
+        let (mut game, _) = GameState::new(GameMode::Survival, 3, &settings);
+        game.update_with(vec![], now);
+        match game.update_with(wasted, now) {
+            GameCondition::Lost(LossReason::FinesseFault) => (),
+            _ => panic!("Strict finesse didn't end the game"),
+        }
+    }
+
+    #[test]
+    fn finesse_drill() {
+        let mut settings = GameSettings::new();
+        settings.start_countdown = Duration::from_millis(0);
+        let (mut game, _) = GameState::new(GameMode::FinesseTrainer, 2, &settings);
+        let now = game.start_time;
+        game.update_with(vec![], now);
+        let target = game.finesse_drill.as_ref().unwrap().target();
+        assert_eq!(
+            Some(target.shape()),
+            game.current_piece().map(|t| t.shape())
+        );
+        assert_eq!(Some(target), game.hint);
+        assert!(game.previews().is_empty());
+
+        game.update_with(vec![Trigger::HoldPiece, Trigger::HardDrop], now);
+        assert!(game.hold_piece.is_none());
+        assert!(game.field.occupied_rows().is_empty());
+        assert_eq!(1, game.finesse_drill.as_ref().unwrap().attempts());
+        assert!(game.current_piece().is_none());
+
+        let next = game.finesse_drill.as_ref().unwrap().target();
+        game.update_with(vec![], now + DRILL_FEEDBACK_DURATION);
+        assert_eq!(Some(next.shape()), game.current_piece().map(|t| t.shape()));
+    }
+
+    #[test]
+    fn setup_practice() {
+        let mut settings = GameSettings::new();
+        settings.start_countdown = Duration::from_millis(0);
+        let (mut game, _) = GameState::new(GameMode::Setup { setup: 0 }, 6, &settings);
+        let pieces = SETUPS[0].pieces();
+        let now = game.start_time;
+        game.update_with(vec![], now);
+        assert_eq!(Some(pieces[0]), game.current_piece().map(|t| t.shape()));
+        assert_eq!(pieces[1..].to_vec(), game.previews());
+
+        // Straight down from spawn is outside the target for the first piece
+        game.update_with(vec![Trigger::HardDrop], now);
+        assert!(game.field.occupied_rows().is_empty());
+        assert_eq!(1, game.setup_practice.as_ref().unwrap().attempts());
+        let targets = game
+            .render_info()
+            .playing_field
+            .blocks()
+            .filter(|info| match info.block_type {
+                DrawBlockType::Target(_) => true,
+                _ => false,
+            })
+            .count();
+        assert_eq!(SETUPS[0].queue.len() * 4, targets);
+        game.update_with(vec![], now);
+        assert_eq!(Some(pieces[0]), game.current_piece().map(|t| t.shape()));
+    }
+
+    #[test]
+    fn undo() {
+        let mut settings = GameSettings::new();
+        settings.start_countdown = Duration::from_millis(0);
+        let (mut game, _) = GameState::new(GameMode::PerfectClear, 8, &settings);
+        let now = game.start_time;
+        game.update_with(vec![], now);
+        let first = game.current_piece();
+        let previews = game.previews();
+        game.update_with(vec![Trigger::HardDrop], now);
+        game.update_with(vec![], now);
+        let second = game.current_piece();
+        game.update_with(vec![Trigger::HoldPiece], now);
+        game.update_with(vec![Trigger::HardDrop], now);
+        game.update_with(vec![], now);
+        assert_eq!(2, game.stats.pieces);
+        assert!(game.is_record_eligible());
+
+        game.update_with(vec![Trigger::Undo], now);
+        assert_eq!(second.map(|t| t.shape()), game.hold_piece);
+        assert!(!game.can_hold);
+        game.update_with(vec![Trigger::Undo], now);
+        assert_eq!(first, game.current_piece());
+        assert_eq!(previews, game.previews());
+        assert!(game.field.occupied_rows().is_empty());
+        assert_eq!(0, game.stats.pieces);
+        assert!(!game.is_record_eligible());
+
+        // The same pieces come again
+        game.update_with(vec![Trigger::HardDrop], now);
+        game.update_with(vec![], now);
+        assert_eq!(second, game.current_piece());
+
+        let (mut game, _) = GameState::new(GameMode::Survival, 8, &settings);
+        let now = game.start_time;
+        game.update_with(vec![], now);
+        game.update_with(vec![Trigger::HardDrop], now);
+        game.update_with(vec![Trigger::Undo], now);
+        assert_eq!(1, game.stats.pieces);
+        assert!(game.is_record_eligible());
+    }
+
+    #[test]
+    fn puzzle() {
+        use crate::position::{RotateDir, ShiftDir};
+        use crate::puzzle::PuzzleFile;
+        let mut settings = GameSettings::new();
+        settings.start_countdown = Duration::from_millis(0);
+        let puzzle = PuzzleFile::index_of("dig-out").unwrap();
+        let (mut game, _) = GameState::new(GameMode::Puzzle { puzzle: puzzle }, 1, &settings);
+        let start = game.field.clone();
+        let now = game.start_time;
+        game.update_with(vec![], now);
+        assert_eq!(Some(Shape::J), game.current_piece().map(|t| t.shape()));
+        assert_eq!(vec![Shape::I], game.previews());
+
+        // Two pieces without a clear use up the limit
+        game.update_with(vec![Trigger::HoldPiece, Trigger::HardDrop], now);
+        assert_eq!(None, game.hold_piece);
+        game.update_with(vec![], now);
+        // Undo would keep a solve out of the high scores, which mark puzzles solved
+        game.update_with(vec![Trigger::Undo], now);
+        assert_eq!(1, game.stats.pieces);
+        game.update_with(vec![Trigger::HardDrop], now);
+        assert!(game.field == start);
+        assert_eq!(1, game.puzzle_run.as_ref().unwrap().retries());
+
+        game.update_with(vec![], now);
+        game.update_with(vec![Trigger::HardDrop], now);
+        game.update_with(vec![Trigger::Rotate(RotateDir::CW)], now);
+        let x = game.current_piece().unwrap().to_minos().positions()[0].x;
+        let mut inputs = vec![Trigger::Shift(ShiftDir::Left); (x - 1) as usize];
+        inputs.push(Trigger::HardDrop);
+        match game.update_with(inputs, now) {
+            GameCondition::Playing => (),
+            _ => panic!("Finished before the lines cleared"),
+        }
+        assert!(game.puzzle_run.as_ref().unwrap().is_solved());
+        match game.update_with(vec![], now + Duration::from_secs(1)) {
+            GameCondition::Won => (),
+            _ => panic!("Solving the puzzle didn't win"),
+        }
+        assert!(game.is_record_eligible());
+    }
+
+    #[test]
+    fn zen() {
+        let mut settings = GameSettings::new();
+        settings.start_countdown = Duration::from_millis(0);
+        let (mut game, _) = GameState::new(GameMode::Zen { gravity: false }, 4, &settings);
+        let now = game.start_time;
+        let rows = game.field.playing_boundary_height() + 4;
+        for y in 0..rows {
+            game.field.push_garbage_row(y % 2);
+        }
+        game.update_with(vec![], now);
+        let spawned = game.current_piece().expect("The stack wasn't shifted down");
+        assert!(game.field.occupied_rows().len() < rows as usize);
+
+        // Nothing falls or locks on its own
+        game.update_with(vec![], now + Duration::from_secs(60));
+        assert_eq!(Some(spawned), game.current_piece());
+        for _ in 0..30 {
+            game.update_with(vec![Trigger::SoftDown], now + Duration::from_secs(61));
+        }
+        game.update_with(vec![], now + Duration::from_secs(120));
+        assert_eq!(0, game.stats.pieces);
+        assert!(game.current_piece().is_some());
+
+        for _ in 0..30 {
+            game.update_with(vec![Trigger::HardDrop], now + Duration::from_secs(121));
+            game.update_with(vec![], now + Duration::from_secs(122));
+        }
+        assert_eq!(30, game.stats.pieces);
+        assert_eq!(30, game.zen_totals().unwrap().pieces);
+
+        let earlier = ZenTotals {
+            games: 2,
+            ..ZenTotals::new()
+        };
+        game.set_zen_totals(earlier);
+        assert_eq!(Some(earlier), game.earlier_zen_totals());
+        assert_eq!((3, 30), {
+            let totals = game.zen_totals().unwrap();
+            (totals.games, totals.pieces)
+        });
+    }
+
+    #[test]
+    fn hidden_stack() {
+        let mut settings = GameSettings::new();
+        settings.start_countdown = Duration::from_millis(0);
+        let stack = StackVisibility::Fading;
+        let (mut game, _) = GameState::new(GameMode::Hidden { stack: stack }, 2, &settings);
+        let now = game.start_time;
+        game.update_with(vec![], now);
+        game.update_with(vec![Trigger::HardDrop], now);
+        let locked: Vec<Pos> = game
+            .field
+            .occupied_rows()
+            .iter()
+            .flat_map(|y| (0..game.field.width()).map(move |x| p(x, *y)))
+            .filter(|pos| game.field.block(*pos) != FieldBlock::Empty)
+            .collect();
+        assert_eq!(4, locked.len());
+        let shown = |game: &GameState| {
+            game.render_info()
+                .playing_field
+                .blocks()
+                .filter(|info| locked.contains(&info.pos))
+                .map(|info| match info.block_type {
+                    DrawBlockType::Occupied(_) => 1.0,
+                    DrawBlockType::Faded(_, opacity) => opacity,
+                    _ => 0.0,
+                })
+                .sum::<f32>()
+        };
+        assert_eq!(4.0, shown(&game));
+        game.update_with(vec![], now + Duration::from_millis(3500));
+        assert_eq!(2.0, shown(&game));
+        game.update_with(vec![], now + Duration::from_millis(5000));
+        assert_eq!(0.0, shown(&game));
+
+        // The whole stack shows again once the game is over
+        game.control = Control::Lost(LossReason::BlockOut);
+        assert_eq!(4.0, shown(&game));
+    }
+
+    #[test]
+    fn ultra_top_out_at_time_up() {
+        let mut settings = GameSettings::new();
+        settings.start_countdown = Duration::from_millis(0);
+        let (mut game, _) = GameState::new(GameMode::Ultra { seconds: 60 }, 1, &settings);
+        let now = game.start_time;
+        game.update_with(vec![], now);
+        game.control = Control::Lost(LossReason::TopOut);
+        match game.update_with(vec![], now + Duration::from_secs(60)) {
+            GameCondition::Lost(LossReason::TopOut) => (),
+            _ => panic!("Topping out as time ran out didn't lose"),
+        }
+    }
+
+    #[test]
+    fn countdown_presses_not_counted() {
+        let settings = GameSettings::new();
+        let (mut game, _) = GameState::new(GameMode::Survival, 3, &settings);
+        // Play starts at "Go", after the countdown
+        let go = game.start_time;
+        let before_go = GameTime::from_millis(0);
+        game.update_from_keyboard(vec![(Trigger::HardDrop, true)], 1, before_go);
+        assert_eq!(0, game.stats.key_presses);
+        assert_eq!(0, game.stats.pieces);
+
+        game.update_from_keyboard(vec![], 0, go);
+        game.update_from_keyboard(vec![(Trigger::HardDrop, true)], 1, go);
+        assert_eq!(1, game.stats.key_presses);
+        assert_eq!(1, game.stats.pieces);
+    }
+
+    #[test]
+    fn hints() {
+        let mut settings = GameSettings::new();
+        settings.start_countdown = Duration::from_millis(0);
+        let (mut game, _) = GameState::new(GameMode::Survival, 3, &settings);
+        game.update_with(vec![], game.start_time);
+        assert!(game.current_piece().is_some());
+        assert!(game.hint.is_none());
+
+        assert!(game.is_record_eligible());
+
+        settings.toggle_hints(GameMode::Survival);
+        let (mut game, _) = GameState::new(GameMode::Survival, 3, &settings);
+        game.update_with(vec![], game.start_time);
+        let hint = game.hint.expect("No hint for the first piece");
+        assert_eq!(Some(hint), AiPlayer::new(Difficulty::Expert).hint(&game));
+        assert_eq!(game.current_piece().map(|t| t.shape()), Some(hint.shape()));
+        assert!(!game.is_record_eligible());
+
+        // Turning hints off doesn't make a saved game count again
+        settings.toggle_hints(GameMode::Survival);
+        let (loaded, _) = GameState::load(&game.save(), &settings).unwrap();
+        assert!(!loaded.is_record_eligible());
+    }
+
+    #[test]
+    fn save_load() {
+        let settings = GameSettings::new();
+        let (mut game, _) = GameState::new(GameMode::Survival, 5, &settings);
+        game.control = Control::WaitForClear(vec![], game.start_time);
+        game.update_with(vec![], game.start_time);
+        game.hold_piece = Some(Shape::S);
+        game.stats.lines = 12;
+
+        let text = game.save();
+        let (loaded, clock) = GameState::load(&text, &settings).unwrap();
+        assert_eq!(text, loaded.save());
+        assert!(clock.now() >= game.last_update_time);
+
+        assert!(
+            GameState::load(&text.replace("blocks-save 10", "blocks-save 9"), &settings).is_none()
+        );
+    }
+
+    #[test]
+    fn versus_with_strict_finesse() {
+        let mut settings = GameSettings::new();
+        settings.start_countdown = Duration::from_millis(0);
+        settings.strict_finesse = true;
+        let mode = GameMode::Versus {
+            difficulty: crate::ai::Difficulty::Hard,
+        };
+        let (mut game, clock) = GameState::new(mode, 3, &settings);
+        let now = clock.now();
+        for i in 0..100 {
+            match game.update_with(vec![], now + Duration::from_millis(i * 50)) {
+                GameCondition::Playing => (),
+                _ => panic!("The opponent's finesse ended the game"),
+            }
+        }
+        assert!(game.opponent.as_ref().unwrap().game.stats.pieces > 1);
+    }
+
+    #[test]
+    fn versus_garbage() {
+        let mut settings = GameSettings::new();
+        settings.start_countdown = Duration::from_millis(0);
+        let mode = GameMode::Versus {
+            difficulty: crate::ai::Difficulty::Easy,
+        };
+        let (mut game, clock) = GameState::new(mode, 3, &settings);
+        let now = clock.now();
+        game.update_with(vec![], now);
+
+        game.incoming_garbage = 3;
+        game.outgoing_garbage = 2;
+        game.update_with(vec![Trigger::HardDrop], now);
+        assert_eq!(Some(0), game.render_info().pending_garbage);
+        assert!(game.field.occupied_rows().len() > 3);
+        // The rows of one attack share a single hole
+        let holes: Vec<Vec<Coord>> = (0..3).map(|y| holes(&game.field, y)).collect();
+        assert_eq!(1, holes[0].len());
+        assert!(holes.iter().all(|h| *h == holes[0]));
+        let opponent = &game.opponent.as_ref().unwrap().game;
+        assert_eq!(2, opponent.incoming_garbage);
+
+        let text = game.save();
+        let (loaded, _) = GameState::load(&text, &settings).unwrap();
+        assert_eq!(text, loaded.save());
+    }
+
+    fn holes(field: &Field, y: Coord) -> Vec<Coord> {
+        (0..field.width())
+            .filter(|x| field.is_open(p(*x, y)))
+            .collect()
+    }
+}
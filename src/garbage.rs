@@ -0,0 +1,111 @@
+use crate::savegame::{SaveReader, SaveWriter};
+use crate::time::GameTime;
+use core::time::Duration;
+
+/// Timer for survival mode's rising garbage. Each time the timer fires, a row becomes pending, and
+/// pending rows rise into the field when the next piece locks without clearing any lines.
+pub struct RisingGarbage {
+    next_rise_time: GameTime,
+    num_rises: u32,
+    pending_rows: u32,
+}
+
+const INITIAL_RISE_PERIOD: Duration = Duration::from_millis(8000);
+const MIN_RISE_PERIOD: Duration = Duration::from_millis(1000);
+const RISE_PERIOD_DECAY: f32 = 0.95;
+
+impl RisingGarbage {
+    pub fn new(start_time: GameTime) -> Self {
+        RisingGarbage {
+            next_rise_time: start_time + INITIAL_RISE_PERIOD,
+            num_rises: 0,
+            pending_rows: 0,
+        }
+    }
+
+    pub fn update(&mut self, now: GameTime) {
+        while self.next_rise_time <= now {
+            self.pending_rows += 1;
+            self.num_rises += 1;
+            self.next_rise_time += rise_period(self.num_rises);
+        }
+    }
+
+    /// Cancels up to `lines` pending rows.
+    pub fn cancel(&mut self, lines: u32) {
+        self.pending_rows = self.pending_rows.saturating_sub(lines);
+    }
+
+    pub fn take_pending(&mut self) -> u32 {
+        core::mem::replace(&mut self.pending_rows, 0)
+    }
+
+    pub fn pending(&self) -> u32 {
+        self.pending_rows
+    }
+
+    pub fn save(&self, w: &mut SaveWriter) {
+        w.put_time(self.next_rise_time);
+        w.put(self.num_rises);
+        w.put(self.pending_rows);
+    }
+
+    pub fn load(r: &mut SaveReader) -> Option<RisingGarbage> {
+        Some(RisingGarbage {
+            next_rise_time: r.get_time()?,
+            num_rises: r.get()?,
+            pending_rows: r.get()?,
+        })
+    }
+}
+
+fn rise_period(num_rises: u32) -> Duration {
+    let millis = INITIAL_RISE_PERIOD.as_millis() as f32 * RISE_PERIOD_DECAY.powi(num_rises as i32);
+    Duration::from_millis(millis as u64).max(MIN_RISE_PERIOD)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time::GameClock;
+
+    #[test]
+    fn rises_accumulate() {
+        let start_time = GameClock::new().now();
+        let mut g = RisingGarbage::new(start_time);
+
+        g.update(start_time);
+        assert_eq!(0, g.pending());
+
+        g.update(start_time + INITIAL_RISE_PERIOD);
+        assert_eq!(1, g.pending());
+
+        g.update(start_time + INITIAL_RISE_PERIOD + rise_period(1));
+        assert_eq!(2, g.pending());
+    }
+
+    #[test]
+    fn cancel_and_take() {
+        let start_time = GameClock::new().now();
+        let mut g = RisingGarbage::new(start_time);
+        g.update(start_time + INITIAL_RISE_PERIOD * 3);
+        let pending = g.pending();
+        assert!(pending >= 3);
+
+        g.cancel(2);
+        assert_eq!(pending - 2, g.pending());
+        g.cancel(100);
+        assert_eq!(0, g.pending());
+
+        g.update(start_time + INITIAL_RISE_PERIOD * 10);
+        let pending = g.pending();
+        assert_eq!(pending, g.take_pending());
+        assert_eq!(0, g.pending());
+    }
+
+    #[test]
+    fn period_speeds_up() {
+        assert!(rise_period(10) < rise_period(1));
+        assert_eq!(MIN_RISE_PERIOD, rise_period(1000));
+    }
+}
@@ -0,0 +1,347 @@
+use crate::gamestate::Summary;
+use crate::mode::GameMode;
+use crate::replay;
+use crate::savegame::{SaveError, Storage};
+use crate::stats::Statistics;
+use crate::time::{format_date, format_duration};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::time::Duration;
+
+/// The best results for each mode and configuration, kept between sessions
+pub struct HighScores {
+    tables: Vec<Table>,
+    /// Replays of entries pushed out of the tables, to delete once the tables are saved without
+    /// them
+    dropped_replays: Vec<String>,
+}
+
+struct Table {
+    mode: GameMode,
+    entries: Vec<Entry>,
+}
+
+pub struct Entry {
+    pub summary: Summary,
+    /// When the game was played, in seconds since the Unix epoch
+    pub date: u64,
+    /// The save profile the game's replay is stored under
+    pub replay: String,
+}
+
+pub const ENTRIES_PER_MODE: usize = 5;
+
+const FORMAT_VERSION: u32 = 1;
+const SAVE_PROFILE: &str = "highscores";
+
+impl HighScores {
+    pub fn new() -> Self {
+        HighScores {
+            tables: vec![],
+            dropped_replays: vec![],
+        }
+    }
+
+    /// The saved high scores, or none if they are missing or unreadable
+    pub fn load(storage: &dyn Storage) -> Self {
+        storage
+            .retrieve(SAVE_PROFILE)
+            .and_then(|text| HighScores::decode(&text))
+            .unwrap_or_else(HighScores::new)
+    }
+
+    pub fn save(&mut self, storage: &dyn Storage) -> Result<(), SaveError> {
+        storage.store(SAVE_PROFILE, &self.encode())?;
+        for replay in self.dropped_replays.drain(..) {
+            // A replay left behind only takes up space
+            let _ = storage.remove(&replay);
+        }
+        Ok(())
+    }
+
+    /// Adds a finished game if it is one of the best for its mode, returning its rank from 0.
+    /// `completed` is false when the game ended before reaching the mode's goal.
+    pub fn record(&mut self, summary: Summary, completed: bool, date: u64) -> Option<usize> {
+        if !is_eligible(summary.mode, completed) {
+            return None;
+        }
+        self.insert(Entry {
+            summary: summary,
+            date: date,
+            replay: replay::profile_name(date, summary.seed),
+        })
+    }
+
+    fn insert(&mut self, entry: Entry) -> Option<usize> {
+        let mode = entry.summary.mode;
+        let table = match self.tables.iter().position(|t| t.mode == mode) {
+            Some(i) => &mut self.tables[i],
+            None => {
+                self.tables.push(Table {
+                    mode: mode,
+                    entries: vec![],
+                });
+                self.tables.last_mut().unwrap()
+            }
+        };
+
+        let rank = table
+            .entries
+            .iter()
+            .take_while(|e| !ranks_above(&entry.summary, &e.summary))
+            .count();
+        if rank >= ENTRIES_PER_MODE {
+            return None;
+        }
+        table.entries.insert(rank, entry);
+        if table.entries.len() > ENTRIES_PER_MODE {
+            let dropped = table.entries.pop().unwrap();
+            self.dropped_replays.push(dropped.replay);
+        }
+        Some(rank)
+    }
+
+    /// Every mode configuration with at least one entry
+    pub fn modes(&self) -> Vec<GameMode> {
+        self.tables.iter().map(|t| t.mode).collect()
+    }
+
+    pub fn top(&self, mode: GameMode) -> &[Entry] {
+        match self.tables.iter().find(|t| t.mode == mode) {
+            Some(t) => &t.entries,
+            None => &[],
+        }
+    }
+
+    /// A header line, then one line per entry of
+    /// `<mode> <seed> <date> <milliseconds> <score> <lines> <pieces> <perfect clears> <replay>`
+    fn encode(&self) -> String {
+        let mut result = format!("blocks-highscores {}\n", FORMAT_VERSION);
+        for entry in self.tables.iter().flat_map(|t| t.entries.iter()) {
+            let summary = &entry.summary;
+            result += &format!(
+                "{} {} {} {} {} {} {} {} {}\n",
+                summary.mode.code(),
+                summary.seed,
+                entry.date,
+                summary.elapsed.as_millis(),
+                summary.stats.score,
+                summary.stats.lines,
+                summary.stats.pieces,
+                summary.stats.perfect_clears,
+                entry.replay
+            );
+        }
+        result
+    }
+
+    fn decode(text: &str) -> Option<HighScores> {
+        let mut lines = text.lines();
+        if lines.next()? != format!("blocks-highscores {}", FORMAT_VERSION) {
+            return None;
+        }
+
+        // Entries for modes that no longer exist, like a removed puzzle, are dropped on their own
+        // rather than losing the whole table
+        let mut result = HighScores::new();
+        for entry in lines.filter_map(decode_entry) {
+            result.insert(entry);
+        }
+        Some(result)
+    }
+}
+
+fn decode_entry(line: &str) -> Option<Entry> {
+    let fields: Vec<&str> = line.split(' ').collect();
+    if fields.len() != 9 {
+        return None;
+    }
+    Some(Entry {
+        summary: Summary {
+            mode: GameMode::from_code(fields[0])?,
+            seed: fields[1].parse().ok()?,
+            stats: Statistics {
+                score: fields[4].parse().ok()?,
+                lines: fields[5].parse().ok()?,
+                pieces: fields[6].parse().ok()?,
+                perfect_clears: fields[7].parse().ok()?,
+                ..Statistics::default()
+            },
+            elapsed: Duration::from_millis(fields[3].parse().ok()?),
+        },
+        date: fields[2].parse().ok()?,
+        replay: String::from(fields[8]),
+    })
+}
+
+/// The value an entry is ranked by, followed by when it was played
+pub fn format_entry(entry: &Entry) -> String {
+    let summary = &entry.summary;
+    let result = match summary.mode {
+        GameMode::Marathon { .. } | GameMode::Ultra { .. } | GameMode::Hidden { .. } => {
+            format!("{}", summary.stats.score)
+        }
+        GameMode::Sprint { .. }
+        | GameMode::Survival
+        | GameMode::Versus { .. }
+        | GameMode::Puzzle { .. } => format_duration(summary.elapsed),
+        GameMode::PerfectClear => format!("{} PCs", summary.stats.perfect_clears),
+        GameMode::FinesseTrainer | GameMode::Setup { .. } | GameMode::Zen { .. } => {
+            format!("{} pieces", summary.stats.pieces)
+        }
+    };
+    format!("{}   {}", result, format_date(entry.date))
+}
+
+fn is_eligible(mode: GameMode, completed: bool) -> bool {
+    match mode {
+        // A puzzle's table doubles as the record of it being solved
+        GameMode::Sprint { .. } | GameMode::Versus { .. } | GameMode::Puzzle { .. } => completed,
+        // Drills have no score, and only end when the player leaves
+        GameMode::FinesseTrainer | GameMode::Setup { .. } | GameMode::Zen { .. } => false,
+        _ => true,
+    }
+}
+
+fn ranks_above(a: &Summary, b: &Summary) -> bool {
+    match a.mode {
+        GameMode::Marathon { .. } | GameMode::Ultra { .. } | GameMode::Hidden { .. } => {
+            a.stats.score > b.stats.score
+        }
+        GameMode::Sprint { .. } | GameMode::Versus { .. } | GameMode::Puzzle { .. } => {
+            a.elapsed < b.elapsed
+        }
+        GameMode::Survival => a.elapsed > b.elapsed,
+        GameMode::PerfectClear => a.stats.perfect_clears > b.stats.perfect_clears,
+        GameMode::FinesseTrainer | GameMode::Setup { .. } | GameMode::Zen { .. } => {
+            a.stats.pieces > b.stats.pieces
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::RefCell;
+
+    /// Keeps profiles in memory
+    struct Profiles(RefCell<Vec<(String, String)>>);
+
+    impl Storage for Profiles {
+        fn store(&self, profile: &str, text: &str) -> Result<(), SaveError> {
+            self.remove(profile)?;
+            let mut profiles = self.0.borrow_mut();
+            profiles.push((String::from(profile), String::from(text)));
+            Ok(())
+        }
+
+        fn retrieve(&self, profile: &str) -> Option<String> {
+            let profiles = self.0.borrow();
+            let found = profiles.iter().find(|(p, _)| p == profile)?;
+            Some(found.1.clone())
+        }
+
+        fn remove(&self, profile: &str) -> Result<(), SaveError> {
+            self.0.borrow_mut().retain(|(p, _)| p != profile);
+            Ok(())
+        }
+    }
+
+    fn summary(mode: GameMode, score: u32, millis: u64) -> Summary {
+        Summary {
+            mode: mode,
+            seed: 0,
+            stats: Statistics {
+                score: score,
+                ..Statistics::default()
+            },
+            elapsed: Duration::from_millis(millis),
+        }
+    }
+
+    #[test]
+    fn ranks_by_score() {
+        let marathon = GameMode::Marathon { start_level: 1 };
+        let mut h = HighScores::new();
+        assert_eq!(Some(0), h.record(summary(marathon, 100, 0), false, 0));
+        assert_eq!(Some(0), h.record(summary(marathon, 300, 0), false, 0));
+        assert_eq!(Some(1), h.record(summary(marathon, 200, 0), false, 0));
+
+        let scores: Vec<u32> = h
+            .top(marathon)
+            .iter()
+            .map(|e| e.summary.stats.score)
+            .collect();
+        assert_eq!(vec![300, 200, 100], scores);
+    }
+
+    #[test]
+    fn ranks_sprint_by_time() {
+        let sprint = GameMode::Sprint { lines: 40 };
+        let mut h = HighScores::new();
+        assert_eq!(None, h.record(summary(sprint, 0, 1000), false, 0));
+        assert_eq!(Some(0), h.record(summary(sprint, 0, 5000), true, 0));
+        assert_eq!(Some(0), h.record(summary(sprint, 0, 4000), true, 0));
+        assert!(h.top(GameMode::Sprint { lines: 20 }).is_empty());
+    }
+
+    #[test]
+    fn keeps_top_entries() {
+        let ultra = GameMode::Ultra { seconds: 120 };
+        let mut h = HighScores::new();
+        for score in 0..(ENTRIES_PER_MODE as u32 + 3) {
+            h.record(summary(ultra, score * 10, 0), true, 0);
+        }
+        assert_eq!(None, h.record(summary(ultra, 5, 0), true, 0));
+        assert_eq!(ENTRIES_PER_MODE, h.top(ultra).len());
+        assert_eq!(vec![ultra], h.modes());
+    }
+
+    #[test]
+    fn removes_replays_pushed_out() {
+        let ultra = GameMode::Ultra { seconds: 120 };
+        let storage = Profiles(RefCell::new(vec![]));
+        let mut h = HighScores::new();
+        for date in 0..(ENTRIES_PER_MODE as u64 + 1) {
+            let rank = h.record(summary(ultra, date as u32 * 10, 0), true, date);
+            let replay = &h.top(ultra)[rank.unwrap()].replay;
+            storage.store(replay, "replay").unwrap();
+            h.save(&storage).unwrap();
+        }
+
+        let lowest = replay::profile_name(0, 0);
+        assert_eq!(None, storage.retrieve(&lowest));
+        let kept = h.top(ultra);
+        assert!(kept.iter().all(|e| storage.retrieve(&e.replay).is_some()));
+        assert_eq!(h.encode(), HighScores::load(&storage).encode());
+    }
+
+    #[test]
+    fn encode_decode() {
+        let sprint = GameMode::Sprint { lines: 20 };
+        let mut h = HighScores::new();
+        h.record(summary(sprint, 0, 61_250), true, 1_700_000_000);
+        h.record(summary(GameMode::Survival, 0, 5000), false, 1_700_000_100);
+
+        let text = h.encode();
+        assert_eq!(
+            "blocks-highscores 1\n\
+             sprint:20 0 1700000000 61250 0 0 0 0 replay-1700000000-0\n\
+             survival 0 1700000100 5000 0 0 0 0 replay-1700000100-0\n",
+            text
+        );
+
+        let decoded = HighScores::decode(&text).unwrap();
+        assert_eq!(vec![sprint, GameMode::Survival], decoded.modes());
+        let entry = &decoded.top(sprint)[0];
+        assert_eq!(Duration::from_millis(61_250), entry.summary.elapsed);
+        assert_eq!("replay-1700000000-0", entry.replay);
+        assert_eq!("1:01.25   2023-11-14", format_entry(entry));
+
+        assert!(HighScores::decode("blocks-highscores 2\n").is_none());
+
+        let with_unknown = text.replace("sprint:20", "puzzle:removed");
+        let decoded = HighScores::decode(&with_unknown).unwrap();
+        assert_eq!(vec![GameMode::Survival], decoded.modes());
+    }
+}
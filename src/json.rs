@@ -0,0 +1,333 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::Chars;
+
+/// A JSON value, enough to talk to bots. Objects keep their fields in order.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+/// How deeply arrays and objects may nest, so a hostile bot can't overflow the stack
+const MAX_DEPTH: usize = 64;
+
+struct Parser<'a> {
+    chars: core::iter::Peekable<Chars<'a>>,
+    /// Arrays and objects open around the current value
+    depth: usize,
+}
+
+impl Json {
+    /// Parses a whole document, which may be surrounded by whitespace
+    pub fn parse(text: &str) -> Option<Json> {
+        let mut parser = Parser {
+            chars: text.chars().peekable(),
+            depth: 0,
+        };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        match parser.chars.next() {
+            Some(_) => None,
+            None => Some(value),
+        }
+    }
+
+    pub fn object(fields: Vec<(&str, Json)>) -> Json {
+        Json::Object(
+            fields
+                .into_iter()
+                .map(|(key, value)| (String::from(key), value))
+                .collect(),
+        )
+    }
+
+    pub fn string(text: &str) -> Json {
+        Json::String(String::from(text))
+    }
+
+    /// An object's field
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    /// The value of a number without a fractional part
+    pub fn as_i32(&self) -> Option<i32> {
+        match *self {
+            Json::Number(n) if n as i32 as f64 == n => Some(n as i32),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(n) => write!(f, "{}", n),
+            Json::String(text) => write_string(f, text),
+            Json::Array(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, text: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in text.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+impl<'a> Parser<'a> {
+    fn value(&mut self) -> Option<Json> {
+        self.skip_whitespace();
+        match *self.chars.peek()? {
+            'n' => self.keyword("null", Json::Null),
+            't' => self.keyword("true", Json::Bool(true)),
+            'f' => self.keyword("false", Json::Bool(false)),
+            '"' => self.string().map(Json::String),
+            '[' => {
+                self.chars.next();
+                self.enter()?;
+                let mut values = vec![];
+                if !self.next_is(']') {
+                    loop {
+                        values.push(self.value()?);
+                        if self.next_is(']') {
+                            break;
+                        }
+                        self.expect(',')?;
+                    }
+                }
+                self.depth -= 1;
+                Some(Json::Array(values))
+            }
+            '{' => {
+                self.chars.next();
+                self.enter()?;
+                let mut fields = vec![];
+                if !self.next_is('}') {
+                    loop {
+                        self.skip_whitespace();
+                        let key = self.string()?;
+                        self.expect(':')?;
+                        fields.push((key, self.value()?));
+                        if self.next_is('}') {
+                            break;
+                        }
+                        self.expect(',')?;
+                    }
+                }
+                self.depth -= 1;
+                Some(Json::Object(fields))
+            }
+            _ => self.number(),
+        }
+    }
+
+    /// Goes into an array or object, unless they are already nested too deeply
+    fn enter(&mut self) -> Option<()> {
+        if self.depth == MAX_DEPTH {
+            return None;
+        }
+        self.depth += 1;
+        Some(())
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(' ') | Some('\t') | Some('\n') | Some('\r') = self.chars.peek() {
+            self.chars.next();
+        }
+    }
+
+    /// Consumes `c` if it comes next, after any whitespace
+    fn next_is(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        if self.chars.peek() == Some(&c) {
+            self.chars.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Option<()> {
+        if self.next_is(c) {
+            Some(())
+        } else {
+            None
+        }
+    }
+
+    fn keyword(&mut self, word: &str, value: Json) -> Option<Json> {
+        for expected in word.chars() {
+            if self.chars.next()? != expected {
+                return None;
+            }
+        }
+        Some(value)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        if self.chars.next()? != '"' {
+            return None;
+        }
+        let mut text = String::new();
+        loop {
+            match self.chars.next()? {
+                '"' => return Some(text),
+                '\\' => match self.chars.next()? {
+                    'n' => text.push('\n'),
+                    'r' => text.push('\r'),
+                    't' => text.push('\t'),
+                    'b' => text.push('\u{8}'),
+                    'f' => text.push('\u{c}'),
+                    'u' => {
+                        let mut code = self.hex()?;
+                        if (0xd800..0xdc00).contains(&code) {
+                            // A surrogate pair, for characters outside the basic plane
+                            self.keyword("\\u", Json::Null)?;
+                            let low = self.hex()?;
+                            code = 0x10000 + ((code - 0xd800) << 10) + (low.checked_sub(0xdc00)?);
+                        }
+                        text.push(core::char::from_u32(code)?);
+                    }
+                    c => text.push(c),
+                },
+                c => text.push(c),
+            }
+        }
+    }
+
+    fn hex(&mut self) -> Option<u32> {
+        let mut code = 0;
+        for _ in 0..4 {
+            code = code * 16 + self.chars.next()?.to_digit(16)?;
+        }
+        Some(code)
+    }
+
+    fn number(&mut self) -> Option<Json> {
+        let mut text = String::new();
+        while let Some(c) = self.chars.peek() {
+            match c {
+                '0'..='9' | '-' | '+' | '.' | 'e' | 'E' => text.push(*c),
+                _ => break,
+            }
+            self.chars.next();
+        }
+        // Rust accepts a leading plus, but JSON doesn't
+        if text.starts_with('+') {
+            return None;
+        }
+        text.parse().ok().map(Json::Number)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let text = r#"{"type":"start","hold":null,"queue":["T","I"],"combo":0,"ok":true,"x":-1.5}"#;
+        let json = Json::parse(text).unwrap();
+        assert_eq!(Some("start"), json.get("type").and_then(|t| t.as_str()));
+        assert_eq!(Some(&Json::Null), json.get("hold"));
+        assert_eq!(
+            Some(2),
+            json.get("queue")
+                .and_then(|q| q.as_array())
+                .map(|q| q.len())
+        );
+        assert_eq!(Some(0), json.get("combo").and_then(|c| c.as_i32()));
+        assert_eq!(None, json.get("x").and_then(|x| x.as_i32()));
+        assert_eq!(text, format!("{}", json));
+    }
+
+    #[test]
+    fn strings() {
+        let json = Json::parse(r#" [ "a\"b\\c\n", "é\ud83d\ude00" ] "#).unwrap();
+        assert_eq!(
+            Json::Array(vec![Json::string("a\"b\\c\n"), Json::string("é😀")]),
+            json
+        );
+        assert_eq!(r#"["a\"b\\c\n","é😀"]"#, format!("{}", json));
+    }
+
+    #[test]
+    fn invalid() {
+        assert_eq!(None, Json::parse(""));
+        assert_eq!(None, Json::parse("[1,]"));
+        assert_eq!(None, Json::parse(r#"{"a" 1}"#));
+        assert_eq!(None, Json::parse("nul"));
+        assert_eq!(None, Json::parse("1 2"));
+        assert_eq!(None, Json::parse("+1"));
+        assert_eq!(None, Json::parse("[+1]"));
+    }
+
+    #[test]
+    fn nesting() {
+        let deepest = format!("{}{}", "[".repeat(MAX_DEPTH), "]".repeat(MAX_DEPTH));
+        assert!(Json::parse(&deepest).is_some());
+        let nested = format!(
+            "{}1{}",
+            "[{\"a\":".repeat(MAX_DEPTH),
+            "}]".repeat(MAX_DEPTH)
+        );
+        assert_eq!(None, Json::parse(&nested));
+        assert_eq!(None, Json::parse(&"[".repeat(100_000)));
+    }
+}
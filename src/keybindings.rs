@@ -1,89 +1,38 @@
-use crate::input::{InputEvent, KeyStateMachine, RepeatingKeyStateMachine, SingleKeyStateMachine};
 use crate::position::{RotateDir, ShiftDir};
-use crate::time::GameTime;
-use alloc::boxed::Box;
-use alloc::vec::Vec;
-use core::ops::Index;
-use core::time::Duration;
-use quicksilver::input::{ButtonState, Key};
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq, Debug)]
 pub enum Trigger {
     Shift(ShiftDir),
     SoftDown,
     Rotate(RotateDir),
     HardDrop,
     HoldPiece,
-}
-
-pub struct KeyboardStates {
-    bindings: Vec<Binding>,
-}
-
-struct Binding {
-    key: Key,
-    trigger: Trigger,
-    state: Box<dyn KeyStateMachine>,
-}
-
-impl KeyboardStates {
-    pub fn new() -> KeyboardStates {
-        KeyboardStates {
-            bindings: vec![
-                bind_shift(Key::Left, Trigger::Shift(ShiftDir::Left)),
-                bind_shift(Key::Right, Trigger::Shift(ShiftDir::Right)),
-                bind_drop(Key::Down, Trigger::SoftDown),
-                bind_single(Key::Z, Trigger::Rotate(RotateDir::CCW)),
-                bind_single(Key::X, Trigger::Rotate(RotateDir::CW)),
-                bind_single(Key::Space, Trigger::HardDrop),
-                bind_single(Key::Up, Trigger::HardDrop),
-                bind_single(Key::C, Trigger::HoldPiece),
-            ],
-        }
-    }
-
-    pub fn update<T>(&mut self, keyboard: &T, now: GameTime) -> Vec<Trigger>
-    where
-        T: Index<Key, Output = ButtonState>,
-    {
-        let mut result = vec![];
-        for binding in self.bindings.iter_mut() {
-            if let InputEvent::Fire = binding.state.update(keyboard[binding.key].is_down(), now) {
-                result.push(binding.trigger);
-            }
+    /// Takes back the last piece, in modes which allow it
+    Undo,
+}
+
+impl Trigger {
+    pub const ALL: [Trigger; 8] = [
+        Trigger::Shift(ShiftDir::Left),
+        Trigger::Shift(ShiftDir::Right),
+        Trigger::SoftDown,
+        Trigger::HardDrop,
+        Trigger::Rotate(RotateDir::CW),
+        Trigger::Rotate(RotateDir::CCW),
+        Trigger::HoldPiece,
+        Trigger::Undo,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Trigger::Shift(ShiftDir::Left) => "Move left",
+            Trigger::Shift(ShiftDir::Right) => "Move right",
+            Trigger::SoftDown => "Soft drop",
+            Trigger::HardDrop => "Hard drop",
+            Trigger::Rotate(RotateDir::CW) => "Rotate right",
+            Trigger::Rotate(RotateDir::CCW) => "Rotate left",
+            Trigger::HoldPiece => "Hold",
+            Trigger::Undo => "Undo",
         }
-        result
-    }
-}
-
-fn bind_shift(key: Key, trigger: Trigger) -> Binding {
-    bind(
-        key,
-        trigger,
-        Box::new(RepeatingKeyStateMachine::new(
-            Duration::from_millis(120),
-            Duration::from_millis(40),
-        )),
-    )
-}
-
-fn bind_drop(key: Key, trigger: Trigger) -> Binding {
-    let duration = Duration::from_millis(40);
-    bind(
-        key,
-        trigger,
-        Box::new(RepeatingKeyStateMachine::new(duration, duration)),
-    )
-}
-
-fn bind_single(key: Key, trigger: Trigger) -> Binding {
-    bind(key, trigger, Box::new(SingleKeyStateMachine::new()))
-}
-
-fn bind(key: Key, trigger: Trigger, ksm: Box<dyn KeyStateMachine>) -> Binding {
-    Binding {
-        key: key,
-        trigger: trigger,
-        state: ksm,
     }
 }
@@ -0,0 +1,146 @@
+use crate::input::{InputEvent, KeyStateMachine, RepeatingKeyStateMachine, SingleKeyStateMachine};
+use crate::keybindings::Trigger;
+use crate::position::{RotateDir, ShiftDir};
+use crate::settings::GameSettings;
+use crate::time::GameTime;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::ops::Index;
+use quicksilver::input::{ButtonState, Key};
+
+/// Which keys fire which triggers
+pub struct KeyBindings {
+    bindings: Vec<(Key, Trigger)>,
+}
+
+impl KeyBindings {
+    pub fn new() -> Self {
+        KeyBindings {
+            bindings: vec![
+                (Key::Left, Trigger::Shift(ShiftDir::Left)),
+                (Key::Right, Trigger::Shift(ShiftDir::Right)),
+                (Key::Down, Trigger::SoftDown),
+                (Key::Z, Trigger::Rotate(RotateDir::CCW)),
+                (Key::X, Trigger::Rotate(RotateDir::CW)),
+                (Key::Space, Trigger::HardDrop),
+                (Key::Up, Trigger::HardDrop),
+                (Key::C, Trigger::HoldPiece),
+                (Key::Back, Trigger::Undo),
+            ],
+        }
+    }
+
+    pub fn keys_for(&self, trigger: Trigger) -> Vec<Key> {
+        self.bindings
+            .iter()
+            .filter(|(_, t)| *t == trigger)
+            .map(|(key, _)| *key)
+            .collect()
+    }
+
+    /// Makes `key` the only key for `trigger`, taking it away from any other trigger.
+    pub fn rebind(&mut self, trigger: Trigger, key: Key) {
+        self.bindings.retain(|(k, t)| *k != key && *t != trigger);
+        self.bindings.push((key, trigger));
+    }
+}
+
+pub struct KeyboardStates {
+    bindings: Vec<Binding>,
+    key_presses: u32,
+}
+
+struct Binding {
+    key: Key,
+    trigger: Trigger,
+    state: Box<dyn KeyStateMachine>,
+    was_down: bool,
+}
+
+impl KeyboardStates {
+    pub fn new(settings: &GameSettings, bindings: &KeyBindings) -> KeyboardStates {
+        KeyboardStates {
+            bindings: bindings
+                .bindings
+                .iter()
+                .map(|(key, trigger)| match trigger {
+                    Trigger::Shift(_) => bind_shift(*key, *trigger, settings),
+                    Trigger::SoftDown => bind_drop(*key, *trigger, settings),
+                    _ => bind_single(*key, *trigger),
+                })
+                .collect(),
+            key_presses: 0,
+        }
+    }
+
+    /// Number of times a bound key has been pressed since the last call, not counting auto-repeat
+    pub fn take_key_presses(&mut self) -> u32 {
+        core::mem::replace(&mut self.key_presses, 0)
+    }
+
+    /// The triggers fired now, each with whether its key was just pressed rather than repeating
+    pub fn update<T>(&mut self, keyboard: &T, now: GameTime) -> Vec<(Trigger, bool)>
+    where
+        T: Index<Key, Output = ButtonState>,
+    {
+        let mut result = vec![];
+        for binding in self.bindings.iter_mut() {
+            let is_down = keyboard[binding.key].is_down();
+            let pressed = is_down && !binding.was_down;
+            if pressed {
+                self.key_presses += 1;
+            }
+            binding.was_down = is_down;
+
+            if let InputEvent::Fire = binding.state.update(is_down, now) {
+                result.push((binding.trigger, pressed));
+            }
+        }
+        result
+    }
+}
+
+fn bind_shift(key: Key, trigger: Trigger, settings: &GameSettings) -> Binding {
+    bind(
+        key,
+        trigger,
+        Box::new(RepeatingKeyStateMachine::new(settings.das, settings.arr)),
+    )
+}
+
+fn bind_drop(key: Key, trigger: Trigger, settings: &GameSettings) -> Binding {
+    let duration = settings.soft_drop_repeat;
+    bind(
+        key,
+        trigger,
+        Box::new(RepeatingKeyStateMachine::new(duration, duration)),
+    )
+}
+
+fn bind_single(key: Key, trigger: Trigger) -> Binding {
+    bind(key, trigger, Box::new(SingleKeyStateMachine::new()))
+}
+
+fn bind(key: Key, trigger: Trigger, ksm: Box<dyn KeyStateMachine>) -> Binding {
+    Binding {
+        key: key,
+        trigger: trigger,
+        state: ksm,
+        was_down: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rebind() {
+        let mut b = KeyBindings::new();
+        assert_eq!(vec![Key::Space, Key::Up], b.keys_for(Trigger::HardDrop));
+
+        b.rebind(Trigger::HardDrop, Key::C);
+        assert_eq!(vec![Key::C], b.keys_for(Trigger::HardDrop));
+        assert!(b.keys_for(Trigger::HoldPiece).is_empty());
+    }
+}
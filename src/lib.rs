@@ -0,0 +1,56 @@
+//! The game itself, without a window: rules, modes, saving and the players that can drive it.
+//! Shared by the game and the headless arena.
+#![no_std]
+
+#[cfg(not(target_arch = "wasm32"))]
+extern crate std;
+#[cfg(target_arch = "wasm32")]
+extern crate stdweb;
+
+#[cfg(test)]
+#[macro_use]
+extern crate double;
+
+#[cfg(test)]
+#[macro_use]
+extern crate hamcrest2;
+
+#[macro_use]
+extern crate num_derive;
+extern crate getrandom;
+extern crate num_traits;
+#[macro_use]
+extern crate alloc;
+
+pub mod ai;
+pub mod block_render;
+pub mod controlled;
+pub mod field;
+pub mod finesse;
+pub mod finesse_drill;
+pub mod fumen;
+pub mod gamestate;
+pub mod garbage;
+pub mod highscores;
+pub mod input;
+pub mod json;
+pub mod keybindings;
+pub mod lockdelay;
+pub mod mode;
+pub mod movegen;
+pub mod perfect_clear;
+pub mod position;
+pub mod puzzle;
+pub mod random_bag;
+pub mod replay;
+pub mod rng;
+pub mod savegame;
+pub mod settings;
+pub mod setups;
+pub mod shapes;
+pub mod stats;
+pub mod tbp;
+pub mod tetromino;
+pub mod time;
+pub mod visibility;
+pub mod zen;
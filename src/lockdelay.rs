@@ -1,4 +1,5 @@
 use crate::controlled::DropResult;
+use crate::savegame::{SaveReader, SaveWriter};
 use crate::time::GameTime;
 use core::time::Duration;
 
@@ -33,6 +34,26 @@ impl LockDelay {
         }
     }
 
+    pub fn save(&self, w: &mut SaveWriter) {
+        w.put_duration(self.accumulated_time);
+        match self.prev_lock_time {
+            Some(t) => w.put_time(t),
+            None => w.put('-'),
+        }
+        w.put(self.num_resets);
+    }
+
+    pub fn load(r: &mut SaveReader) -> Option<LockDelay> {
+        Some(LockDelay {
+            accumulated_time: r.get_duration()?,
+            prev_lock_time: match r.token()? {
+                "-" => None,
+                millis => Some(GameTime::from_millis(millis.parse().ok()?)),
+            },
+            num_resets: r.get()?,
+        })
+    }
+
     pub fn reset(&mut self) {
         if let Some(_) = self.prev_lock_time {
             if self.num_resets < ALLOWED_RESETS {
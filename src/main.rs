@@ -1,141 +1,595 @@
 #![no_std]
 
-#[cfg(test)]
-#[macro_use]
-extern crate double;
-
-#[cfg(test)]
-#[macro_use]
-extern crate hamcrest2;
+#[cfg(not(target_arch = "wasm32"))]
+extern crate std;
 
-extern crate quicksilver;
-#[macro_use]
-extern crate num_derive;
+extern crate blocks;
 extern crate futures;
-extern crate getrandom;
-extern crate num_traits;
+extern crate quicksilver;
 #[macro_use]
 extern crate alloc;
 
-mod controlled;
-mod field;
-mod gamestate;
-mod input;
-mod keybindings;
-mod lockdelay;
-mod position;
-mod random_bag;
+mod keyboard;
+mod menu;
 mod render;
 mod resources;
-mod shapes;
-mod tetromino;
-mod time;
+mod storage;
 
+use ai::{AiPlayer, Difficulty};
 use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use blocks::{
+    ai, block_render, field, fumen, gamestate, highscores, input, keybindings, mode, position,
+    replay, rng, savegame, settings, shapes, tbp, time, zen,
+};
+use core::time::Duration;
+use fumen::{EXPORT_PROFILE, MAX_EXPORT_PAGES};
 use futures::Async;
-use gamestate::{GameCondition, GameState};
+use gamestate::{GameCondition, GameState, LossReason, SoundEffect};
+use highscores::HighScores;
+use keyboard::{KeyBindings, KeyboardStates};
+use menu::{input_for_button, input_for_key, Menu, MenuAction, MenuInput};
+use mode::GameMode;
 use quicksilver::{
     geom::Vector,
-    input::{ButtonState, Key},
+    input::{ButtonState, GamepadButton, Key},
     lifecycle::{run, Event, Settings, State, Window},
     Result,
 };
-use render::draw_field;
+use render::{draw_countdown, draw_field, draw_menu, draw_results, draw_title};
 use resources::{ResourceFuture, Resources};
-use time::{GameClock, PausedClock};
+use rng::Rng;
+use savegame::{SaveError, Storage, BUG_REPORT_PROFILE, SAVED_GAME_PROFILE};
+use settings::{BoardSize, GameSettings};
+use storage::SaveFiles;
+use tbp::BotPlayer;
+use time::{unix_time, Countdown, GameClock, PausedClock};
+use zen::ZenTotals;
 
 pub struct Game {
     pub state: GameState,
     pub screen_size: Vector,
     pub resources: Resources,
+    /// Set up from the settings when play starts, and again after they change
+    pub keyboard: Option<KeyboardStates>,
+    /// Positions exported with F11 during this game
+    pub export: Vec<fumen::Page>,
+}
+
+pub struct GameResults {
+    pub outcome: GameCondition,
+    pub replay_message: Option<String>,
+    /// Position in the mode's high score table, if the game made it in
+    pub high_score_rank: Option<usize>,
+    /// The name of the external bot that played the game, if one did
+    pub bot_name: Option<String>,
 }
 
 enum GameScreen {
     Loading(Box<ResourceFuture>),
+    Menu(Resources, Menu),
     Playing(Game, GameClock),
-    Paused(Game, PausedClock),
-    Won(Game),
-    Lost(Game),
+    Paused(Game, PausedClock, Menu),
+    /// Counting down before play continues after a pause
+    Resuming(Game, PausedClock, Countdown),
+    Finished(Game, GameResults),
     Swap,
 }
 
 impl GameScreen {
-    fn evolve(&mut self, window: &Window) {
+    fn evolve(&mut self) {
         *self = match core::mem::replace(self, GameScreen::Swap) {
             GameScreen::Loading(mut resource_future) => match resource_future.poll() {
-                Ok(Async::Ready(resources)) => {
-                    let (game_state, clock) = GameState::new();
-                    GameScreen::Playing(
-                        Game {
-                            state: game_state,
-                            screen_size: window.screen_size(),
-                            resources: resources,
-                        },
-                        clock,
-                    )
-                }
+                Ok(Async::Ready(resources)) => GameScreen::menu(resources, Menu::new()),
                 _ => GameScreen::Loading(resource_future),
             },
             other => other,
         };
     }
+
+    fn start(
+        resources: Resources,
+        mode: GameMode,
+        seed: u64,
+        settings: &GameSettings,
+        window: &Window,
+    ) -> GameScreen {
+        let (mut game_state, clock) = GameState::new(mode, seed, settings);
+        load_zen_totals(&mut game_state);
+        GameScreen::Playing(
+            Game {
+                state: game_state,
+                screen_size: window.screen_size(),
+                resources: resources,
+                keyboard: None,
+                export: vec![],
+            },
+            clock,
+        )
+    }
+
+    /// Plays on from a shared position
+    fn start_position(
+        resources: Resources,
+        page: &fumen::Page,
+        settings: &GameSettings,
+        window: &Window,
+    ) -> GameScreen {
+        let mode = GameMode::Marathon { start_level: 1 };
+        let (game_state, clock) =
+            GameState::from_position(mode, Rng::random_seed(), settings, page);
+        GameScreen::Playing(
+            Game {
+                state: game_state,
+                screen_size: window.screen_size(),
+                resources: resources,
+                keyboard: None,
+                export: vec![],
+            },
+            clock,
+        )
+    }
+
+    /// Shows a menu, offering to continue the saved game if there is one
+    fn menu(resources: Resources, mut menu: Menu) -> GameScreen {
+        menu.set_saved_game(SaveFiles.retrieve(SAVED_GAME_PROFILE).is_some());
+        GameScreen::Menu(resources, menu)
+    }
+
+    /// Restores the saved game, paused and counting down to continue
+    fn continue_saved(
+        resources: Resources,
+        settings: &GameSettings,
+        window: &Window,
+    ) -> GameScreen {
+        let loaded = SaveFiles
+            .retrieve(SAVED_GAME_PROFILE)
+            .and_then(|text| GameState::load(&text, settings));
+        // A game can only be continued once, and an unreadable save is no use either
+        let _ = SaveFiles.remove(SAVED_GAME_PROFILE);
+        match loaded {
+            Some((mut game_state, clock)) => {
+                load_zen_totals(&mut game_state);
+                GameScreen::Resuming(
+                    Game {
+                        state: game_state,
+                        screen_size: window.screen_size(),
+                        resources: resources,
+                        keyboard: None,
+                        export: vec![],
+                    },
+                    clock.pause(),
+                    Countdown::new(RESUME_COUNTDOWN_SECONDS),
+                )
+            }
+            None => GameScreen::menu(resources, Menu::main()),
+        }
+    }
+
+    fn pause(self) -> GameScreen {
+        match self {
+            GameScreen::Playing(g, c) => GameScreen::Paused(g, c.pause(), Menu::pause()),
+            GameScreen::Resuming(g, c, _) => GameScreen::Paused(g, c, Menu::pause()),
+            other => other,
+        }
+    }
+
+    fn key_pressed(
+        self,
+        key: Key,
+        window: &Window,
+        settings: &mut GameSettings,
+        bindings: &mut KeyBindings,
+        high_scores: &HighScores,
+    ) -> GameScreen {
+        match (self, key) {
+            (GameScreen::Playing(g, c), Key::F12) => {
+                let _ = SaveFiles.store(BUG_REPORT_PROFILE, &g.state.save());
+                GameScreen::Playing(g, c)
+            }
+            (GameScreen::Playing(mut g, c), Key::F11) => {
+                if g.export.len() < MAX_EXPORT_PAGES {
+                    g.export.push(g.state.position());
+                    let _ = SaveFiles.store(EXPORT_PROFILE, &fumen::encode(&g.export));
+                }
+                GameScreen::Playing(g, c)
+            }
+            (screen @ GameScreen::Playing(..), Key::Escape)
+            | (screen @ GameScreen::Resuming(..), Key::Escape) => screen.pause(),
+            (GameScreen::Menu(r, mut menu), key) if menu.is_rebinding() => {
+                menu.rebind(key, bindings);
+                GameScreen::Menu(r, menu)
+            }
+            (GameScreen::Paused(g, c, mut menu), key) if menu.is_rebinding() => {
+                menu.rebind(key, bindings);
+                GameScreen::Paused(g, c, menu)
+            }
+            (screen @ GameScreen::Menu(..), key) | (screen @ GameScreen::Paused(..), key) => {
+                match input_for_key(key) {
+                    Some(input) => screen.menu_input(input, window, settings, high_scores),
+                    None => screen,
+                }
+            }
+            (GameScreen::Finished(g, results), key) => {
+                let summary = g.state.summary();
+                match key {
+                    Key::R => {
+                        GameScreen::start(g.resources, summary.mode, summary.seed, settings, window)
+                    }
+                    Key::N => GameScreen::start(
+                        g.resources,
+                        summary.mode,
+                        Rng::random_seed(),
+                        settings,
+                        window,
+                    ),
+                    Key::M | Key::Escape => GameScreen::menu(g.resources, Menu::mode_select()),
+                    Key::S => {
+                        let profile = replay::profile_name(unix_time(), summary.seed);
+                        let message = match g.state.replay().save_as(&SaveFiles, &profile) {
+                            Ok(()) => format!("Replay saved as {}", profile),
+                            Err(_) => String::from("Could not save replay"),
+                        };
+                        GameScreen::Finished(
+                            g,
+                            GameResults {
+                                replay_message: Some(message),
+                                ..results
+                            },
+                        )
+                    }
+                    _ => GameScreen::Finished(g, results),
+                }
+            }
+            (other, _) => other,
+        }
+    }
+
+    /// Handles navigation from the keyboard or a gamepad
+    fn menu_input(
+        self,
+        input: MenuInput,
+        window: &Window,
+        settings: &mut GameSettings,
+        high_scores: &HighScores,
+    ) -> GameScreen {
+        match self {
+            GameScreen::Menu(r, mut menu) => match menu.input(input, settings, high_scores) {
+                Some(MenuAction::StartGame(mode)) => {
+                    GameScreen::start(r, mode, Rng::random_seed(), settings, window)
+                }
+                Some(MenuAction::Continue) => GameScreen::continue_saved(r, settings, window),
+                Some(_) | None => GameScreen::Menu(r, menu),
+            },
+            GameScreen::Paused(mut g, c, mut menu) => {
+                match menu.input(input, settings, high_scores) {
+                    Some(MenuAction::Resume) => {
+                        g.state.apply_settings(settings);
+                        // The keyboard is set up again with any new handling or bindings
+                        g.keyboard = None;
+                        GameScreen::Resuming(g, c, Countdown::new(RESUME_COUNTDOWN_SECONDS))
+                    }
+                    Some(MenuAction::Restart) => {
+                        keep_zen_totals(&g);
+                        let mode = g.state.summary().mode;
+                        GameScreen::start(g.resources, mode, Rng::random_seed(), settings, window)
+                    }
+                    Some(MenuAction::SaveAndQuit) => {
+                        // The saved game counts towards zen totals again once it's continued
+                        if let Some(totals) = g.state.earlier_zen_totals() {
+                            let _ = totals.save(&SaveFiles);
+                        }
+                        match SaveFiles.store(SAVED_GAME_PROFILE, &g.state.save()) {
+                            Ok(()) => GameScreen::menu(g.resources, Menu::main()),
+                            Err(_) => GameScreen::Paused(g, c, menu),
+                        }
+                    }
+                    Some(MenuAction::QuitToMenu) => {
+                        keep_zen_totals(&g);
+                        GameScreen::menu(g.resources, Menu::main())
+                    }
+                    Some(_) | None => GameScreen::Paused(g, c, menu),
+                }
+            }
+            GameScreen::Finished(g, results) => match input {
+                MenuInput::Confirm => {
+                    let summary = g.state.summary();
+                    GameScreen::start(g.resources, summary.mode, summary.seed, settings, window)
+                }
+                MenuInput::Back => GameScreen::menu(g.resources, Menu::mode_select()),
+                _ => GameScreen::Finished(g, results),
+            },
+            other => other,
+        }
+    }
+}
+
+const RESUME_COUNTDOWN_SECONDS: u32 = 3;
+
+/// Counts a zen game on top of the totals saved from earlier ones
+fn load_zen_totals(state: &mut GameState) {
+    if state.zen_totals().is_some() {
+        state.set_zen_totals(ZenTotals::load(&SaveFiles));
+    }
+}
+
+/// Saves the zen totals with the game so far, after every lock and when it's left, so they're
+/// kept however the game ends
+fn keep_zen_totals(game: &Game) {
+    if let Some(totals) = game.state.zen_totals() {
+        let _ = totals.save(&SaveFiles);
+    }
 }
 
 struct GameWrapper {
     // Initialzied on the first loop
     loading_game: GameScreen,
+    settings: GameSettings,
+    bindings: KeyBindings,
+    high_scores: HighScores,
+    /// A position to play as soon as loading finishes
+    launch_position: Option<fumen::Page>,
+    /// The attract mode game, while the title screen shows
+    demo: Option<Demo>,
+    /// An external bot playing every game in place of the keyboard
+    bot: Option<BotPlayer>,
+}
+
+/// A game the AI plays behind the title screen
+struct Demo {
+    state: GameState,
+    clock: GameClock,
+    ai: AiPlayer,
+}
+
+impl Demo {
+    fn new(settings: &GameSettings) -> Demo {
+        let mut settings = settings.clone();
+        settings.start_countdown = Duration::from_millis(0);
+        settings.board = BoardSize::Standard;
+        settings.hint_modes.clear();
+        settings.strict_finesse = false;
+        let mode = GameMode::Marathon { start_level: 1 };
+        let (state, clock) = GameState::new(mode, Rng::random_seed(), &settings);
+        Demo {
+            state: state,
+            clock: clock,
+            ai: AiPlayer::new(Difficulty::Hard),
+        }
+    }
+
+    /// Plays on silently, starting a new game when this one ends
+    fn update(&mut self, settings: &GameSettings) {
+        let now = self.clock.now();
+        let inputs = self.ai.update(&self.state, now);
+        let condition = self.state.update_with(inputs, now);
+        self.state.take_sound_effects();
+        match condition {
+            GameCondition::Playing => (),
+            _ => *self = Demo::new(settings),
+        }
+    }
 }
 
 impl State for GameWrapper {
     fn new() -> Result<GameWrapper> {
         Ok(GameWrapper {
             loading_game: GameScreen::Loading(Box::new(resources::load_resources())),
+            settings: GameSettings::new(),
+            bindings: KeyBindings::new(),
+            high_scores: HighScores::load(&SaveFiles),
+            launch_position: fumen::launch_fumen()
+                .and_then(|text| fumen::decode(&text))
+                .and_then(|pages| pages.into_iter().next()),
+            demo: None,
+            bot: tbp::launch_bot(),
         })
     }
 
     fn draw(&mut self, window: &mut Window) -> Result<()> {
         match &self.loading_game {
-            GameScreen::Playing(g, _)
-            | GameScreen::Paused(g, _)
-            | GameScreen::Won(g)
-            | GameScreen::Lost(g) => draw_field(window, g),
+            GameScreen::Playing(g, _) => draw_field(window, g, self.settings.skin),
+            // The field stays hidden while paused so the stack can't be studied
+            GameScreen::Paused(g, _, menu) => draw_menu(
+                window,
+                &g.resources,
+                &menu.view(&self.settings, &self.bindings, &self.high_scores),
+            ),
+            GameScreen::Resuming(g, _, countdown) => {
+                draw_field(window, g, self.settings.skin)?;
+                draw_countdown(window, g, countdown.remaining())
+            }
+            GameScreen::Finished(g, results) => draw_results(window, g, results),
+            GameScreen::Menu(r, menu) => {
+                let view = menu.view(&self.settings, &self.bindings, &self.high_scores);
+                match &self.demo {
+                    Some(demo) if menu.is_title() => draw_title(
+                        window,
+                        r,
+                        &view,
+                        &demo.state.render_info(),
+                        self.settings.skin,
+                    ),
+                    _ => draw_menu(window, r, &view),
+                }
+            }
             _ => Ok(()),
         }
     }
 
     fn update(&mut self, window: &mut Window) -> Result<()> {
-        self.loading_game.evolve(window);
+        self.loading_game.evolve();
 
         self.loading_game = match core::mem::replace(&mut self.loading_game, GameScreen::Swap) {
+            GameScreen::Menu(resources, menu) => match self.launch_position.take() {
+                Some(page) => GameScreen::start_position(resources, &page, &self.settings, window),
+                None => GameScreen::Menu(resources, menu),
+            },
             GameScreen::Playing(mut game, clock) => {
-                match game.state.update(window.keyboard(), clock.now()) {
-                    GameCondition::Won => GameScreen::Won(game),
-                    GameCondition::Lost => GameScreen::Lost(game),
+                let (settings, bindings) = (&self.settings, &self.bindings);
+                let condition = match &mut self.bot {
+                    Some(bot) => {
+                        let inputs = bot.update(&game.state);
+                        game.state.update_with(inputs, clock.now())
+                    }
+                    None => {
+                        let keyboard = game
+                            .keyboard
+                            .get_or_insert_with(|| KeyboardStates::new(settings, bindings));
+                        // Held keys are followed during the countdown too, so DAS is already
+                        // charged at "Go"
+                        let inputs = keyboard.update(window.keyboard(), clock.now());
+                        let presses = keyboard.take_key_presses();
+                        game.state
+                            .update_from_keyboard(inputs, presses, clock.now())
+                    }
+                };
+                let effects = game.state.take_sound_effects();
+                for effect in effects.iter() {
+                    // Audio is optional; carry on silently without an output device
+                    let _ = game.resources.sounds.play(*effect, self.settings.volume);
+                }
+                if effects.contains(&SoundEffect::Lock) {
+                    keep_zen_totals(&game);
+                }
+                match condition {
                     GameCondition::Playing => GameScreen::Playing(game, clock),
+                    // Strict finesse goes straight into another attempt
+                    GameCondition::Lost(LossReason::FinesseFault) => {
+                        let mode = game.state.summary().mode;
+                        GameScreen::start(
+                            game.resources,
+                            mode,
+                            Rng::random_seed(),
+                            &self.settings,
+                            window,
+                        )
+                    }
+                    outcome => {
+                        let completed = match outcome {
+                            GameCondition::Won => true,
+                            _ => false,
+                        };
+                        let (rank, saved) = self.record_high_score(&game, completed);
+                        GameScreen::Finished(
+                            game,
+                            GameResults {
+                                outcome: outcome,
+                                replay_message: match saved {
+                                    Ok(()) => None,
+                                    Err(_) => Some(String::from("Could not save high scores")),
+                                },
+                                high_score_rank: rank,
+                                bot_name: self
+                                    .bot
+                                    .as_ref()
+                                    .and_then(|bot| bot.name())
+                                    .map(String::from),
+                            },
+                        )
+                    }
+                }
+            }
+            GameScreen::Resuming(game, clock, countdown) => {
+                if countdown.remaining() == 0 {
+                    GameScreen::Playing(game, clock.resume())
+                } else {
+                    GameScreen::Resuming(game, clock, countdown)
                 }
             }
             other => other,
         };
 
+        match &self.loading_game {
+            GameScreen::Menu(_, menu) if menu.is_title() => {
+                let settings = &self.settings;
+                self.demo
+                    .get_or_insert_with(|| Demo::new(settings))
+                    .update(settings);
+            }
+            _ => self.demo = None,
+        }
+
         Ok(())
     }
 
-    fn event(&mut self, event: &Event, _window: &mut Window) -> Result<()> {
+    fn event(&mut self, event: &Event, window: &mut Window) -> Result<()> {
         match event {
-            Event::Key(Key::Escape, ButtonState::Pressed) => {
+            Event::Key(key, ButtonState::Pressed) => {
+                self.loading_game = core::mem::replace(&mut self.loading_game, GameScreen::Swap)
+                    .key_pressed(
+                        *key,
+                        window,
+                        &mut self.settings,
+                        &mut self.bindings,
+                        &self.high_scores,
+                    );
+            }
+            Event::GamepadButton(_, GamepadButton::Start, ButtonState::Pressed)
+                if self.is_playing() =>
+            {
+                self.loading_game =
+                    core::mem::replace(&mut self.loading_game, GameScreen::Swap).pause();
+            }
+            Event::GamepadButton(_, button, ButtonState::Pressed) => {
+                if let Some(input) = input_for_button(*button) {
+                    self.loading_game =
+                        core::mem::replace(&mut self.loading_game, GameScreen::Swap).menu_input(
+                            input,
+                            window,
+                            &mut self.settings,
+                            &self.high_scores,
+                        );
+                }
+            }
+            Event::Unfocused => {
                 self.loading_game =
-                    match core::mem::replace(&mut self.loading_game, GameScreen::Swap) {
-                        GameScreen::Playing(g, c) => GameScreen::Paused(g, c.pause()),
-                        GameScreen::Paused(g, c) => GameScreen::Playing(g, c.resume()),
-                        other => other,
-                    };
+                    core::mem::replace(&mut self.loading_game, GameScreen::Swap).pause();
             }
             _ => (),
         }
+        // Bots assume the standard board, so no other size can be picked while one plays
+        if self.bot.is_some() {
+            self.settings.board = BoardSize::Standard;
+        }
         Ok(())
     }
 }
 
+impl GameWrapper {
+    /// Adds the game to the high scores, saving them along with its replay if it made the table
+    fn record_high_score(
+        &mut self,
+        game: &Game,
+        completed: bool,
+    ) -> (Option<usize>, core::result::Result<(), SaveError>) {
+        if !game.state.is_record_eligible() || self.bot.is_some() {
+            return (None, Ok(()));
+        }
+        let summary = game.state.summary();
+        let rank = self.high_scores.record(summary, completed, unix_time());
+        let saved = match rank {
+            Some(rank) => {
+                let entry = &self.high_scores.top(summary.mode)[rank];
+                game.state
+                    .replay()
+                    .save_as(&SaveFiles, &entry.replay)
+                    .and_then(|()| self.high_scores.save(&SaveFiles))
+            }
+            None => Ok(()),
+        };
+        (rank, saved)
+    }
+
+    fn is_playing(&self) -> bool {
+        match self.loading_game {
+            GameScreen::Playing(..) | GameScreen::Resuming(..) => true,
+            _ => false,
+        }
+    }
+}
+
 fn main() {
     run::<GameWrapper>(
         "Blocks",
@@ -0,0 +1,584 @@
+use crate::highscores::{format_entry, HighScores};
+use crate::keybindings::Trigger;
+use crate::keyboard::KeyBindings;
+use crate::mode::GameMode;
+use crate::settings::{GameSettings, Skin};
+use alloc::string::String;
+use alloc::vec::Vec;
+use quicksilver::input::{GamepadButton, Key};
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum MenuInput {
+    Up,
+    Down,
+    Left,
+    Right,
+    Confirm,
+    Back,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum MenuAction {
+    StartGame(GameMode),
+    Continue,
+    Resume,
+    Restart,
+    SaveAndQuit,
+    QuitToMenu,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum Page {
+    Title,
+    Main,
+    ModeSelect,
+    ModeOptions(GameMode),
+    Settings,
+    Bindings { rebinding: bool },
+    HighScores { mode_index: usize },
+    Pause,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum MainItem {
+    Continue,
+    Play,
+    HighScores,
+    Settings,
+}
+
+/// Navigation state of the menus shown between games and while paused
+pub struct Menu {
+    page: Page,
+    selected: usize,
+    /// Opened from a paused game, so backing out of the settings returns to the pause menu
+    in_game: bool,
+    /// There is a saved game to continue
+    saved_game: bool,
+}
+
+/// What to draw for the current page
+pub struct MenuView {
+    pub title: String,
+    pub items: Vec<String>,
+    pub selected: Option<usize>,
+    pub help: &'static str,
+}
+
+const PAUSE_ITEMS: [&str; 5] = [
+    "Resume",
+    "Restart",
+    "Settings",
+    "Save and Quit",
+    "Quit to Menu",
+];
+const SETTINGS_ITEMS: usize = 9;
+
+impl Menu {
+    pub fn new() -> Self {
+        Menu {
+            page: Page::Title,
+            selected: 0,
+            in_game: false,
+            saved_game: false,
+        }
+    }
+
+    pub fn main() -> Self {
+        Menu {
+            page: Page::Main,
+            selected: 0,
+            in_game: false,
+            saved_game: false,
+        }
+    }
+
+    pub fn mode_select() -> Self {
+        Menu {
+            page: Page::ModeSelect,
+            selected: 0,
+            in_game: false,
+            saved_game: false,
+        }
+    }
+
+    pub fn pause() -> Self {
+        Menu {
+            page: Page::Pause,
+            selected: 0,
+            in_game: true,
+            saved_game: false,
+        }
+    }
+
+    pub fn set_saved_game(&mut self, saved_game: bool) {
+        self.saved_game = saved_game;
+    }
+
+    fn main_items(&self) -> Vec<MainItem> {
+        let mut items = vec![];
+        if self.saved_game {
+            items.push(MainItem::Continue);
+        }
+        items.extend_from_slice(&[MainItem::Play, MainItem::HighScores, MainItem::Settings]);
+        items
+    }
+
+    fn main_index(&self, item: MainItem) -> usize {
+        self.main_items()
+            .iter()
+            .position(|i| *i == item)
+            .unwrap_or(0)
+    }
+
+    /// Whether the title screen is showing, which has a demo game playing behind it
+    pub fn is_title(&self) -> bool {
+        self.page == Page::Title
+    }
+
+    /// Whether the menu is waiting for the player to press a key to bind
+    pub fn is_rebinding(&self) -> bool {
+        self.page == (Page::Bindings { rebinding: true })
+    }
+
+    /// Binds the key pressed while rebinding to the selected trigger
+    pub fn rebind(&mut self, key: Key, bindings: &mut KeyBindings) {
+        if self.is_rebinding() {
+            if key != Key::Escape {
+                bindings.rebind(Trigger::ALL[self.selected], key);
+            }
+            self.page = Page::Bindings { rebinding: false };
+        }
+    }
+
+    pub fn input(
+        &mut self,
+        input: MenuInput,
+        settings: &mut GameSettings,
+        high_scores: &HighScores,
+    ) -> Option<MenuAction> {
+        let num_items = self.num_items();
+        match input {
+            MenuInput::Up if num_items > 0 => {
+                self.selected = (self.selected + num_items - 1) % num_items
+            }
+            MenuInput::Down if num_items > 0 => self.selected = (self.selected + 1) % num_items,
+            MenuInput::Left => self.adjust(-1, settings, high_scores),
+            MenuInput::Right => self.adjust(1, settings, high_scores),
+            MenuInput::Confirm => return self.confirm(settings),
+            MenuInput::Back => return self.back(),
+            _ => (),
+        }
+        None
+    }
+
+    fn num_items(&self) -> usize {
+        match self.page {
+            Page::Title | Page::HighScores { .. } => 0,
+            Page::Main => self.main_items().len(),
+            Page::Pause => PAUSE_ITEMS.len(),
+            Page::ModeSelect => GameMode::ALL.len(),
+            Page::ModeOptions(mode) => match mode.option() {
+                Some(_) => 3,
+                None => 2,
+            },
+            Page::Settings => SETTINGS_ITEMS,
+            Page::Bindings { .. } => Trigger::ALL.len(),
+        }
+    }
+
+    fn go_to(&mut self, page: Page, selected: usize) {
+        self.page = page;
+        self.selected = selected;
+    }
+
+    fn confirm(&mut self, settings: &mut GameSettings) -> Option<MenuAction> {
+        match self.page {
+            Page::Title => self.go_to(Page::Main, 0),
+            Page::Main => match self.main_items()[self.selected] {
+                MainItem::Continue => return Some(MenuAction::Continue),
+                MainItem::Play => self.go_to(Page::ModeSelect, 0),
+                MainItem::HighScores => self.go_to(Page::HighScores { mode_index: 0 }, 0),
+                MainItem::Settings => self.go_to(Page::Settings, 0),
+            },
+            Page::ModeSelect => {
+                let mode = GameMode::ALL[self.selected];
+                self.go_to(Page::ModeOptions(mode), 0);
+            }
+            Page::ModeOptions(mode) if self.selected == hints_index(mode) => {
+                settings.toggle_hints(mode)
+            }
+            Page::ModeOptions(mode) => return Some(MenuAction::StartGame(mode)),
+            Page::Settings => match self.selected {
+                3 => settings.toggle_skin(),
+                7 => settings.strict_finesse = !settings.strict_finesse,
+                8 => self.go_to(Page::Bindings { rebinding: false }, 0),
+                _ => (),
+            },
+            Page::Bindings { .. } => self.page = Page::Bindings { rebinding: true },
+            Page::HighScores { .. } => (),
+            Page::Pause => match self.selected {
+                0 => return Some(MenuAction::Resume),
+                1 => return Some(MenuAction::Restart),
+                2 => self.go_to(Page::Settings, 0),
+                3 => return Some(MenuAction::SaveAndQuit),
+                _ => return Some(MenuAction::QuitToMenu),
+            },
+        }
+        None
+    }
+
+    fn back(&mut self) -> Option<MenuAction> {
+        match self.page {
+            Page::Title => (),
+            Page::Main => self.go_to(Page::Title, 0),
+            Page::ModeSelect => self.go_to(Page::Main, self.main_index(MainItem::Play)),
+            Page::ModeOptions(mode) => {
+                let index = GameMode::ALL
+                    .iter()
+                    .position(|m| m.name() == mode.name())
+                    .unwrap_or(0);
+                self.go_to(Page::ModeSelect, index);
+            }
+            Page::Settings if self.in_game => self.go_to(Page::Pause, 2),
+            Page::Settings => self.go_to(Page::Main, self.main_index(MainItem::Settings)),
+            Page::Bindings { .. } => self.go_to(Page::Settings, 8),
+            Page::HighScores { .. } => {
+                self.go_to(Page::Main, self.main_index(MainItem::HighScores))
+            }
+            Page::Pause => return Some(MenuAction::Resume),
+        }
+        None
+    }
+
+    fn adjust(&mut self, step: i32, settings: &mut GameSettings, high_scores: &HighScores) {
+        match self.page {
+            Page::ModeOptions(mode) if self.selected == hints_index(mode) => {
+                settings.toggle_hints(mode)
+            }
+            Page::ModeOptions(mode) if self.selected == 0 => {
+                self.page = Page::ModeOptions(mode.adjust_option(step))
+            }
+            Page::Settings => match self.selected {
+                0 => settings.adjust_das(step),
+                1 => settings.adjust_arr(step),
+                2 => settings.adjust_soft_drop_repeat(step),
+                3 => settings.toggle_skin(),
+                4 => settings.adjust_volume(step),
+                5 => settings.adjust_start_countdown(step),
+                6 => settings.adjust_board(step),
+                7 => settings.strict_finesse = !settings.strict_finesse,
+                _ => (),
+            },
+            Page::HighScores { mode_index } => {
+                let num_modes = high_scores.modes().len().max(1) as i32;
+                self.page = Page::HighScores {
+                    mode_index: (mode_index as i32 + step).rem_euclid(num_modes) as usize,
+                }
+            }
+            _ => (),
+        }
+    }
+
+    pub fn view(
+        &self,
+        settings: &GameSettings,
+        bindings: &KeyBindings,
+        high_scores: &HighScores,
+    ) -> MenuView {
+        const NAVIGATE: &str = "Up/Down - Select   Enter - Confirm   Esc - Back";
+        let (title, items, help) = match self.page {
+            Page::Title => (String::from("BLOCKS"), vec![], "Press Enter or Start"),
+            Page::Main => (
+                String::from("BLOCKS"),
+                self.main_items()
+                    .iter()
+                    .map(|item| String::from(item.name()))
+                    .collect(),
+                NAVIGATE,
+            ),
+            Page::Pause => (
+                String::from("PAUSED"),
+                PAUSE_ITEMS.iter().map(|s| String::from(*s)).collect(),
+                "Up/Down - Select   Enter - Confirm   Esc - Resume\n\
+                 While playing: F11 - Export position   F12 - Save bug report",
+            ),
+            Page::ModeSelect => (
+                String::from("Select Mode"),
+                GameMode::ALL
+                    .iter()
+                    .map(|m| String::from(m.name()))
+                    .collect(),
+                NAVIGATE,
+            ),
+            Page::ModeOptions(mode) => {
+                let mut items = vec![];
+                if let Some((label, value)) = mode.option() {
+                    items.push(format!("{}: < {} >", label, value));
+                }
+                items.push(String::from("Start"));
+                items.push(format!(
+                    "Hints: < {} >",
+                    if settings.hints_for(mode) {
+                        "On"
+                    } else {
+                        "Off"
+                    }
+                ));
+                let title = match mode {
+                    // Only solving a puzzle puts it in the high scores
+                    GameMode::Puzzle { .. } if !high_scores.top(mode).is_empty() => {
+                        format!("{} - Solved", mode.name())
+                    }
+                    _ => String::from(mode.name()),
+                };
+                (
+                    title,
+                    items,
+                    "Left/Right - Change   Enter - Start   Esc - Back",
+                )
+            }
+            Page::Settings => (
+                String::from("Settings"),
+                vec![
+                    format!("DAS: < {}ms >", settings.das.as_millis()),
+                    format!("ARR: < {}ms >", settings.arr.as_millis()),
+                    format!("Soft drop: < {}ms >", settings.soft_drop_repeat.as_millis()),
+                    format!(
+                        "Skin: < {} >",
+                        match settings.skin {
+                            Skin::Textured => "Textured",
+                            Skin::Flat => "Flat",
+                        }
+                    ),
+                    format!("Volume: < {}% >", settings.volume),
+                    format!(
+                        "Countdown: < {:.1}s >",
+                        settings.start_countdown.as_millis() as f32 / 1000.0
+                    ),
+                    format!("Board: < {} >", settings.board.name()),
+                    format!(
+                        "Strict finesse: < {} >",
+                        if settings.strict_finesse { "On" } else { "Off" }
+                    ),
+                    String::from("Controls"),
+                ],
+                "Left/Right - Change   Esc - Back",
+            ),
+            Page::Bindings { rebinding } => (
+                String::from("Controls"),
+                Trigger::ALL
+                    .iter()
+                    .map(|t| {
+                        let keys: Vec<String> = bindings
+                            .keys_for(*t)
+                            .iter()
+                            .map(|k| format!("{:?}", k))
+                            .collect();
+                        format!("{}: {}", t.name(), keys.join(", "))
+                    })
+                    .collect(),
+                if rebinding {
+                    "Press a key to bind   Esc - Cancel"
+                } else {
+                    "Enter - Rebind   Esc - Back"
+                },
+            ),
+            Page::HighScores { mode_index } => {
+                let modes = high_scores.modes();
+                match modes.get(mode_index) {
+                    Some(mode) => (
+                        format!("High Scores - {}", mode.description()),
+                        high_scores
+                            .top(*mode)
+                            .iter()
+                            .enumerate()
+                            .map(|(i, e)| format!("{}. {}", i + 1, format_entry(e)))
+                            .collect(),
+                        "Left/Right - Mode   Esc - Back",
+                    ),
+                    None => (
+                        String::from("High Scores"),
+                        vec![String::from("No games played yet")],
+                        "Esc - Back",
+                    ),
+                }
+            }
+        };
+        MenuView {
+            title: title,
+            items: items,
+            selected: match self.page {
+                Page::Title | Page::HighScores { .. } => None,
+                _ => Some(self.selected),
+            },
+            help: help,
+        }
+    }
+}
+
+impl MainItem {
+    fn name(&self) -> &'static str {
+        match self {
+            MainItem::Continue => "Continue",
+            MainItem::Play => "Play",
+            MainItem::HighScores => "High Scores",
+            MainItem::Settings => "Settings",
+        }
+    }
+}
+
+pub fn input_for_key(key: Key) -> Option<MenuInput> {
+    match key {
+        Key::Up => Some(MenuInput::Up),
+        Key::Down => Some(MenuInput::Down),
+        Key::Left => Some(MenuInput::Left),
+        Key::Right => Some(MenuInput::Right),
+        Key::Return | Key::Space => Some(MenuInput::Confirm),
+        Key::Escape | Key::Back => Some(MenuInput::Back),
+        _ => None,
+    }
+}
+
+pub fn input_for_button(button: GamepadButton) -> Option<MenuInput> {
+    match button {
+        GamepadButton::DpadUp => Some(MenuInput::Up),
+        GamepadButton::DpadDown => Some(MenuInput::Down),
+        GamepadButton::DpadLeft => Some(MenuInput::Left),
+        GamepadButton::DpadRight => Some(MenuInput::Right),
+        GamepadButton::FaceDown | GamepadButton::Start => Some(MenuInput::Confirm),
+        GamepadButton::FaceRight | GamepadButton::Select => Some(MenuInput::Back),
+        _ => None,
+    }
+}
+
+/// The position of the hints toggle on a mode's options page, after the option and "Start"
+fn hints_index(mode: GameMode) -> usize {
+    match mode.option() {
+        Some(_) => 2,
+        None => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(
+        menu: &mut Menu,
+        settings: &mut GameSettings,
+        inputs: &[MenuInput],
+    ) -> Option<MenuAction> {
+        let high_scores = HighScores::new();
+        let mut action = None;
+        for input in inputs {
+            action = menu.input(*input, settings, &high_scores);
+        }
+        action
+    }
+
+    #[test]
+    fn start_configured_mode() {
+        use MenuInput::*;
+        let mut menu = Menu::new();
+        let mut settings = GameSettings::new();
+        assert_eq!(
+            Some(MenuAction::StartGame(GameMode::Sprint { lines: 100 })),
+            press(
+                &mut menu,
+                &mut settings,
+                &[Confirm, Confirm, Down, Confirm, Right, Down, Confirm]
+            )
+        );
+    }
+
+    #[test]
+    fn modes_without_options_start_first() {
+        use MenuInput::*;
+        let mut menu = Menu::mode_select();
+        let mut settings = GameSettings::new();
+        assert_eq!(
+            Some(MenuAction::StartGame(GameMode::Survival)),
+            press(
+                &mut menu,
+                &mut settings,
+                &[Down, Down, Down, Confirm, Confirm]
+            )
+        );
+    }
+
+    #[test]
+    fn hints_per_mode() {
+        use MenuInput::*;
+        let mut menu = Menu::mode_select();
+        let mut settings = GameSettings::new();
+        assert_eq!(
+            None,
+            press(&mut menu, &mut settings, &[Down, Confirm, Up, Confirm])
+        );
+        assert!(settings.hints_for(GameMode::Sprint { lines: 40 }));
+        assert!(!settings.hints_for(GameMode::Sprint { lines: 100 }));
+        assert!(!settings.hints_for(GameMode::Marathon { start_level: 1 }));
+        press(&mut menu, &mut settings, &[Left]);
+        assert!(!settings.hints_for(GameMode::Sprint { lines: 40 }));
+    }
+
+    #[test]
+    fn pause_settings_return_to_pause() {
+        use MenuInput::*;
+        let mut menu = Menu::pause();
+        let mut settings = GameSettings::new();
+        assert_eq!(
+            Some(MenuAction::Resume),
+            press(&mut menu, &mut settings, &[Down, Down, Confirm, Back, Back])
+        );
+        assert_eq!(
+            Some(MenuAction::QuitToMenu),
+            press(&mut menu, &mut settings, &[Down, Down, Confirm])
+        );
+    }
+
+    #[test]
+    fn continue_saved_game() {
+        use MenuInput::*;
+        let mut menu = Menu::main();
+        menu.set_saved_game(true);
+        let mut settings = GameSettings::new();
+        assert_eq!(
+            Some(MenuAction::Continue),
+            press(&mut menu, &mut settings, &[Confirm])
+        );
+        press(&mut menu, &mut settings, &[Up, Confirm, Back]);
+        assert_eq!(
+            Some(3),
+            menu.view(&settings, &KeyBindings::new(), &HighScores::new())
+                .selected
+        );
+    }
+
+    #[test]
+    fn change_settings() {
+        use MenuInput::*;
+        let mut menu = Menu::new();
+        let mut settings = GameSettings::new();
+        press(
+            &mut menu,
+            &mut settings,
+            &[
+                Confirm, Up, Confirm, Right, Down, Left, Up, Up, Confirm, Down,
+            ],
+        );
+        assert_eq!(130, settings.das.as_millis());
+        assert_eq!(30, settings.arr.as_millis());
+
+        press(&mut menu, &mut settings, &[Confirm]);
+        assert!(menu.is_rebinding());
+        let mut bindings = KeyBindings::new();
+        menu.rebind(Key::A, &mut bindings);
+        assert!(!menu.is_rebinding());
+        assert_eq!(
+            vec![Key::A],
+            bindings.keys_for(Trigger::Shift(blocks::position::ShiftDir::Right))
+        );
+    }
+}
@@ -0,0 +1,264 @@
+use crate::ai::Difficulty;
+use crate::puzzle::{PuzzleFile, PUZZLE_FILES};
+use crate::setups::{Setup, SETUPS};
+use crate::visibility::StackVisibility;
+use alloc::string::String;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum GameMode {
+    /// Level up every 10 lines, and win after the final level
+    Marathon { start_level: i32 },
+    /// Clear a number of lines as fast as possible
+    Sprint { lines: i32 },
+    /// Score as much as possible before time runs out
+    Ultra { seconds: u32 },
+    /// Garbage rises from the bottom on a timer; survive as long as possible
+    Survival,
+    /// Practise perfect clears; the board resets after each attempt
+    PerfectClear,
+    /// Trade garbage with an AI opponent until one of you tops out
+    Versus { difficulty: Difficulty },
+    /// Place single pieces on target placements in as few inputs as possible
+    FinesseTrainer,
+    /// Build one of the `SETUPS` from its piece order, starting again after a wrong piece
+    Setup { setup: usize },
+    /// Reach the goal of one of the `PUZZLE_FILES` from its field and queue
+    Puzzle { puzzle: usize },
+    /// Stack at leisure with no way to lose, and with gravity only if it's wanted
+    Zen { gravity: bool },
+    /// Marathon with the stack hidden, apart from while lines clear and once the game is over
+    Hidden { stack: StackVisibility },
+}
+
+const MAX_START_LEVEL: i32 = 15;
+const SPRINT_LINES: [i32; 3] = [20, 40, 100];
+const ULTRA_SECONDS: [u32; 4] = [60, 120, 180, 300];
+
+impl GameMode {
+    pub const ALL: [GameMode; 11] = [
+        GameMode::Marathon { start_level: 1 },
+        GameMode::Sprint { lines: 40 },
+        GameMode::Ultra { seconds: 120 },
+        GameMode::Survival,
+        GameMode::PerfectClear,
+        GameMode::Versus {
+            difficulty: Difficulty::Normal,
+        },
+        GameMode::FinesseTrainer,
+        GameMode::Setup { setup: 0 },
+        GameMode::Puzzle { puzzle: 0 },
+        GameMode::Zen { gravity: false },
+        GameMode::Hidden {
+            stack: StackVisibility::Fading,
+        },
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GameMode::Marathon { .. } => "Marathon",
+            GameMode::Sprint { .. } => "Sprint",
+            GameMode::Ultra { .. } => "Ultra",
+            GameMode::Survival => "Survival",
+            GameMode::PerfectClear => "Perfect Clear",
+            GameMode::Versus { .. } => "Versus",
+            GameMode::FinesseTrainer => "Finesse Trainer",
+            GameMode::Setup { .. } => "Setups",
+            GameMode::Puzzle { .. } => "Puzzles",
+            GameMode::Zen { .. } => "Zen",
+            GameMode::Hidden { .. } => "Hidden",
+        }
+    }
+
+    /// The name along with the mode's option, to tell configurations of the same mode apart
+    pub fn description(&self) -> String {
+        match self.option() {
+            Some((_, value)) => format!("{} {}", self.name(), value),
+            None => String::from(self.name()),
+        }
+    }
+
+    /// The label and current value of the mode's adjustable option, if it has one
+    pub fn option(&self) -> Option<(&'static str, String)> {
+        match *self {
+            GameMode::Marathon { start_level } => {
+                Some(("Start level", format!("L{}", start_level)))
+            }
+            GameMode::Sprint { lines } => Some(("Lines", format!("{}L", lines))),
+            GameMode::Ultra { seconds } => Some((
+                "Time limit",
+                format!("{}:{:02}", seconds / 60, seconds % 60),
+            )),
+            GameMode::Versus { difficulty } => Some(("Opponent", String::from(difficulty.name()))),
+            GameMode::Setup { setup } => Some(("Setup", String::from(SETUPS[setup].name))),
+            GameMode::Puzzle { puzzle } => {
+                Some(("Puzzle", String::from(PUZZLE_FILES[puzzle].name)))
+            }
+            GameMode::Zen { gravity } => Some(("Gravity", String::from(gravity_name(gravity)))),
+            GameMode::Hidden { stack } => Some(("Stack", String::from(stack.name()))),
+            GameMode::Survival | GameMode::PerfectClear | GameMode::FinesseTrainer => None,
+        }
+    }
+
+    /// A short machine-readable form, stable across versions for saved data
+    pub fn code(&self) -> String {
+        match *self {
+            GameMode::Marathon { start_level } => format!("marathon:{}", start_level),
+            GameMode::Sprint { lines } => format!("sprint:{}", lines),
+            GameMode::Ultra { seconds } => format!("ultra:{}", seconds),
+            GameMode::Survival => String::from("survival"),
+            GameMode::PerfectClear => String::from("perfect-clear"),
+            GameMode::Versus { difficulty } => format!("versus:{}", difficulty.code()),
+            GameMode::FinesseTrainer => String::from("finesse-trainer"),
+            GameMode::Setup { setup } => format!("setup:{}", SETUPS[setup].code),
+            GameMode::Puzzle { puzzle } => format!("puzzle:{}", PUZZLE_FILES[puzzle].code),
+            GameMode::Zen { gravity: false } => String::from("zen:off"),
+            GameMode::Zen { gravity: true } => String::from("zen:slow"),
+            GameMode::Hidden { stack } => format!("hidden:{}", stack.code()),
+        }
+    }
+
+    pub fn from_code(code: &str) -> Option<GameMode> {
+        let mut parts = code.splitn(2, ':');
+        let name = parts.next()?;
+        let option = parts.next();
+        match (name, option) {
+            ("marathon", Some(level)) => Some(GameMode::Marathon {
+                start_level: level.parse().ok()?,
+            }),
+            ("sprint", Some(lines)) => Some(GameMode::Sprint {
+                lines: lines.parse().ok()?,
+            }),
+            ("ultra", Some(seconds)) => Some(GameMode::Ultra {
+                seconds: seconds.parse().ok()?,
+            }),
+            ("survival", None) => Some(GameMode::Survival),
+            ("perfect-clear", None) => Some(GameMode::PerfectClear),
+            ("finesse-trainer", None) => Some(GameMode::FinesseTrainer),
+            ("setup", Some(code)) => Some(GameMode::Setup {
+                setup: Setup::index_of(code)?,
+            }),
+            ("puzzle", Some(code)) => Some(GameMode::Puzzle {
+                puzzle: PuzzleFile::index_of(code)?,
+            }),
+            ("zen", Some("off")) => Some(GameMode::Zen { gravity: false }),
+            ("zen", Some("slow")) => Some(GameMode::Zen { gravity: true }),
+            ("hidden", Some(stack)) => Some(GameMode::Hidden {
+                stack: StackVisibility::from_code(stack)?,
+            }),
+            ("versus", Some(difficulty)) => Some(GameMode::Versus {
+                difficulty: Difficulty::from_code(difficulty)?,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Steps the mode's option up or down through its allowed values
+    pub fn adjust_option(&self, step: i32) -> GameMode {
+        match *self {
+            GameMode::Marathon { start_level } => GameMode::Marathon {
+                start_level: (start_level + step).max(1).min(MAX_START_LEVEL),
+            },
+            GameMode::Sprint { lines } => GameMode::Sprint {
+                lines: step_through(&SPRINT_LINES, lines, step),
+            },
+            GameMode::Ultra { seconds } => GameMode::Ultra {
+                seconds: step_through(&ULTRA_SECONDS, seconds, step),
+            },
+            GameMode::Versus { difficulty } => GameMode::Versus {
+                difficulty: step_through(&Difficulty::ALL, difficulty, step),
+            },
+            GameMode::Setup { setup } => GameMode::Setup {
+                setup: (setup as i32 + step).max(0).min(SETUPS.len() as i32 - 1) as usize,
+            },
+            GameMode::Puzzle { puzzle } => GameMode::Puzzle {
+                puzzle: (puzzle as i32 + step)
+                    .max(0)
+                    .min(PUZZLE_FILES.len() as i32 - 1) as usize,
+            },
+            GameMode::Zen { gravity } => GameMode::Zen {
+                gravity: step_through(&[false, true], gravity, step),
+            },
+            GameMode::Hidden { stack } => GameMode::Hidden {
+                stack: step_through(&StackVisibility::ALL, stack, step),
+            },
+            other => other,
+        }
+    }
+
+    /// Practice modes, where pieces can be taken back to try again. Puzzles start over after a
+    /// failed attempt instead, since solving one is only recorded by games without undo.
+    pub fn allows_undo(&self) -> bool {
+        match self {
+            GameMode::PerfectClear | GameMode::Setup { .. } | GameMode::Zen { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// How locked blocks are hidden, for modes which hide the stack
+    pub fn stack_visibility(&self) -> Option<StackVisibility> {
+        match *self {
+            GameMode::Hidden { stack } => Some(stack),
+            _ => None,
+        }
+    }
+
+    /// Modes with no way to lose, which only end when the player leaves
+    pub fn is_endless(&self) -> bool {
+        match self {
+            GameMode::Zen { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+fn gravity_name(gravity: bool) -> &'static str {
+    if gravity {
+        "Slow"
+    } else {
+        "Off"
+    }
+}
+
+fn step_through<T: Copy + PartialEq>(values: &[T], current: T, step: i32) -> T {
+    let index = values.iter().position(|v| *v == current).unwrap_or(0) as i32;
+    let new_index = (index + step).max(0).min(values.len() as i32 - 1);
+    values[new_index as usize]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adjust_option() {
+        let sprint = GameMode::Sprint { lines: 40 };
+        assert_eq!(GameMode::Sprint { lines: 100 }, sprint.adjust_option(1));
+        assert_eq!(GameMode::Sprint { lines: 100 }, sprint.adjust_option(5));
+        assert_eq!(GameMode::Sprint { lines: 20 }, sprint.adjust_option(-1));
+
+        let marathon = GameMode::Marathon { start_level: 1 };
+        assert_eq!(marathon, marathon.adjust_option(-1));
+        assert_eq!(
+            GameMode::Marathon { start_level: 3 },
+            marathon.adjust_option(2)
+        );
+
+        assert_eq!(GameMode::Survival, GameMode::Survival.adjust_option(1));
+    }
+
+    #[test]
+    fn code() {
+        for mode in GameMode::ALL.iter() {
+            assert_eq!(Some(*mode), GameMode::from_code(&mode.code()));
+        }
+        assert_eq!(None, GameMode::from_code("sprint"));
+        assert_eq!(None, GameMode::from_code("survival:1"));
+    }
+
+    #[test]
+    fn description() {
+        assert_eq!("Sprint 40L", GameMode::Sprint { lines: 40 }.description());
+        assert_eq!("Ultra 2:00", GameMode::Ultra { seconds: 120 }.description());
+        assert_eq!("Survival", GameMode::Survival.description());
+    }
+}
@@ -0,0 +1,274 @@
+use crate::field::Field;
+use crate::keybindings::Trigger;
+use crate::position::{Coord, Pos, RotateDir, ShiftDir};
+use crate::shapes::Shape;
+use crate::tetromino::Tetromino;
+use alloc::collections::vec_deque::VecDeque;
+use alloc::vec::Vec;
+
+/// Somewhere a piece can lock, and how to get it there from where it spawns
+pub struct Placement {
+    /// The piece as it locks
+    pub tetromino: Tetromino,
+    /// One of the shortest input sequences, ending with a hard drop. A soft drop to the floor
+    /// counts as one input since the key is held, but appears as a `SoftDown` for each row.
+    pub inputs: Vec<Trigger>,
+    /// Whether locking here scores as a T-spin, the same as `ControlledBlocks::is_t_spin`
+    pub t_spin: bool,
+}
+
+/// A piece's position and whether it got there by rotating, which decides T-spins
+#[derive(Copy, Clone, PartialEq)]
+struct Node {
+    tetromino: Tetromino,
+    rotated_last: bool,
+}
+
+/// The tetromino's root can be this far outside the field while its minos are inside
+const ROOT_MARGIN: Coord = 3;
+
+/// Every distinct place `shape` can lock on `field`, reached by shifting, rotating with wall
+/// kicks and soft dropping from the spawn position. Placements covering the same blocks count
+/// once, unless only one of them is a T-spin. Gravity and lock delay are ignored.
+pub fn placements(field: &Field, shape: Shape) -> Vec<Placement> {
+    match Tetromino::try_new(field.spawn_pos(), shape, field) {
+        Some(spawned) => placements_from(field, spawned),
+        None => vec![],
+    }
+}
+
+/// Every distinct place a piece already in play can lock, as for `placements`
+pub fn placements_from(field: &Field, spawned: Tetromino) -> Vec<Placement> {
+    let mut visited = Visited::new(field);
+    let mut queue = VecDeque::new();
+    // Each node is stored with the node it was reached from and the inputs taken
+    let mut nodes: Vec<(Node, Option<usize>, Vec<Trigger>)> = vec![];
+    let start = Node {
+        tetromino: spawned,
+        rotated_last: false,
+    };
+    visited.insert(start);
+    nodes.push((start, None, vec![]));
+    queue.push_back(0);
+
+    let mut result: Vec<Placement> = vec![];
+    let mut locked: Vec<([Pos; 4], bool)> = vec![];
+    while let Some(index) = queue.pop_front() {
+        let node = nodes[index].0;
+
+        let landed = node.tetromino.hard_drop(field);
+        let t_spin =
+            node.rotated_last && landed == node.tetromino && landed.is_t_spin_position(field);
+        let key = (sorted_minos(landed), t_spin);
+        if !locked.contains(&key) {
+            locked.push(key);
+            let mut inputs = path(&nodes, index);
+            inputs.push(Trigger::HardDrop);
+            result.push(Placement {
+                tetromino: landed,
+                inputs: inputs,
+                t_spin: t_spin,
+            });
+        }
+
+        for (next, inputs) in moves(node, landed, field) {
+            if visited.insert(next) {
+                nodes.push((next, Some(index), inputs));
+                queue.push_back(nodes.len() - 1);
+            }
+        }
+    }
+    result
+}
+
+/// The nodes one input away, with the triggers for that input
+fn moves(node: Node, landed: Tetromino, field: &Field) -> Vec<(Node, Vec<Trigger>)> {
+    let t = node.tetromino;
+    let mut result = vec![];
+    for dir in [ShiftDir::Left, ShiftDir::Right].iter() {
+        if let Some(shifted) = t.try_shift(*dir, field) {
+            result.push((
+                Node {
+                    tetromino: shifted,
+                    rotated_last: false,
+                },
+                vec![Trigger::Shift(*dir)],
+            ));
+        }
+    }
+    for dir in [RotateDir::CW, RotateDir::CCW].iter() {
+        if let Some(rotated) = t.try_rotate(*dir, field) {
+            result.push((
+                Node {
+                    tetromino: rotated,
+                    rotated_last: true,
+                },
+                vec![Trigger::Rotate(*dir)],
+            ));
+        }
+    }
+    let rows = t.pos().y - landed.pos().y;
+    if rows > 0 {
+        result.push((
+            Node {
+                tetromino: landed,
+                rotated_last: false,
+            },
+            vec![Trigger::SoftDown; rows as usize],
+        ));
+    }
+    result
+}
+
+fn path(nodes: &[(Node, Option<usize>, Vec<Trigger>)], mut index: usize) -> Vec<Trigger> {
+    let mut steps = vec![];
+    while let (_, Some(previous), inputs) = &nodes[index] {
+        steps.push(inputs);
+        index = *previous;
+    }
+    steps
+        .iter()
+        .rev()
+        .flat_map(|inputs| inputs.iter())
+        .copied()
+        .collect()
+}
+
+/// The blocks a piece covers in a fixed order, so pieces covering the same blocks compare equal
+pub fn sorted_minos(t: Tetromino) -> [Pos; 4] {
+    let mut minos = t.to_minos().positions();
+    minos.sort_by_key(|m| (m.y, m.x));
+    minos
+}
+
+/// Nodes already searched, indexed by rotation, rotated_last and root position
+struct Visited {
+    width: Coord,
+    height: Coord,
+    seen: Vec<bool>,
+}
+
+impl Visited {
+    fn new(field: &Field) -> Visited {
+        let width = field.width() + ROOT_MARGIN;
+        let height = field.game_height() + ROOT_MARGIN;
+        Visited {
+            width: width,
+            height: height,
+            seen: vec![false; (width * height * 8) as usize],
+        }
+    }
+
+    /// Marks a node as seen, returning false if it already was
+    fn insert(&mut self, node: Node) -> bool {
+        let pos = node.tetromino.pos();
+        let layer = node.tetromino.rotation() as Coord * 2 + node.rotated_last as Coord;
+        let index = ((layer * self.height + pos.y + ROOT_MARGIN) * self.width + pos.x + ROOT_MARGIN)
+            as usize;
+        !core::mem::replace(&mut self.seen[index], true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::position::{p, Rotations};
+
+    /// Follows the inputs the way the game would, soft dropping one row per `SoftDown`
+    fn play(field: &Field, shape: Shape, inputs: &[Trigger]) -> Tetromino {
+        let mut t = Tetromino::try_new(field.spawn_pos(), shape, field).unwrap();
+        for input in inputs {
+            t = match input {
+                Trigger::Shift(dir) => t.try_shift(*dir, field).unwrap(),
+                Trigger::Rotate(dir) => t.try_rotate(*dir, field).unwrap(),
+                Trigger::SoftDown => t.try_down(field).unwrap(),
+                Trigger::HardDrop => t.hard_drop(field),
+                Trigger::HoldPiece | Trigger::Undo => panic!("Placements never hold or undo"),
+            };
+        }
+        t
+    }
+
+    #[test]
+    fn empty_field() {
+        let field = Field::new();
+        let counts: Vec<usize> = Shape::ALL
+            .iter()
+            .map(|s| placements(&field, *s).len())
+            .collect();
+        // I, O, J, L, S, Z, T
+        assert_eq!(vec![17, 9, 34, 34, 17, 17, 34], counts);
+
+        for shape in Shape::ALL.iter() {
+            for placement in placements(&field, *shape) {
+                assert_eq!(Some(&Trigger::HardDrop), placement.inputs.last());
+                assert_eq!(placement.tetromino, play(&field, *shape, &placement.inputs));
+                assert!(!placement.t_spin);
+            }
+        }
+    }
+
+    #[test]
+    fn spawn_drop_is_one_input() {
+        let field = Field::new();
+        let placements = placements(&field, Shape::T);
+        assert_eq!(vec![Trigger::HardDrop], placements[0].inputs);
+        assert_eq!(Rotations::Zero, placements[0].tetromino.rotation());
+    }
+
+    #[test]
+    fn t_spin_double() {
+        let field = Field::from_ascii(
+            "
+            ##........
+            #...######
+            ##.#######
+            ",
+        )
+        .unwrap();
+        let spins: Vec<Placement> = placements(&field, Shape::T)
+            .into_iter()
+            .filter(|placement| placement.t_spin)
+            .collect();
+        assert!(!spins.is_empty());
+
+        let slot = spins
+            .iter()
+            .find(|s| s.tetromino.to_minos().contains(p(2, 0)))
+            .expect("No T-spin into the slot");
+        assert_eq!(Rotations::Two, slot.tetromino.rotation());
+        assert_eq!(slot.tetromino, play(&field, Shape::T, &slot.inputs));
+        let before_drop = slot.inputs[slot.inputs.len() - 2];
+        assert!(match before_drop {
+            Trigger::Rotate(_) => true,
+            _ => false,
+        });
+    }
+
+    #[test]
+    fn tuck_under_overhang() {
+        let field = Field::from_ascii(
+            "
+            ######....
+            ..........
+            ",
+        )
+        .unwrap();
+        let under = placements(&field, Shape::I)
+            .into_iter()
+            .find(|placement| placement.tetromino.to_minos().contains(p(0, 0)))
+            .expect("Can't tuck under the overhang");
+        assert!(under.inputs.contains(&Trigger::SoftDown));
+        assert_eq!(under.tetromino, play(&field, Shape::I, &under.inputs));
+    }
+
+    #[test]
+    fn blocked_spawn() {
+        let mut field = Field::new();
+        let spawn = field.spawn_pos();
+        for x in 0..field.width() {
+            field.occupy(spawn + p(x - spawn.x, 2), Shape::I);
+        }
+        assert!(placements(&field, Shape::T).is_empty());
+    }
+}
@@ -0,0 +1,98 @@
+use crate::savegame::{SaveReader, SaveWriter};
+
+/// Tracks attempts in perfect clear practice. An attempt fails once the stack is taller than a
+/// perfect clear can clean up, or once it has used enough pieces for a 4 line perfect clear.
+#[derive(Clone)]
+pub struct PerfectClearPractice {
+    attempts: u32,
+    successes: u32,
+    pieces_this_attempt: u32,
+}
+
+#[derive(PartialEq, Debug)]
+pub enum AttemptResult {
+    Continue,
+    Succeeded,
+    Failed,
+}
+
+const MAX_STACK_HEIGHT: usize = 4;
+const MAX_PIECES: u32 = 10;
+
+impl PerfectClearPractice {
+    pub fn new() -> Self {
+        PerfectClearPractice {
+            attempts: 0,
+            successes: 0,
+            pieces_this_attempt: 0,
+        }
+    }
+
+    /// `stack_height` is the number of rows left occupied once any completed lines are removed.
+    pub fn record_lock(&mut self, perfect_clear: bool, stack_height: usize) -> AttemptResult {
+        self.pieces_this_attempt += 1;
+        let result = if perfect_clear {
+            self.successes += 1;
+            AttemptResult::Succeeded
+        } else if stack_height > MAX_STACK_HEIGHT || self.pieces_this_attempt >= MAX_PIECES {
+            AttemptResult::Failed
+        } else {
+            return AttemptResult::Continue;
+        };
+        self.attempts += 1;
+        self.pieces_this_attempt = 0;
+        result
+    }
+
+    pub fn save(&self, w: &mut SaveWriter) {
+        w.put(self.attempts);
+        w.put(self.successes);
+        w.put(self.pieces_this_attempt);
+    }
+
+    pub fn load(r: &mut SaveReader) -> Option<PerfectClearPractice> {
+        Some(PerfectClearPractice {
+            attempts: r.get()?,
+            successes: r.get()?,
+            pieces_this_attempt: r.get()?,
+        })
+    }
+
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    pub fn successes(&self) -> u32 {
+        self.successes
+    }
+
+    /// Percentage of finished attempts that ended in a perfect clear
+    pub fn success_rate(&self) -> u32 {
+        if self.attempts == 0 {
+            0
+        } else {
+            self.successes * 100 / self.attempts
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attempts() {
+        let mut pc = PerfectClearPractice::new();
+        assert_eq!(AttemptResult::Continue, pc.record_lock(false, 1));
+        assert_eq!(AttemptResult::Succeeded, pc.record_lock(true, 0));
+        assert_eq!(AttemptResult::Failed, pc.record_lock(false, 5));
+        for _ in 0..(MAX_PIECES - 1) {
+            assert_eq!(AttemptResult::Continue, pc.record_lock(false, 2));
+        }
+        assert_eq!(AttemptResult::Failed, pc.record_lock(false, 2));
+
+        assert_eq!(3, pc.attempts());
+        assert_eq!(1, pc.successes());
+        assert_eq!(33, pc.success_rate());
+    }
+}
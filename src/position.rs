@@ -9,7 +9,7 @@ pub struct Pos {
     pub y: Coord,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq, Debug)]
 pub enum ShiftDir {
     Left,
     Right,
@@ -24,7 +24,7 @@ pub enum Rotations {
     Three,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq, Debug)]
 pub enum RotateDir {
     CW,
     CCW,
@@ -0,0 +1,373 @@
+use crate::field::{Field, FieldBlock};
+use crate::position::{p, Coord};
+use crate::savegame::{SaveReader, SaveWriter};
+use crate::shapes::Shape;
+use crate::stats::LineClear;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// A file from static/puzzles. They are built into the game so every platform has them, and
+/// build.rs lists each file there in `PUZZLE_FILES`.
+pub struct PuzzleFile {
+    /// The file name without its extension, stable across versions for saved data
+    pub code: &'static str,
+    pub name: &'static str,
+    text: &'static str,
+}
+
+// Every file in static/puzzles, sorted by name, generated by build.rs
+include!(concat!(env!("OUT_DIR"), "/puzzle_files.rs"));
+
+/// A starting field to solve with a fixed queue of pieces.
+///
+/// Puzzle files start with one `<key> <value>` line for each of `name`, `goal`, `queue`, `hold`
+/// (`yes` or `no`, default `no`) and `pieces` (the piece limit, default the whole queue), then a
+/// `field` line followed by rows in the notation read by `Field::from_ascii`.
+#[derive(Clone)]
+pub struct Puzzle {
+    pub name: String,
+    pub goal: Goal,
+    pub field: Field,
+    pub queue: Vec<Shape>,
+    pub hold: bool,
+    /// How many pieces can be placed before the attempt fails
+    pub piece_limit: u32,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Goal {
+    /// Clear at least this many lines in total
+    Lines(u32),
+    TSpinDouble,
+    PerfectClear,
+    /// Leave no garbage blocks in the field
+    ClearGarbage,
+}
+
+#[derive(PartialEq, Debug)]
+pub enum PuzzleResult {
+    Continue,
+    Solved,
+    Failed,
+}
+
+/// Attempts at one puzzle, starting again from its field whenever one fails
+#[derive(Clone)]
+pub struct PuzzleRun {
+    puzzle: Puzzle,
+    /// Pieces placed this attempt
+    pieces: u32,
+    /// Pieces taken from the queue this attempt, including one in hold
+    dealt: u32,
+    lines: u32,
+    retries: u32,
+    solved: bool,
+}
+
+impl PuzzleFile {
+    /// Parses the file, which every game of it does once when its `PuzzleRun` starts. The
+    /// `every_puzzle_is_solvable` test makes sure each file parses.
+    pub fn puzzle(&self) -> Puzzle {
+        Puzzle::parse(self.text).expect("Invalid puzzle file")
+    }
+
+    pub fn index_of(code: &str) -> Option<usize> {
+        PUZZLE_FILES.iter().position(|f| f.code == code)
+    }
+}
+
+impl Puzzle {
+    pub fn parse(text: &str) -> Option<Puzzle> {
+        let mut lines = text.lines();
+        let mut name = None;
+        let mut goal = None;
+        let mut queue = None;
+        let mut hold = false;
+        let mut piece_limit = None;
+        loop {
+            let line = lines.next()?.trim();
+            let mut parts = line.splitn(2, ' ');
+            let value = parts.nth(1).map(|v| v.trim());
+            match (line.split(' ').next()?, value) {
+                ("", None) => (),
+                ("field", None) => break,
+                ("name", Some(value)) => name = Some(String::from(value)),
+                ("goal", Some(value)) => goal = Some(Goal::parse(value)?),
+                ("queue", Some(value)) => {
+                    queue = Some(
+                        value
+                            .chars()
+                            .map(Shape::from_letter)
+                            .collect::<Option<Vec<Shape>>>()?,
+                    )
+                }
+                ("hold", Some("yes")) => hold = true,
+                ("hold", Some("no")) => hold = false,
+                ("pieces", Some(value)) => piece_limit = Some(value.parse().ok()?),
+                _ => return None,
+            }
+        }
+        let queue: Vec<Shape> = queue?;
+        let piece_limit = piece_limit.unwrap_or(queue.len() as u32);
+        if piece_limit == 0 || piece_limit as usize > queue.len() {
+            return None;
+        }
+        let rows: Vec<&str> = lines.collect();
+        Some(Puzzle {
+            name: name?,
+            goal: goal?,
+            field: Field::from_ascii(&rows.join("\n"))?,
+            queue: queue,
+            hold: hold,
+            piece_limit: piece_limit,
+        })
+    }
+}
+
+impl Goal {
+    fn parse(text: &str) -> Option<Goal> {
+        let mut words = text.split_whitespace();
+        let goal = match words.next()? {
+            "lines" => Goal::Lines(words.next()?.parse().ok()?),
+            "tsd" => Goal::TSpinDouble,
+            "pc" => Goal::PerfectClear,
+            "garbage" => Goal::ClearGarbage,
+            _ => return None,
+        };
+        match words.next() {
+            Some(_) => None,
+            None => Some(goal),
+        }
+    }
+
+    pub fn description(&self) -> String {
+        match self {
+            Goal::Lines(1) => String::from("Clear a line"),
+            Goal::Lines(lines) => format!("Clear {} lines", lines),
+            Goal::TSpinDouble => String::from("T-spin double"),
+            Goal::PerfectClear => String::from("Perfect clear"),
+            Goal::ClearGarbage => String::from("Clear all garbage"),
+        }
+    }
+}
+
+impl PuzzleRun {
+    pub fn new(index: usize) -> PuzzleRun {
+        PuzzleRun {
+            puzzle: PUZZLE_FILES[index].puzzle(),
+            pieces: 0,
+            dealt: 0,
+            lines: 0,
+            retries: 0,
+            solved: false,
+        }
+    }
+
+    pub fn puzzle(&self) -> &Puzzle {
+        &self.puzzle
+    }
+
+    /// Takes the next piece from the queue, unless every one has been dealt
+    pub fn deal(&mut self) -> Option<()> {
+        if self.remaining() == 0 {
+            return None;
+        }
+        self.dealt += 1;
+        Some(())
+    }
+
+    /// Pieces in the queue which haven't been dealt yet
+    pub fn remaining(&self) -> usize {
+        self.puzzle.queue.len() - self.dealt as usize
+    }
+
+    /// Checks the goal once a piece has locked in `field`, with `lines` about to be cleared
+    pub fn record_lock(
+        &mut self,
+        field: &Field,
+        lines: &[Coord],
+        clear: LineClear,
+    ) -> PuzzleResult {
+        self.pieces += 1;
+        self.lines += clear.lines;
+        let met = match self.puzzle.goal {
+            Goal::Lines(goal) => self.lines >= goal,
+            Goal::TSpinDouble => clear.t_spin && clear.lines == 2,
+            Goal::PerfectClear => clear.perfect_clear,
+            Goal::ClearGarbage => !has_garbage(field, lines),
+        };
+        if met {
+            self.solved = true;
+            PuzzleResult::Solved
+        } else if self.pieces >= self.puzzle.piece_limit {
+            PuzzleResult::Failed
+        } else {
+            PuzzleResult::Continue
+        }
+    }
+
+    /// Starts another attempt, once the field and queue are reset
+    pub fn retry(&mut self) {
+        self.pieces = 0;
+        self.dealt = 0;
+        self.lines = 0;
+        self.retries += 1;
+    }
+
+    pub fn is_solved(&self) -> bool {
+        self.solved
+    }
+
+    pub fn pieces(&self) -> u32 {
+        self.pieces
+    }
+
+    pub fn retries(&self) -> u32 {
+        self.retries
+    }
+
+    pub fn save(&self, w: &mut SaveWriter) {
+        w.put(self.pieces);
+        w.put(self.dealt);
+        w.put(self.lines);
+        w.put(self.retries);
+        w.put(self.solved);
+    }
+
+    pub fn load(r: &mut SaveReader, index: usize) -> Option<PuzzleRun> {
+        let run = PuzzleRun {
+            pieces: r.get()?,
+            dealt: r.get()?,
+            lines: r.get()?,
+            retries: r.get()?,
+            solved: r.get()?,
+            ..PuzzleRun::new(index)
+        };
+        if run.dealt as usize > run.puzzle.queue.len() {
+            return None;
+        }
+        Some(run)
+    }
+}
+
+/// Whether any garbage is left outside the rows being cleared
+fn has_garbage(field: &Field, lines: &[Coord]) -> bool {
+    (0..field.game_height())
+        .filter(|y| !lines.contains(y))
+        .any(|y| (0..field.width()).any(|x| field.block(p(x, y)) == FieldBlock::Garbage))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::movegen::placements;
+
+    /// Whether the rest of the queue from `next` can reach the goal, trying every placement of
+    /// the piece in play and, where the puzzle allows it, of the piece swapped in from hold
+    fn solvable(run: &PuzzleRun, field: &Field, next: usize, hold: Option<Shape>) -> bool {
+        let queue = &run.puzzle.queue;
+        let mut choices = vec![];
+        if next < queue.len() {
+            choices.push((queue[next], hold, next + 1));
+            if run.puzzle.hold {
+                match hold {
+                    Some(held) => choices.push((held, Some(queue[next]), next + 1)),
+                    None if next + 1 < queue.len() => {
+                        choices.push((queue[next + 1], Some(queue[next]), next + 2))
+                    }
+                    None => (),
+                }
+            }
+        }
+        choices.into_iter().any(|(shape, hold, next)| {
+            placements(field, shape).into_iter().any(|placement| {
+                let mut field = field.clone();
+                let mut run = run.clone();
+                placement.tetromino.to_minos().apply_to_field(&mut field);
+                let lines = field.find_lines();
+                let clear = LineClear {
+                    lines: lines.len() as u32,
+                    t_spin: placement.t_spin,
+                    perfect_clear: !lines.is_empty() && field.is_clear_except(&lines),
+                };
+                match run.record_lock(&field, &lines, clear) {
+                    PuzzleResult::Solved => true,
+                    PuzzleResult::Failed => false,
+                    PuzzleResult::Continue => {
+                        field.remove_lines(&lines);
+                        solvable(&run, &field, next, hold)
+                    }
+                }
+            })
+        })
+    }
+
+    #[test]
+    fn every_puzzle_is_solvable() {
+        for (index, file) in PUZZLE_FILES.iter().enumerate() {
+            let run = PuzzleRun::new(index);
+            assert!(
+                solvable(&run, &run.puzzle.field, 0, None),
+                "{} can't be solved",
+                file.code
+            );
+            assert_eq!(Some(index), PuzzleFile::index_of(file.code));
+            assert_eq!(file.name, run.puzzle.name);
+        }
+    }
+
+    #[test]
+    fn parse() {
+        let puzzle = Puzzle::parse(
+            "
+            name Test
+            goal lines 3
+            queue IOT
+            field
+            ##..######
+            ",
+        )
+        .unwrap();
+        assert_eq!("Test", puzzle.name);
+        assert_eq!(Goal::Lines(3), puzzle.goal);
+        assert_eq!(vec![Shape::I, Shape::O, Shape::T], puzzle.queue);
+        assert!(!puzzle.hold);
+        assert_eq!(3, puzzle.piece_limit);
+        assert_eq!(FieldBlock::Garbage, puzzle.field.block(p(0, 0)));
+
+        assert!(Puzzle::parse("name Test\ngoal tsd\nqueue T\npieces 2\nfield\n").is_none());
+        assert!(Puzzle::parse("name Test\ngoal spin\nqueue T\nfield\n").is_none());
+        assert!(Puzzle::parse("name Test\ngoal tsd\nqueue T\n").is_none());
+    }
+
+    #[test]
+    fn piece_limit() {
+        let mut run = PuzzleRun::new(PuzzleFile::index_of("dig-out").unwrap());
+        let field = run.puzzle.field.clone();
+        let no_clear = LineClear {
+            lines: 0,
+            t_spin: false,
+            perfect_clear: false,
+        };
+        assert_eq!(Some(()), run.deal());
+        assert_eq!(
+            PuzzleResult::Continue,
+            run.record_lock(&field, &[], no_clear)
+        );
+        assert_eq!(PuzzleResult::Failed, run.record_lock(&field, &[], no_clear));
+        assert_eq!(Some(()), run.deal());
+        assert_eq!(None, run.deal());
+
+        run.retry();
+        assert_eq!((0, 1), (run.pieces(), run.retries()));
+        assert_eq!(2, run.remaining());
+        let cleared = LineClear {
+            lines: 3,
+            ..no_clear
+        };
+        assert_eq!(
+            PuzzleResult::Solved,
+            run.record_lock(&field, &[0, 1, 2], cleared)
+        );
+    }
+}
@@ -1,11 +1,17 @@
+use crate::rng::Rng;
+use crate::savegame::{SaveReader, SaveWriter};
 use crate::shapes::Shape;
 use alloc::collections::vec_deque::VecDeque;
+use alloc::string::String;
 use alloc::vec::Vec;
-use getrandom;
 
+#[derive(Clone)]
 pub struct RandomBag {
     remaining: Vec<Shape>,
     upcoming: VecDeque<Shape>,
+    /// Shapes to deal after the previews, before any random ones
+    queued: VecDeque<Shape>,
+    rng: Rng,
 }
 
 const NUM_SHAPES: usize = 7;
@@ -23,10 +29,26 @@ const ALL_SHAPES: [Shape; NUM_SHAPES] = [
 const NUM_PREVIEWS: usize = 6;
 
 impl RandomBag {
-    pub fn new() -> RandomBag {
+    pub fn new(seed: u64) -> RandomBag {
         let mut result = RandomBag {
             remaining: ALL_SHAPES.to_vec(),
             upcoming: VecDeque::with_capacity(NUM_PREVIEWS),
+            queued: VecDeque::new(),
+            rng: Rng::new(seed),
+        };
+        while result.upcoming.len() < NUM_PREVIEWS {
+            result.fill_upcoming();
+        }
+        result
+    }
+
+    /// A bag which deals all of `queue` first, then continues randomly
+    pub fn with_queue(seed: u64, queue: &[Shape]) -> RandomBag {
+        let mut result = RandomBag {
+            remaining: ALL_SHAPES.to_vec(),
+            upcoming: queue.iter().copied().take(NUM_PREVIEWS).collect(),
+            queued: queue.iter().copied().skip(NUM_PREVIEWS).collect(),
+            rng: Rng::new(seed),
         };
         while result.upcoming.len() < NUM_PREVIEWS {
             result.fill_upcoming();
@@ -45,19 +67,60 @@ impl RandomBag {
             .expect("Expected upcoming to have values")
     }
 
+    /// The shapes left in the current bag, then the upcoming queue, then any queued shapes
+    /// after it, then the random state
+    pub fn save(&self, w: &mut SaveWriter) {
+        w.label("bag");
+        w.put(shape_letters(self.remaining.iter()));
+        w.put(shape_letters(self.upcoming.iter()));
+        w.put(self.queued.len());
+        for shape in self.queued.iter() {
+            w.put_shape(Some(*shape));
+        }
+        self.rng.save(w);
+    }
+
+    pub fn load(r: &mut SaveReader) -> Option<RandomBag> {
+        r.label("bag")?;
+        let remaining: Vec<Shape> = parse_shapes(r.token()?)?;
+        let upcoming: VecDeque<Shape> = parse_shapes(r.token()?)?;
+        if remaining.is_empty() || upcoming.len() != NUM_PREVIEWS {
+            return None;
+        }
+        let count: usize = r.get()?;
+        let mut queued = VecDeque::new();
+        for _ in 0..count {
+            queued.push_back(r.get_shape()??);
+        }
+        Some(RandomBag {
+            remaining: remaining,
+            upcoming: upcoming,
+            queued: queued,
+            rng: Rng::load(r)?,
+        })
+    }
+
     fn fill_upcoming(&mut self) {
-        let mut rand_byte: [u8; 1] = [0];
-        getrandom::getrandom(&mut rand_byte).unwrap();
-        self.upcoming.push_back(
-            self.remaining
-                .remove((rand_byte[0] as usize) % self.remaining.len()),
-        );
+        if let Some(shape) = self.queued.pop_front() {
+            self.upcoming.push_back(shape);
+            return;
+        }
+        let index = self.rng.below(self.remaining.len());
+        self.upcoming.push_back(self.remaining.remove(index));
         if self.remaining.is_empty() {
             self.remaining = ALL_SHAPES.to_vec();
         }
     }
 }
 
+fn shape_letters<'a, I: Iterator<Item = &'a Shape>>(shapes: I) -> String {
+    shapes.map(|s| s.letter()).collect()
+}
+
+fn parse_shapes<C: core::iter::FromIterator<Shape>>(letters: &str) -> Option<C> {
+    letters.chars().map(Shape::from_letter).collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -65,7 +128,7 @@ mod tests {
 
     #[test]
     fn select_all_from_bag() {
-        let mut r = RandomBag::new();
+        let mut r = RandomBag::new(Rng::random_seed());
         let mut seen_shapes = vec![];
         for _ in 0..NUM_SHAPES {
             seen_shapes.push(r.take_next())
@@ -73,4 +136,51 @@ mod tests {
 
         assert_that!(&seen_shapes, contains(ALL_SHAPES.to_vec()).exactly());
     }
+
+    #[test]
+    fn save_load() {
+        let mut a = RandomBag::new(99);
+        a.take_next();
+        let mut w = SaveWriter::new();
+        a.save(&mut w);
+        let text = w.finish();
+
+        let mut b = RandomBag::load(&mut SaveReader::new(&text)).unwrap();
+        for _ in 0..(NUM_SHAPES * 2) {
+            assert_eq!(a.take_next(), b.take_next());
+        }
+    }
+
+    #[test]
+    fn seeded_order() {
+        let mut a = RandomBag::new(1234);
+        let mut b = RandomBag::new(1234);
+        for _ in 0..(NUM_SHAPES * 3) {
+            assert_eq!(a.take_next(), b.take_next());
+        }
+    }
+
+    #[test]
+    fn given_queue_first() {
+        let mut r = RandomBag::with_queue(5, &[Shape::T, Shape::T, Shape::I]);
+        assert_eq!(NUM_PREVIEWS, r.previews().len());
+        assert_eq!(Shape::T, r.take_next());
+        assert_eq!(Shape::T, r.take_next());
+        assert_eq!(Shape::I, r.take_next());
+    }
+
+    #[test]
+    fn long_queue() {
+        let queue = [Shape::O; NUM_PREVIEWS + 3];
+        let mut a = RandomBag::with_queue(5, &queue);
+        a.take_next();
+        let mut w = SaveWriter::new();
+        a.save(&mut w);
+        let mut b = RandomBag::load(&mut SaveReader::new(&w.finish())).unwrap();
+        for _ in 1..queue.len() {
+            assert_eq!(Shape::O, a.take_next());
+            assert_eq!(Shape::O, b.take_next());
+        }
+        assert_eq!(a.take_next(), b.take_next());
+    }
 }
@@ -1,7 +1,15 @@
-use crate::position::Coord;
-use crate::position::Pos;
+use crate::block_render::{BlockRenderInstructions, DrawBlockType, RenderBlockInfo};
+use crate::field::PlayingFieldRenderBlocksInstructions;
+use crate::gamestate::{GameCondition, RenderInfo};
+use crate::menu::MenuView;
+use crate::mode::GameMode;
+use crate::resources::Resources;
+use crate::settings::Skin;
 use crate::shapes::Shape;
-use crate::Game;
+use crate::time::format_duration;
+use crate::{Game, GameResults};
+use alloc::string::String;
+use core::time::Duration;
 
 use quicksilver::{
     geom::{Rectangle, Transform, Vector},
@@ -16,24 +24,49 @@ fn bg_color() -> Color {
     Color::from_rgba(0x33, 0x33, 0x33, 1.0)
 }
 
-pub enum DrawBlockType {
-    Empty,
-    Occupied(Shape),
-    OutOfPlay,
-    GhostPiece(Shape),
-    ClearingLine,
+fn image_for_mino<'a>(images: &'a Images, b: &DrawBlockType, skin: Skin) -> Background<'a> {
+    match (b, skin) {
+        (DrawBlockType::Occupied(shape), Skin::Flat) => Background::Col(color_for_shape(*shape)),
+        (DrawBlockType::Faded(shape, opacity), Skin::Flat) => {
+            Background::Col(color_for_shape(*shape).with_alpha(*opacity))
+        }
+        (DrawBlockType::GhostPiece(shape), Skin::Flat) => {
+            Background::Col(color_for_shape(*shape).with_alpha(0.4))
+        }
+        (DrawBlockType::Hint(shape), Skin::Flat) => {
+            Background::Col(color_for_shape(*shape).with_alpha(0.15))
+        }
+        (DrawBlockType::Target(shape), Skin::Flat) => {
+            Background::Col(color_for_shape(*shape).with_alpha(0.3))
+        }
+        (b, _) => image_for_textured_mino(images, b),
+    }
 }
 
-fn image_for_mino<'a>(images: &'a Images, b: &DrawBlockType) -> Background<'a> {
+fn image_for_textured_mino<'a>(images: &'a Images, b: &DrawBlockType) -> Background<'a> {
     match b {
         DrawBlockType::Empty => Background::Img(&images.empty_mino),
         DrawBlockType::Occupied(shape) => Background::Img(image_for_shape(images, *shape)),
+        DrawBlockType::Faded(shape, opacity) => Background::Blended(
+            image_for_shape(images, *shape),
+            Color::WHITE.with_alpha(*opacity),
+        ),
         DrawBlockType::GhostPiece(shape) => Background::Blended(
             image_for_shape(images, *shape),
             Color::from_rgba(0x90, 0x90, 0x90, 1.0),
         ),
+        DrawBlockType::Hint(shape) => {
+            Background::Blended(&images.empty_mino, color_for_shape(*shape))
+        }
+        DrawBlockType::Target(shape) => Background::Blended(
+            image_for_shape(images, *shape),
+            Color::from_rgba(0x50, 0x50, 0x50, 1.0),
+        ),
         DrawBlockType::OutOfPlay => Background::Col(bg_color()),
         DrawBlockType::ClearingLine => Background::Col(Color::from_rgba(0x80, 0x80, 0x80, 1.0)),
+        DrawBlockType::Garbage => {
+            Background::Blended(&images.empty_mino, Color::from_rgba(0x60, 0x60, 0x60, 1.0))
+        }
     }
 }
 
@@ -49,19 +82,16 @@ fn image_for_shape<'a>(images: &'a Images, shape: Shape) -> &'a Image {
     }
 }
 
-pub struct RenderBlockInfo {
-    pub pos: Pos,
-    pub block_type: DrawBlockType,
-}
-
-pub trait BlockRenderInstructions<I>
-where
-    I: Iterator<Item = RenderBlockInfo>,
-{
-    fn blocks(&self) -> I;
-
-    fn height_blocks(&self) -> Coord;
-    fn width_blocks(&self) -> Coord;
+fn color_for_shape(shape: Shape) -> Color {
+    match shape {
+        Shape::I => Color::from_rgba(0x00, 0xc8, 0xe0, 1.0),
+        Shape::O => Color::from_rgba(0xf0, 0xd0, 0x00, 1.0),
+        Shape::T => Color::from_rgba(0xa0, 0x30, 0xd0, 1.0),
+        Shape::S => Color::from_rgba(0x40, 0xc0, 0x40, 1.0),
+        Shape::Z => Color::from_rgba(0xe0, 0x30, 0x30, 1.0),
+        Shape::J => Color::from_rgba(0x30, 0x50, 0xe0, 1.0),
+        Shape::L => Color::from_rgba(0xf0, 0x90, 0x20, 1.0),
+    }
 }
 
 fn render_blocks<T, I>(
@@ -69,6 +99,7 @@ fn render_blocks<T, I>(
     scale_transform: Transform,
     position_transform: Transform,
     images: &Images,
+    skin: Skin,
     window: &mut Window,
 ) where
     I: Iterator<Item = RenderBlockInfo>,
@@ -84,37 +115,97 @@ fn render_blocks<T, I>(
                     ),
                 scale_transform * Vector::new(1, 1),
             ),
-            image_for_mino(images, &block.block_type),
+            image_for_mino(images, &block.block_type, skin),
             Transform::IDENTITY,
             0,
         );
     }
 }
 
-const BLOCK_SIZE_RATIO: f32 = 0.04;
+/// The share of the screen height a field's visible rows fill
+const FIELD_HEIGHT_RATIO: f32 = 0.88;
+/// The most of the screen width a field may take, leaving room for the hold piece and previews
+const FIELD_WIDTH_RATIO: f32 = 0.38;
 
-pub fn draw_field(window: &mut Window, game: &Game) -> Result<()> {
-    window.clear(bg_color())?;
+/// Where an opponent's field goes, to the right of the previews and above the score
+const OPPONENT_FIELD_CENTER: (f32, f32) = (0.88, 0.35);
+/// The most of the screen width and height an opponent's field may take
+const OPPONENT_FIELD_RATIOS: (f32, f32) = (0.16, 0.5);
 
-    let screen_size = &game.screen_size;
-    let full_height = screen_size.y;
-    let block_size = BLOCK_SIZE_RATIO * full_height;
-
-    let render_info = game.state.render_info();
+/// Draws a field as large as fits in an area, with the area's size and center given as shares
+/// of the screen
+fn render_field(
+    window: &mut Window,
+    field: &PlayingFieldRenderBlocksInstructions,
+    images: &Images,
+    skin: Skin,
+    screen_size: Vector,
+    (center_x, center_y): (f32, f32),
+    (width_ratio, height_ratio): (f32, f32),
+) {
+    let width_blocks = field.width_blocks() as f32;
+    let height_blocks = field.height_blocks() as f32;
+    let block_size = (height_ratio * screen_size.y / height_blocks)
+        .min(width_ratio * screen_size.x / width_blocks);
 
     let scale_transform = Transform::scale((block_size, block_size));
     let position_transform = Transform::translate((
-        screen_size.x * 0.5 - (0.5 * block_size * render_info.playing_field.width_blocks() as f32),
-        screen_size.y * 0.5 - (0.5 * block_size * render_info.playing_field.height_blocks() as f32),
+        screen_size.x * center_x - (0.5 * block_size * width_blocks),
+        screen_size.y * center_y - (0.5 * block_size * height_blocks),
     )) * scale_transform;
 
     render_blocks(
-        &render_info.playing_field,
+        field,
         scale_transform,
         position_transform,
-        &game.resources.images,
+        images,
+        skin,
         window,
     );
+}
+
+pub fn draw_field(window: &mut Window, game: &Game, skin: Skin) -> Result<()> {
+    window.clear(bg_color())?;
+    draw_game(
+        window,
+        &game.resources,
+        game.screen_size,
+        &game.state.render_info(),
+        skin,
+    )
+}
+
+/// Draws a game over whatever is already on screen, so it can be a backdrop to other text
+fn draw_game(
+    window: &mut Window,
+    resources: &Resources,
+    screen_size: Vector,
+    render_info: &RenderInfo,
+    skin: Skin,
+) -> Result<()> {
+    let full_height = screen_size.y;
+
+    render_field(
+        window,
+        &render_info.playing_field,
+        &resources.images,
+        skin,
+        screen_size,
+        (0.5, 0.5),
+        (FIELD_WIDTH_RATIO, FIELD_HEIGHT_RATIO),
+    );
+
+    if let Some(opponent) = &render_info.opponent {
+        render_field(
+            window,
+            &opponent.playing_field,
+            &resources.images,
+            skin,
+            screen_size,
+            OPPONENT_FIELD_CENTER,
+            OPPONENT_FIELD_RATIOS,
+        );
+    }
 
     let preview_block_size = 0.03 * full_height;
     let preview_scale_transform = Transform::scale((preview_block_size, preview_block_size));
@@ -125,7 +216,8 @@ pub fn draw_field(window: &mut Window, game: &Game) -> Result<()> {
             &*shape,
             preview_scale_transform,
             preview_root_position * Transform::translate((0, 3 * i as i32)),
-            &game.resources.images,
+            &resources.images,
+            skin,
             window,
         );
     }
@@ -138,19 +230,19 @@ pub fn draw_field(window: &mut Window, game: &Game) -> Result<()> {
             &hold_piece,
             preview_scale_transform,
             hold_piece_position,
-            &game.resources.images,
+            &resources.images,
+            skin,
             window,
         );
     }
 
     let style = FontStyle::new(24.0, Color::BLACK);
-    let score_image = game.resources.font.render(
-        &format!(
-            "Lines: {}\nLevel: {}",
-            render_info.cleared_lines, render_info.level
-        ),
-        &style,
-    )?;
+    let text = format!(
+        "{}\nFaults: {}",
+        score_text(render_info),
+        render_info.stats.finesse_faults
+    );
+    let score_image = resources.font.render(&text, &style)?;
 
     use quicksilver::geom::Shape;
     window.draw(
@@ -160,5 +252,219 @@ pub fn draw_field(window: &mut Window, game: &Game) -> Result<()> {
         Background::Img(&score_image),
     );
 
+    if let Some(banner) = render_info.banner {
+        draw_centered_text(
+            window,
+            resources,
+            banner,
+            (screen_size.x * 0.5, screen_size.y * 0.15),
+        )?;
+    }
+
+    Ok(())
+}
+
+pub fn draw_results(window: &mut Window, game: &Game, results: &GameResults) -> Result<()> {
+    window.clear(bg_color())?;
+
+    let summary = game.state.summary();
+    let stats = &summary.stats;
+    let heading = match results.outcome {
+        GameCondition::Lost(reason) => format!("GAME OVER - {}", reason.description()),
+        _ => String::from("COMPLETE"),
+    };
+    let mut text = format!(
+        "{}\n\n\
+         Mode: {}\n\
+         Score: {}\n\
+         Lines: {}\n\
+         Time: {}\n\
+         Pieces: {} ({:.2} PPS)\n\
+         Keys: {} ({:.2} KPP)\n\
+         Tetrises: {}   T-Spins: {}   Perfect Clears: {}\n\
+         Finesse faults: {}\n\
+         Seed: {}\n\n\
+         R - Retry seed   N - New game   S - Save replay   M - Menu",
+        heading,
+        summary.mode.description(),
+        stats.score,
+        stats.lines,
+        format_duration(summary.elapsed),
+        stats.pieces,
+        stats.pieces_per_second(summary.elapsed),
+        stats.key_presses,
+        stats.keys_per_piece(),
+        stats.tetrises,
+        stats.t_spins,
+        stats.perfect_clears,
+        stats.finesse_faults,
+        summary.seed,
+    );
+    match results.high_score_rank {
+        Some(0) => text += "\nNEW PERSONAL BEST!",
+        Some(rank) => text += &format!("\nNew high score: #{}", rank + 1),
+        None => (),
+    }
+    if let Some(message) = &results.replay_message {
+        text += &format!("\n{}", message);
+    }
+    if let Some(name) = &results.bot_name {
+        text += &format!("\nPlayed by {}", name);
+    }
+
+    draw_centered_text(
+        window,
+        &game.resources,
+        &text,
+        (game.screen_size.x * 0.5, game.screen_size.y * 0.5),
+    )
+}
+
+fn draw_centered_text(
+    window: &mut Window,
+    resources: &Resources,
+    text: &str,
+    center: (f32, f32),
+) -> Result<()> {
+    let image = resources
+        .font
+        .render(text, &FontStyle::new(32.0, Color::WHITE))?;
+
+    use quicksilver::geom::Shape;
+    window.draw(&image.area().with_center(center), Background::Img(&image));
     Ok(())
 }
+
+/// Draws the seconds left before play resumes over the field
+pub fn draw_countdown(window: &mut Window, game: &Game, remaining: u32) -> Result<()> {
+    draw_centered_text(
+        window,
+        &game.resources,
+        &format!("{}", remaining),
+        (game.screen_size.x * 0.5, game.screen_size.y * 0.5),
+    )
+}
+
+pub fn draw_menu(window: &mut Window, resources: &Resources, view: &MenuView) -> Result<()> {
+    window.clear(bg_color())?;
+    draw_menu_text(window, resources, view)
+}
+
+/// Draws the title over a game the AI is playing
+pub fn draw_title(
+    window: &mut Window,
+    resources: &Resources,
+    view: &MenuView,
+    demo: &RenderInfo,
+    skin: Skin,
+) -> Result<()> {
+    window.clear(bg_color())?;
+    let screen_size = window.screen_size();
+    draw_game(window, resources, screen_size, demo, skin)?;
+    draw_menu_text(window, resources, view)
+}
+
+fn draw_menu_text(window: &mut Window, resources: &Resources, view: &MenuView) -> Result<()> {
+    let mut text = format!("{}\n", view.title);
+    for (i, item) in view.items.iter().enumerate() {
+        let marker = if view.selected == Some(i) { ">" } else { " " };
+        text += &format!("\n{} {}", marker, item);
+    }
+    text += &format!("\n\n{}", view.help);
+
+    let screen_size = window.screen_size();
+    draw_centered_text(
+        window,
+        resources,
+        &text,
+        (screen_size.x * 0.5, screen_size.y * 0.5),
+    )
+}
+
+fn score_text(render_info: &RenderInfo) -> String {
+    match render_info.mode {
+        GameMode::Marathon { .. } | GameMode::Hidden { .. } => format!(
+            "Score: {}\nLines: {}\nLevel: {}",
+            render_info.stats.score, render_info.stats.lines, render_info.level
+        ),
+        GameMode::Sprint { lines } => format!(
+            "Lines: {}/{}\nTime: {}",
+            render_info.stats.lines,
+            lines,
+            format_duration(render_info.elapsed)
+        ),
+        GameMode::Ultra { seconds } => format!(
+            "Score: {}\nLines: {}\nTime left: {}",
+            render_info.stats.score,
+            render_info.stats.lines,
+            format_duration(
+                Duration::from_secs(seconds as u64)
+                    .checked_sub(render_info.elapsed)
+                    .unwrap_or(Duration::from_secs(0))
+            )
+        ),
+        GameMode::Survival => format!(
+            "Lines: {}\nTime: {}\nGarbage: {}",
+            render_info.stats.lines,
+            format_duration(render_info.elapsed),
+            render_info.pending_garbage.unwrap_or(0)
+        ),
+        GameMode::Versus { .. } => format!(
+            "Sent: {}\nTime: {}\nGarbage: {}",
+            render_info.stats.attack,
+            format_duration(render_info.elapsed),
+            render_info.pending_garbage.unwrap_or(0)
+        ),
+        GameMode::PerfectClear => match render_info.perfect_clear_practice {
+            Some(practice) => format!(
+                "PCs: {}/{}\nRate: {}%",
+                practice.successes(),
+                practice.attempts(),
+                practice.success_rate()
+            ),
+            None => String::new(),
+        },
+        GameMode::FinesseTrainer => match render_info.finesse_drill {
+            Some(drill) => {
+                let (correct, attempts) = drill.target_record();
+                format!(
+                    "Correct: {}/{}\nAccuracy: {}%\nThis one: {}/{}",
+                    drill.correct(),
+                    drill.attempts(),
+                    drill.accuracy(),
+                    correct,
+                    attempts
+                )
+            }
+            None => String::new(),
+        },
+        GameMode::Puzzle { .. } => match render_info.puzzle_run {
+            Some(run) => format!(
+                "{}\nPieces: {}/{}\nRetries: {}",
+                run.puzzle().goal.description(),
+                run.pieces(),
+                run.puzzle().piece_limit,
+                run.retries()
+            ),
+            None => String::new(),
+        },
+        GameMode::Zen { .. } => match render_info.zen_totals {
+            Some(totals) => format!(
+                "Lines: {}\nTime: {}\nAll games: {}",
+                render_info.stats.lines,
+                format_duration(render_info.elapsed),
+                totals.lines
+            ),
+            None => String::new(),
+        },
+        GameMode::Setup { .. } => match render_info.setup_practice {
+            Some(practice) => format!(
+                "{}\nBuilt: {}/{}",
+                practice.setup().name,
+                practice.successes(),
+                practice.attempts()
+            ),
+            None => String::new(),
+        },
+    }
+}
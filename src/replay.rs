@@ -0,0 +1,115 @@
+use crate::keybindings::Trigger;
+use crate::mode::GameMode;
+use crate::position::{RotateDir, ShiftDir};
+use crate::savegame::{SaveError, SaveReader, SaveWriter, Storage};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::time::Duration;
+
+/// Every input of a game, along with the mode and seed it was played with.
+pub struct Replay {
+    mode: GameMode,
+    seed: u64,
+    inputs: Vec<(Duration, Trigger)>,
+}
+
+const FORMAT_VERSION: u32 = 1;
+
+impl Replay {
+    pub fn new(mode: GameMode, seed: u64) -> Self {
+        Replay {
+            mode: mode,
+            seed: seed,
+            inputs: vec![],
+        }
+    }
+
+    /// Records an input `time` after the start of the game.
+    pub fn record(&mut self, time: Duration, trigger: Trigger) {
+        self.inputs.push((time, trigger));
+    }
+
+    /// A header of format version, mode and seed, followed by one line of
+    /// `<milliseconds> <input>` per input.
+    pub fn encode(&self) -> String {
+        let mut result = format!(
+            "blocks-replay {}\n{}\n{}\n",
+            FORMAT_VERSION,
+            self.mode.description(),
+            self.seed
+        );
+        for (time, trigger) in self.inputs.iter() {
+            result += &format!("{} {}\n", time.as_millis(), trigger_code(*trigger));
+        }
+        result
+    }
+
+    /// Writes the inputs so far into a saved game; the mode and seed are saved with the game
+    pub fn save_inputs(&self, w: &mut SaveWriter) {
+        w.label("inputs");
+        w.put(self.inputs.len());
+        for (time, trigger) in self.inputs.iter() {
+            w.put_duration(*time);
+            w.put(trigger_code(*trigger));
+        }
+    }
+
+    pub fn load_inputs(mode: GameMode, seed: u64, r: &mut SaveReader) -> Option<Replay> {
+        r.label("inputs")?;
+        let count: usize = r.get()?;
+        let mut replay = Replay::new(mode, seed);
+        for _ in 0..count {
+            let time = r.get_duration()?;
+            replay.record(time, trigger_from_code(r.token()?)?);
+        }
+        Some(replay)
+    }
+
+    /// Saves under a profile of its own, so it isn't replaced by the next saved replay
+    pub fn save_as(&self, storage: &dyn Storage, profile: &str) -> Result<(), SaveError> {
+        storage.store(profile, &self.encode())
+    }
+}
+
+/// The profile a game played at `date` with `seed` is saved under, so saving a replay doesn't
+/// replace one saved before it
+pub fn profile_name(date: u64, seed: u64) -> String {
+    format!("replay-{}-{}", date, seed)
+}
+
+fn trigger_code(trigger: Trigger) -> &'static str {
+    match trigger {
+        Trigger::Shift(ShiftDir::Left) => "left",
+        Trigger::Shift(ShiftDir::Right) => "right",
+        Trigger::SoftDown => "soft",
+        Trigger::Rotate(RotateDir::CW) => "cw",
+        Trigger::Rotate(RotateDir::CCW) => "ccw",
+        Trigger::HardDrop => "hard",
+        Trigger::HoldPiece => "hold",
+        Trigger::Undo => "undo",
+    }
+}
+
+fn trigger_from_code(code: &str) -> Option<Trigger> {
+    Trigger::ALL
+        .iter()
+        .copied()
+        .find(|t| trigger_code(*t) == code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode() {
+        let mut replay = Replay::new(GameMode::Marathon { start_level: 1 }, 77);
+        replay.record(Duration::from_millis(5), Trigger::Shift(ShiftDir::Left));
+        replay.record(Duration::from_millis(1250), Trigger::HardDrop);
+
+        assert_eq!(
+            "blocks-replay 1\nMarathon L1\n77\n5 left\n1250 hard\n",
+            replay.encode()
+        );
+    }
+}
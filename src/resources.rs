@@ -1,6 +1,8 @@
+use crate::gamestate::SoundEffect;
 use quicksilver::{
     graphics::{Font, Image},
-    Error, Future,
+    sound::Sound,
+    Error, Future, Result,
 };
 
 pub struct Images {
@@ -14,9 +16,27 @@ pub struct Images {
     pub t_mino: Image,
 }
 
+pub struct Sounds {
+    pub lock: Sound,
+    pub line_clear: Sound,
+}
+
 pub struct Resources {
     pub font: Font,
     pub images: Images,
+    pub sounds: Sounds,
+}
+
+impl Sounds {
+    /// Plays `effect` at `volume` percent
+    pub fn play(&mut self, effect: SoundEffect, volume: u32) -> Result<()> {
+        let sound = match effect {
+            SoundEffect::Lock => &mut self.lock,
+            SoundEffect::LineClear => &mut self.line_clear,
+        };
+        sound.set_volume(volume as f32 / 100.0);
+        sound.play()
+    }
 }
 
 pub type ResourceFuture = dyn Future<Item = Resources, Error = Error>;
@@ -31,8 +51,10 @@ pub fn load_resources() -> impl Future<Item = Resources, Error = Error> {
         .join(Image::load("s_mino.png"))
         .join(Image::load("z_mino.png"))
         .join(Image::load("t_mino.png"))
+        .join(Sound::load("lock.wav"))
+        .join(Sound::load("line_clear.wav"))
         .and_then(|big_future| {
-            let ((((((((font, empty), i), o), j), l), s), z), t) = big_future;
+            let ((((((((((font, empty), i), o), j), l), s), z), t), lock), line_clear) = big_future;
             Ok(Resources {
                 font: font,
                 images: Images {
@@ -45,6 +67,10 @@ pub fn load_resources() -> impl Future<Item = Resources, Error = Error> {
                     z_mino: z,
                     t_mino: t,
                 },
+                sounds: Sounds {
+                    lock: lock,
+                    line_clear: line_clear,
+                },
             })
         })
 }
@@ -0,0 +1,71 @@
+use crate::savegame::{SaveReader, SaveWriter};
+use getrandom;
+
+/// Small deterministic random number generator (xorshift64*), so a game can be reproduced from
+/// its seed.
+#[derive(Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        // xorshift gets stuck on a zero state
+        Rng {
+            state: if seed == 0 {
+                0x9e37_79b9_7f4a_7c15
+            } else {
+                seed
+            },
+        }
+    }
+
+    pub fn random_seed() -> u64 {
+        let mut bytes: [u8; 8] = [0; 8];
+        getrandom::getrandom(&mut bytes).unwrap();
+        u64::from_le_bytes(bytes)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    pub fn save(&self, w: &mut SaveWriter) {
+        w.put(self.state);
+    }
+
+    pub fn load(r: &mut SaveReader) -> Option<Rng> {
+        Some(Rng { state: r.get()? })
+    }
+
+    /// A value in `0..n`
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_sequence() {
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+        for _ in 0..100 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+        assert_ne!(Rng::new(1).next_u64(), Rng::new(2).next_u64());
+    }
+
+    #[test]
+    fn below() {
+        let mut r = Rng::new(0);
+        for _ in 0..100 {
+            assert!(r.below(7) < 7);
+        }
+    }
+}
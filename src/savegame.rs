@@ -0,0 +1,149 @@
+use crate::shapes::Shape;
+use crate::time::GameTime;
+use alloc::string::String;
+use core::fmt::Display;
+use core::str::{FromStr, SplitWhitespace};
+use core::time::Duration;
+
+/// Builds the text of a saved game. Values are space separated, with each part of the game on its
+/// own labelled line so a saved board can be read when attached to a bug report.
+pub struct SaveWriter {
+    text: String,
+}
+
+/// Reads back the values written by a `SaveWriter`, in the same order.
+pub struct SaveReader<'a> {
+    tokens: SplitWhitespace<'a>,
+}
+
+/// The game saved on quitting, to continue later
+pub const SAVED_GAME_PROFILE: &str = "savegame";
+/// A snapshot taken on request while playing, to attach to bug reports
+pub const BUG_REPORT_PROFILE: &str = "bug-report";
+
+impl SaveWriter {
+    pub fn new() -> Self {
+        SaveWriter {
+            text: String::new(),
+        }
+    }
+
+    /// Starts a new line
+    pub fn label(&mut self, label: &str) {
+        if !self.text.is_empty() {
+            self.text.push('\n');
+        }
+        self.text += label;
+    }
+
+    pub fn put<T: Display>(&mut self, value: T) {
+        self.text += &format!(" {}", value);
+    }
+
+    pub fn put_time(&mut self, time: GameTime) {
+        self.put(time.as_millis());
+    }
+
+    pub fn put_duration(&mut self, duration: Duration) {
+        self.put(duration.as_millis());
+    }
+
+    pub fn put_shape(&mut self, shape: Option<Shape>) {
+        self.put(shape.map(|s| s.letter()).unwrap_or(NONE));
+    }
+
+    pub fn finish(mut self) -> String {
+        self.text.push('\n');
+        self.text
+    }
+}
+
+const NONE: char = '-';
+
+impl<'a> SaveReader<'a> {
+    pub fn new(text: &'a str) -> Self {
+        SaveReader {
+            tokens: text.split_whitespace(),
+        }
+    }
+
+    /// Checks that the next line has the expected label
+    pub fn label(&mut self, label: &str) -> Option<()> {
+        if self.token()? == label {
+            Some(())
+        } else {
+            None
+        }
+    }
+
+    pub fn token(&mut self) -> Option<&'a str> {
+        self.tokens.next()
+    }
+
+    pub fn get<T: FromStr>(&mut self) -> Option<T> {
+        self.token()?.parse().ok()
+    }
+
+    pub fn get_time(&mut self) -> Option<GameTime> {
+        Some(GameTime::from_millis(self.get()?))
+    }
+
+    pub fn get_duration(&mut self) -> Option<Duration> {
+        Some(Duration::from_millis(self.get()?))
+    }
+
+    /// A shape which may be missing, such as an empty hold
+    pub fn get_shape(&mut self) -> Option<Option<Shape>> {
+        let c = self.get::<char>()?;
+        if c == NONE {
+            Some(None)
+        } else {
+            Some(Some(Shape::from_letter(c)?))
+        }
+    }
+}
+
+/// Saved text couldn't be written
+#[derive(Debug)]
+pub struct SaveError;
+
+/// Where saved text is kept between sessions. The game provides this, so the simulation doesn't
+/// depend on how or where it runs.
+pub trait Storage {
+    fn store(&self, profile: &str, text: &str) -> Result<(), SaveError>;
+
+    /// The text saved under `profile`, if there is any
+    fn retrieve(&self, profile: &str) -> Option<String>;
+
+    /// Deletes a profile, or at least empties it where saves can't be deleted
+    fn remove(&self, profile: &str) -> Result<(), SaveError> {
+        self.store(profile, "")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut w = SaveWriter::new();
+        w.label("first");
+        w.put(12);
+        w.put_shape(Some(Shape::T));
+        w.label("second");
+        w.put_shape(None);
+        w.put_duration(Duration::from_millis(1500));
+        let text = w.finish();
+        assert_eq!("first 12 T\nsecond - 1500\n", text);
+
+        let mut r = SaveReader::new(&text);
+        assert_eq!(Some(()), r.label("first"));
+        assert_eq!(Some(12), r.get::<i32>());
+        assert_eq!(Some(Some(Shape::T)), r.get_shape());
+        assert_eq!(None, r.label("third"));
+        assert_eq!(Some(None), r.get_shape());
+        assert_eq!(Some(Duration::from_millis(1500)), r.get_duration());
+        assert_eq!(None, r.token());
+    }
+}
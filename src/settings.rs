@@ -0,0 +1,189 @@
+use crate::field::Field;
+use crate::mode::GameMode;
+use crate::position::Coord;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::time::Duration;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Skin {
+    Textured,
+    Flat,
+}
+
+/// The size of the field for new games
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum BoardSize {
+    Standard,
+    /// Four wide, for practising stacking next to a well
+    Narrow,
+    /// For more than one player to stack on
+    Wide,
+    /// Half size in both directions, so the blocks are drawn twice as big
+    Big,
+}
+
+/// Player preferences which apply to every game
+#[derive(Clone)]
+pub struct GameSettings {
+    /// Delayed auto shift: how long a shift key is held before it starts repeating
+    pub das: Duration,
+    /// Auto repeat rate: the time between repeated shifts
+    pub arr: Duration,
+    pub soft_drop_repeat: Duration,
+    /// The "Ready" phase before each game, during which shift keys can be held to charge DAS
+    pub start_countdown: Duration,
+    pub skin: Skin,
+    pub board: BoardSize,
+    /// Sound effect volume as a percentage
+    pub volume: u32,
+    /// Codes of the modes which show where the current piece would best go
+    pub hint_modes: Vec<String>,
+    /// Games restart as soon as a piece is placed with more inputs than needed
+    pub strict_finesse: bool,
+}
+
+const HANDLING_STEP: Duration = Duration::from_millis(10);
+const MAX_HANDLING_DELAY: Duration = Duration::from_millis(500);
+const VOLUME_STEP: u32 = 10;
+const COUNTDOWN_STEP: Duration = Duration::from_millis(500);
+const MAX_COUNTDOWN: Duration = Duration::from_secs(5);
+
+impl GameSettings {
+    pub fn new() -> Self {
+        GameSettings {
+            das: Duration::from_millis(120),
+            arr: Duration::from_millis(40),
+            soft_drop_repeat: Duration::from_millis(40),
+            start_countdown: Duration::from_millis(2000),
+            skin: Skin::Textured,
+            board: BoardSize::Standard,
+            volume: 50,
+            hint_modes: vec![],
+            strict_finesse: false,
+        }
+    }
+
+    pub fn adjust_das(&mut self, step: i32) {
+        self.das = adjust_duration(self.das, step);
+    }
+
+    pub fn adjust_arr(&mut self, step: i32) {
+        self.arr = adjust_duration(self.arr, step);
+    }
+
+    pub fn adjust_soft_drop_repeat(&mut self, step: i32) {
+        self.soft_drop_repeat = adjust_duration(self.soft_drop_repeat, step);
+    }
+
+    pub fn adjust_start_countdown(&mut self, step: i32) {
+        self.start_countdown = if step < 0 {
+            self.start_countdown
+                .checked_sub(COUNTDOWN_STEP * (-step) as u32)
+                .unwrap_or(Duration::from_millis(0))
+        } else {
+            (self.start_countdown + COUNTDOWN_STEP * step as u32).min(MAX_COUNTDOWN)
+        };
+    }
+
+    pub fn adjust_volume(&mut self, step: i32) {
+        self.volume = (self.volume as i32 + step * VOLUME_STEP as i32)
+            .max(0)
+            .min(100) as u32;
+    }
+
+    pub fn adjust_board(&mut self, step: i32) {
+        let index = BoardSize::ALL
+            .iter()
+            .position(|b| *b == self.board)
+            .unwrap_or(0) as i32;
+        self.board =
+            BoardSize::ALL[(index + step).rem_euclid(BoardSize::ALL.len() as i32) as usize];
+    }
+
+    /// Whether games of `mode` show placement hints
+    pub fn hints_for(&self, mode: GameMode) -> bool {
+        self.hint_modes.contains(&mode.code())
+    }
+
+    pub fn toggle_hints(&mut self, mode: GameMode) {
+        let code = mode.code();
+        if self.hints_for(mode) {
+            self.hint_modes.retain(|c| *c != code);
+        } else {
+            self.hint_modes.push(code);
+        }
+    }
+
+    pub fn toggle_skin(&mut self) {
+        self.skin = match self.skin {
+            Skin::Textured => Skin::Flat,
+            Skin::Flat => Skin::Textured,
+        };
+    }
+}
+
+impl BoardSize {
+    pub const ALL: [BoardSize; 4] = [
+        BoardSize::Standard,
+        BoardSize::Narrow,
+        BoardSize::Wide,
+        BoardSize::Big,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BoardSize::Standard => "Standard",
+            BoardSize::Narrow => "Narrow",
+            BoardSize::Wide => "Wide",
+            BoardSize::Big => "Big",
+        }
+    }
+
+    /// Width and playing area height in blocks
+    pub fn dimensions(&self) -> (Coord, Coord) {
+        match self {
+            BoardSize::Standard => (Field::STANDARD_WIDTH, Field::STANDARD_HEIGHT),
+            BoardSize::Narrow => (4, Field::STANDARD_HEIGHT),
+            BoardSize::Wide => (16, Field::STANDARD_HEIGHT),
+            BoardSize::Big => (Field::STANDARD_WIDTH / 2, Field::STANDARD_HEIGHT / 2),
+        }
+    }
+
+    pub fn new_field(&self) -> Field {
+        let (width, height) = self.dimensions();
+        Field::with_size(width, height)
+    }
+}
+
+fn adjust_duration(d: Duration, step: i32) -> Duration {
+    if step < 0 {
+        d.checked_sub(HANDLING_STEP * (-step) as u32)
+            .unwrap_or(Duration::from_millis(0))
+    } else {
+        (d + HANDLING_STEP * step as u32).min(MAX_HANDLING_DELAY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adjust_handling() {
+        let mut s = GameSettings::new();
+        s.adjust_arr(-10);
+        assert_eq!(Duration::from_millis(0), s.arr);
+        s.adjust_das(1);
+        assert_eq!(Duration::from_millis(130), s.das);
+        s.adjust_volume(10);
+        assert_eq!(100, s.volume);
+        s.adjust_start_countdown(-1);
+        assert_eq!(Duration::from_millis(1500), s.start_countdown);
+        s.adjust_start_countdown(20);
+        assert_eq!(Duration::from_secs(5), s.start_countdown);
+        s.adjust_board(-1);
+        assert_eq!(BoardSize::Big, s.board);
+        assert_eq!((5, 10), s.board.dimensions());
+    }
+}
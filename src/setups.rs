@@ -0,0 +1,232 @@
+use crate::field::{Field, FieldBlock};
+use crate::position::p;
+use crate::savegame::{SaveReader, SaveWriter};
+use crate::shapes::{MinoSet, Shape};
+use alloc::vec::Vec;
+
+/// A named arrangement of pieces to practise building from a known piece order
+pub struct Setup {
+    pub name: &'static str,
+    /// Stable across versions, for saved data
+    pub code: &'static str,
+    /// The finished setup in the notation read by `Field::from_ascii`, with each cell lettered
+    /// for the piece that fills it
+    pub target: &'static str,
+    /// The pieces it is built with, in order. None of them completes a line except the last.
+    pub queue: &'static str,
+}
+
+pub const SETUPS: [Setup; 5] = [
+    Setup {
+        name: "TSD Opener",
+        code: "tsd",
+        target: "
+            ..Z.......
+            .ZZ..OOSLL
+            JZTTTOOSSL
+            JJJTIIIISL
+            ",
+        queue: "JZIOSLT",
+    },
+    Setup {
+        name: "4-Line PC",
+        code: "pc",
+        target: "
+            ZZTTTLLOOI
+            JZZTSSLOOI
+            JJJSSTLOOI
+            IIIITTTOOI
+            ",
+        queue: "ITOJSLZTOI",
+    },
+    Setup {
+        name: "TKI",
+        code: "tki",
+        target: "
+            ...SZZ....
+            L..SSZZ...
+            LTTTSJJJOO
+            LLTIIIIJOO
+            ",
+        queue: "ILOJSZT",
+    },
+    Setup {
+        name: "DT Cannon",
+        code: "dt",
+        // Ends with the T-spin double, which leaves the cave for a T-spin triple
+        target: "
+            JJ........
+            J....Z..LL
+            JLLLZZTTTL
+            ILOOZSSTLL
+            I.OOSSLLLJ
+            I..IIIISSJ
+            I.IIIISSJJ
+            ",
+        queue: "IISJIOSLLZLJT",
+    },
+    Setup {
+        name: "PCO",
+        code: "pco",
+        // The first bag, with the T kept for the perfect clear
+        target: "
+            I....S....
+            I..LLSS...
+            I.OOLJSZZ.
+            I.OOLJJJZZ
+            ",
+        queue: "IOLJZS",
+    },
+];
+
+/// How a locked piece compares with the setup being built
+#[derive(PartialEq, Debug)]
+pub enum PieceCheck {
+    Correct,
+    Wrong,
+    /// The piece was correct and finished the setup
+    Built,
+}
+
+/// Tracks attempts at building a setup. An attempt ends when a piece is placed outside the
+/// target cells for its shape, or when the setup is complete.
+#[derive(Clone)]
+pub struct SetupPractice {
+    setup: usize,
+    target: Field,
+    attempts: u32,
+    successes: u32,
+}
+
+impl Setup {
+    pub fn target_field(&self) -> Field {
+        Field::from_ascii(self.target).expect("Invalid setup target")
+    }
+
+    pub fn pieces(&self) -> Vec<Shape> {
+        self.queue
+            .chars()
+            .map(|c| Shape::from_letter(c).expect("Invalid setup queue"))
+            .collect()
+    }
+
+    pub fn index_of(code: &str) -> Option<usize> {
+        SETUPS.iter().position(|s| s.code == code)
+    }
+}
+
+impl SetupPractice {
+    pub fn new(setup: usize) -> SetupPractice {
+        SetupPractice {
+            setup: setup,
+            target: SETUPS[setup].target_field(),
+            attempts: 0,
+            successes: 0,
+        }
+    }
+
+    pub fn setup(&self) -> &'static Setup {
+        &SETUPS[self.setup]
+    }
+
+    /// The finished setup, to draw under the field
+    pub fn target(&self) -> &Field {
+        &self.target
+    }
+
+    /// Whether a piece covering `minos` would fill only target cells for its shape
+    pub fn fits(&self, minos: &MinoSet) -> bool {
+        let expected = FieldBlock::Occupied(minos.shape());
+        minos
+            .positions()
+            .iter()
+            .all(|pos| self.target.block(*pos) == expected)
+    }
+
+    /// Checks a piece which has just locked as `minos`, with `field` including it
+    pub fn check(&mut self, field: &Field, minos: &MinoSet) -> PieceCheck {
+        let result = if !self.fits(minos) {
+            PieceCheck::Wrong
+        } else if self.is_built(field) {
+            self.successes += 1;
+            PieceCheck::Built
+        } else {
+            return PieceCheck::Correct;
+        };
+        self.attempts += 1;
+        result
+    }
+
+    fn is_built(&self, field: &Field) -> bool {
+        (0..self.target.playing_boundary_height()).all(|y| {
+            (0..self.target.width()).all(|x| {
+                let pos = p(x, y);
+                self.target.block(pos) == FieldBlock::Empty || field.block(pos) != FieldBlock::Empty
+            })
+        })
+    }
+
+    pub fn save(&self, w: &mut SaveWriter) {
+        w.put(self.attempts);
+        w.put(self.successes);
+    }
+
+    pub fn load(r: &mut SaveReader, setup: usize) -> Option<SetupPractice> {
+        Some(SetupPractice {
+            attempts: r.get()?,
+            successes: r.get()?,
+            ..SetupPractice::new(setup)
+        })
+    }
+
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    pub fn successes(&self) -> u32 {
+        self.successes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::movegen::placements;
+
+    #[test]
+    fn every_setup_builds_in_order() {
+        for setup in SETUPS.iter() {
+            let mut practice = SetupPractice::new(Setup::index_of(setup.code).unwrap());
+            let mut field = Field::new();
+            let pieces = setup.pieces();
+            for (i, shape) in pieces.iter().enumerate() {
+                let minos = placements(&field, *shape)
+                    .into_iter()
+                    .map(|placement| placement.tetromino.to_minos())
+                    .find(|minos| practice.fits(minos))
+                    .unwrap_or_else(|| panic!("{}: nowhere for piece {}", setup.name, i));
+                minos.apply_to_field(&mut field);
+                let expected = if i + 1 < pieces.len() {
+                    PieceCheck::Correct
+                } else {
+                    PieceCheck::Built
+                };
+                assert_eq!(expected, practice.check(&field, &minos), "{}", setup.name);
+                if i + 1 < pieces.len() {
+                    assert!(field.find_lines().is_empty(), "{}: early clear", setup.name);
+                }
+            }
+            assert_eq!(1, practice.successes(), "{} wasn't built", setup.name);
+        }
+    }
+
+    #[test]
+    fn wrong_piece() {
+        let mut practice = SetupPractice::new(0);
+        let mut field = Field::new();
+        let minos = placements(&field, Shape::O)[0].tetromino.to_minos();
+        minos.apply_to_field(&mut field);
+        assert_eq!(PieceCheck::Wrong, practice.check(&field, &minos));
+        assert_eq!((1, 0), (practice.attempts(), practice.successes()));
+    }
+}
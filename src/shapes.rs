@@ -1,7 +1,7 @@
+use crate::block_render::{BlockRenderInstructions, DrawBlockType, RenderBlockInfo};
 use crate::field::{CheckableField, Field};
 use crate::position::Coord;
 use crate::position::{p, Pos, RotateDir, Rotations};
-use crate::render::{BlockRenderInstructions, DrawBlockType, RenderBlockInfo};
 use alloc::vec::Vec;
 
 #[derive(Copy, Clone, Debug, PartialEq)]
@@ -36,10 +36,19 @@ impl MinoSet {
         self.shape
     }
 
+    pub fn positions(&self) -> [Pos; 4] {
+        self.minos
+    }
+
     pub fn contains(&self, p: Pos) -> bool {
         self.minos.contains(&p)
     }
 
+    /// True if every mino is at or above row `y`.
+    pub fn is_above(&self, y: Coord) -> bool {
+        self.minos.iter().all(|mino| mino.y >= y)
+    }
+
     pub fn apply_to_field(&self, field: &mut Field) {
         for mino in self.minos.iter() {
             field.occupy(*mino, self.shape);
@@ -47,12 +56,7 @@ impl MinoSet {
     }
 
     pub fn is_valid(&self, field: &dyn CheckableField) -> bool {
-        for mino in self.minos.iter() {
-            if !field.is_open(*mino) {
-                return false;
-            }
-        }
-        true
+        field.has_room_for(self)
     }
 }
 
@@ -106,6 +110,32 @@ impl ShapeDef for Shape {
 }
 
 impl Shape {
+    pub const ALL: [Shape; 7] = [
+        Shape::I,
+        Shape::O,
+        Shape::J,
+        Shape::L,
+        Shape::S,
+        Shape::Z,
+        Shape::T,
+    ];
+
+    pub fn letter(&self) -> char {
+        match self {
+            Shape::I => 'I',
+            Shape::O => 'O',
+            Shape::J => 'J',
+            Shape::L => 'L',
+            Shape::S => 'S',
+            Shape::Z => 'Z',
+            Shape::T => 'T',
+        }
+    }
+
+    pub fn from_letter(letter: char) -> Option<Shape> {
+        Shape::ALL.iter().copied().find(|s| s.letter() == letter)
+    }
+
     fn positions(&self, rotation: Rotations) -> [Pos; 4] {
         use Rotations::*;
         use Shape::*;
@@ -196,4 +226,11 @@ mod tests {
             contains(vec![p(3, 2), p(3, 3), p(3, 4), p(3, 5)]).exactly()
         );
     }
+
+    #[test]
+    fn is_above() {
+        let minos = Shape::T.to_minos(Rotations::Zero, p(0, 18));
+        assert!(minos.is_above(20));
+        assert!(!minos.is_above(21));
+    }
 }
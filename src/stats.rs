@@ -0,0 +1,201 @@
+use crate::savegame::{SaveReader, SaveWriter};
+use core::time::Duration;
+
+/// The result of locking a piece that completed at least one line, or that was a T-spin
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct LineClear {
+    pub lines: u32,
+    pub t_spin: bool,
+    /// The clear left every cell in the field empty
+    pub perfect_clear: bool,
+}
+
+/// Running totals for a single game
+#[derive(Copy, Clone, Default, Debug)]
+pub struct Statistics {
+    pub lines: i32,
+    pub score: u32,
+    pub attack: u32,
+    pub pieces: u32,
+    pub key_presses: u32,
+    pub tetrises: u32,
+    pub t_spins: u32,
+    pub perfect_clears: u32,
+    /// Pieces placed with more inputs than needed
+    pub finesse_faults: u32,
+}
+
+impl LineClear {
+    pub fn score(&self, level: i32) -> u32 {
+        let base = match (self.t_spin, self.lines) {
+            (false, 0) => 0,
+            (false, 1) => 100,
+            (false, 2) => 300,
+            (false, 3) => 500,
+            (false, _) => 800,
+            (true, 0) => 400,
+            (true, 1) => 800,
+            (true, 2) => 1200,
+            (true, _) => 1600,
+        };
+        let bonus = if self.perfect_clear {
+            match self.lines {
+                1 => 800,
+                2 => 1200,
+                3 => 1800,
+                _ => 2000,
+            }
+        } else {
+            0
+        };
+        (base + bonus) * level as u32
+    }
+
+    /// Lines of garbage this clear sends to an opponent
+    pub fn attack(&self) -> u32 {
+        const PERFECT_CLEAR_ATTACK: u32 = 10;
+        let base = if self.t_spin {
+            self.lines * 2
+        } else {
+            match self.lines {
+                0 | 1 => 0,
+                2 => 1,
+                3 => 2,
+                _ => 4,
+            }
+        };
+        if self.perfect_clear {
+            base + PERFECT_CLEAR_ATTACK
+        } else {
+            base
+        }
+    }
+
+    pub fn banner(&self) -> Option<&'static str> {
+        if self.perfect_clear {
+            return Some("PERFECT CLEAR");
+        }
+        match (self.t_spin, self.lines) {
+            (true, 0) => Some("T-SPIN"),
+            (true, 1) => Some("T-SPIN SINGLE"),
+            (true, 2) => Some("T-SPIN DOUBLE"),
+            (true, _) => Some("T-SPIN TRIPLE"),
+            (false, 4) => Some("TETRIS"),
+            (false, _) => None,
+        }
+    }
+}
+
+impl Statistics {
+    pub fn save(&self, w: &mut SaveWriter) {
+        w.label("stats");
+        w.put(self.lines);
+        w.put(self.score);
+        w.put(self.attack);
+        w.put(self.pieces);
+        w.put(self.key_presses);
+        w.put(self.tetrises);
+        w.put(self.t_spins);
+        w.put(self.perfect_clears);
+        w.put(self.finesse_faults);
+    }
+
+    pub fn load(r: &mut SaveReader) -> Option<Statistics> {
+        r.label("stats")?;
+        Some(Statistics {
+            lines: r.get()?,
+            score: r.get()?,
+            attack: r.get()?,
+            pieces: r.get()?,
+            key_presses: r.get()?,
+            tetrises: r.get()?,
+            t_spins: r.get()?,
+            perfect_clears: r.get()?,
+            finesse_faults: r.get()?,
+        })
+    }
+
+    pub fn pieces_per_second(&self, elapsed: Duration) -> f32 {
+        let seconds = elapsed.as_millis() as f32 / 1000.0;
+        if seconds > 0.0 {
+            self.pieces as f32 / seconds
+        } else {
+            0.0
+        }
+    }
+
+    pub fn keys_per_piece(&self) -> f32 {
+        if self.pieces > 0 {
+            self.key_presses as f32 / self.pieces as f32
+        } else {
+            0.0
+        }
+    }
+
+    pub fn record_clear(&mut self, clear: LineClear, level: i32) {
+        self.lines += clear.lines as i32;
+        self.score += clear.score(level);
+        self.attack += clear.attack();
+        if clear.lines >= 4 {
+            self.tetrises += 1;
+        }
+        if clear.t_spin {
+            self.t_spins += 1;
+        }
+        if clear.perfect_clear {
+            self.perfect_clears += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn perfect_clear_bonus() {
+        let single = LineClear {
+            lines: 1,
+            t_spin: false,
+            perfect_clear: false,
+        };
+        let pc_single = LineClear {
+            lines: 1,
+            t_spin: false,
+            perfect_clear: true,
+        };
+        assert_eq!(100, single.score(1));
+        assert_eq!(900 * 3, pc_single.score(3));
+        assert_eq!(0, single.attack());
+        assert_eq!(10, pc_single.attack());
+        assert_eq!(Some("PERFECT CLEAR"), pc_single.banner());
+    }
+
+    #[test]
+    fn record_clear() {
+        let mut stats = Statistics::default();
+        stats.record_clear(
+            LineClear {
+                lines: 4,
+                t_spin: false,
+                perfect_clear: true,
+            },
+            2,
+        );
+        stats.record_clear(
+            LineClear {
+                lines: 2,
+                t_spin: true,
+                perfect_clear: false,
+            },
+            2,
+        );
+
+        assert_eq!(6, stats.lines);
+        assert_eq!((2800 + 1200) * 2, stats.score);
+        assert_eq!(18, stats.attack);
+        assert_eq!(1, stats.tetrises);
+        assert_eq!(1, stats.t_spins);
+        assert_eq!(1, stats.perfect_clears);
+    }
+}
@@ -0,0 +1,37 @@
+use crate::savegame::{SaveError, Storage};
+use alloc::string::String;
+use quicksilver::saving::{load_raw, save_raw};
+
+/// Saves through quicksilver: to files on the desktop and to local storage in the browser
+pub struct SaveFiles;
+
+const SAVE_APP_NAME: &str = "blocks";
+
+impl Storage for SaveFiles {
+    fn store(&self, profile: &str, text: &str) -> Result<(), SaveError> {
+        save_raw(SAVE_APP_NAME, profile, text.as_bytes()).map_err(|_| SaveError)
+    }
+
+    fn retrieve(&self, profile: &str) -> Option<String> {
+        let text = String::from_utf8(load_raw(SAVE_APP_NAME, profile).ok()?).ok()?;
+        if text.is_empty() {
+            None
+        } else {
+            Some(text)
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn remove(&self, profile: &str) -> Result<(), SaveError> {
+        // The same file quicksilver saves the profile to
+        let path = dirs::data_dir()
+            .ok_or(SaveError)?
+            .join(SAVE_APP_NAME)
+            .join(profile);
+        match std::fs::remove_file(path) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(_) => Err(SaveError),
+        }
+    }
+}
@@ -0,0 +1,573 @@
+use crate::field::{Field, FieldBlock};
+use crate::gamestate::GameState;
+use crate::json::Json;
+use crate::keybindings::Trigger;
+use crate::movegen::{placements, placements_from, sorted_minos, Placement};
+use crate::position::{p, Rotations};
+use crate::shapes::Shape;
+use crate::tetromino::Tetromino;
+use alloc::boxed::Box;
+use alloc::collections::vec_deque::VecDeque;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// Carries Tetris Bot Protocol messages to and from a bot
+pub trait BotConnection {
+    fn send(&mut self, message: &Json);
+    /// The next message from the bot, without waiting for one to arrive
+    fn receive(&mut self) -> Option<Json>;
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum Phase {
+    /// The bot introduces itself first
+    WaitingForInfo,
+    WaitingForReady,
+    Ready,
+    /// The bot reported an error, so it's left alone
+    Failed,
+}
+
+/// The game as the bot was last told about it, to spot changes it needs to hear about
+struct KnownState {
+    field: Field,
+    hold: Option<Shape>,
+    /// The current piece followed by the previews
+    queue: Vec<Shape>,
+}
+
+/// Plays a game with the placements an external bot suggests, through the same inputs as a
+/// player. The bot is expected to assume a standard 10x40 board.
+pub struct BotPlayer {
+    bot: Box<dyn BotConnection>,
+    phase: Phase,
+    name: Option<String>,
+    known: Option<KnownState>,
+    waiting_for_suggestion: bool,
+    plan: VecDeque<Trigger>,
+}
+
+impl BotPlayer {
+    pub fn new(bot: Box<dyn BotConnection>) -> BotPlayer {
+        BotPlayer {
+            bot: bot,
+            phase: Phase::WaitingForInfo,
+            name: None,
+            known: None,
+            waiting_for_suggestion: false,
+            plan: VecDeque::new(),
+        }
+    }
+
+    /// The name the bot gave, once it has introduced itself
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_ref().map(|name| name.as_str())
+    }
+
+    /// Whether the bot is yet to answer, so a game not run in real time should wait for it
+    pub fn is_waiting(&self) -> bool {
+        match self.phase {
+            Phase::WaitingForInfo | Phase::WaitingForReady => true,
+            Phase::Ready => self.waiting_for_suggestion,
+            Phase::Failed => false,
+        }
+    }
+
+    /// The inputs to play now, to pass to `GameState::update_with`
+    pub fn update(&mut self, game: &GameState) -> Vec<Trigger> {
+        while let Some(message) = self.bot.receive() {
+            self.handle_message(&message, game);
+        }
+
+        if !self.plan.is_empty() {
+            let mut result = vec![];
+            while let Some(input) = self.plan.pop_front() {
+                result.push(input);
+                // The piece coming out of hold only appears on the next update
+                if input == Trigger::HoldPiece {
+                    break;
+                }
+            }
+            return result;
+        }
+
+        if self.phase == Phase::Ready && !self.waiting_for_suggestion {
+            if game.current_piece().is_some() {
+                self.sync(game);
+                self.bot
+                    .send(&Json::object(vec![("type", Json::string("suggest"))]));
+                self.waiting_for_suggestion = true;
+            }
+        }
+        vec![]
+    }
+
+    fn handle_message(&mut self, message: &Json, game: &GameState) {
+        match message.get("type").and_then(|t| t.as_str()) {
+            Some("info") if self.phase == Phase::WaitingForInfo => {
+                self.name = message
+                    .get("name")
+                    .and_then(|name| name.as_str())
+                    .map(String::from);
+                self.bot
+                    .send(&Json::object(vec![("type", Json::string("rules"))]));
+                self.phase = Phase::WaitingForReady;
+            }
+            Some("ready") if self.phase == Phase::WaitingForReady => self.phase = Phase::Ready,
+            Some("error") => self.phase = Phase::Failed,
+            Some("suggestion") if self.waiting_for_suggestion => {
+                self.waiting_for_suggestion = false;
+                self.follow_suggestion(message, game);
+            }
+            _ => (),
+        }
+    }
+
+    /// Tells the bot about anything that changed other than by its own moves, such as garbage or
+    /// a new game, and about newly revealed previews
+    fn sync(&mut self, game: &GameState) {
+        let mut queue = vec![];
+        queue.extend(game.current_piece().map(|piece| piece.shape()));
+        queue.extend(game.previews());
+
+        if let Some(known) = &mut self.known {
+            if known.field == *game.field()
+                && known.hold == game.hold_piece()
+                && queue.starts_with(&known.queue)
+            {
+                for shape in &queue[known.queue.len()..] {
+                    self.bot.send(&Json::object(vec![
+                        ("type", Json::string("new_piece")),
+                        ("piece", piece_json(*shape)),
+                    ]));
+                }
+                known.queue = queue;
+                return;
+            }
+            self.bot
+                .send(&Json::object(vec![("type", Json::string("stop"))]));
+        }
+
+        self.bot
+            .send(&start_message(game.field(), game.hold_piece(), &queue));
+        self.known = Some(KnownState {
+            field: game.field().clone(),
+            hold: game.hold_piece(),
+            queue: queue,
+        });
+    }
+
+    /// Plans the inputs for the first suggested move that can be reached
+    fn follow_suggestion(&mut self, message: &Json, game: &GameState) {
+        let moves = message
+            .get("moves")
+            .and_then(|moves| moves.as_array())
+            .unwrap_or(&[]);
+        for suggested in moves {
+            let target = match parse_move(suggested) {
+                Some(target) => target,
+                None => continue,
+            };
+            if let Some(inputs) = inputs_for(game, target.0, target.1) {
+                self.bot.send(&Json::object(vec![
+                    ("type", Json::string("play")),
+                    ("move", suggested.clone()),
+                ]));
+                self.played(target.0);
+                self.plan = inputs.into_iter().collect();
+                return;
+            }
+        }
+        // Nothing usable, so drop the piece where it is. The bot gets restarted on the new board
+        // next piece, since it won't match what the bot knows.
+        self.plan = vec![Trigger::HardDrop].into_iter().collect();
+    }
+
+    /// Follows the bot's view of the game through a move it was told to play
+    fn played(&mut self, piece: Tetromino) {
+        let known = match &mut self.known {
+            Some(known) if !known.queue.is_empty() => known,
+            _ => return,
+        };
+        let current = known.queue.remove(0);
+        if piece.shape() != current {
+            match known.hold {
+                Some(_) => known.hold = Some(current),
+                None if !known.queue.is_empty() => {
+                    known.hold = Some(current);
+                    known.queue.remove(0);
+                }
+                None => (),
+            }
+        }
+        piece.to_minos().apply_to_field(&mut known.field);
+        let lines = known.field.find_lines();
+        known.field.remove_lines(&lines);
+    }
+}
+
+/// The inputs that lock `target`, holding first if it's the other piece
+fn inputs_for(game: &GameState, target: Tetromino, spin: bool) -> Option<Vec<Trigger>> {
+    let field = game.field();
+    let current = game.current_piece()?;
+    let (prefix, options): (&[Trigger], Vec<Placement>) = if target.shape() == current.shape() {
+        (&[], placements_from(field, current))
+    } else {
+        let held = match game.hold_piece() {
+            Some(held) => held,
+            None => *game.previews().first()?,
+        };
+        if !game.can_hold() || held != target.shape() {
+            return None;
+        }
+        (&[Trigger::HoldPiece], placements(field, held))
+    };
+
+    let minos = sorted_minos(target);
+    let matching = |placement: &&Placement| sorted_minos(placement.tetromino) == minos;
+    let placement = options
+        .iter()
+        .filter(matching)
+        .find(|placement| placement.t_spin == spin)
+        .or_else(|| options.iter().find(matching))?;
+    let mut inputs = prefix.to_vec();
+    inputs.extend_from_slice(&placement.inputs);
+    Some(inputs)
+}
+
+fn start_message(field: &Field, hold: Option<Shape>, queue: &[Shape]) -> Json {
+    let board = (0..field.game_height())
+        .map(|y| {
+            Json::Array(
+                (0..field.width())
+                    .map(|x| match field.block(p(x, y)) {
+                        FieldBlock::Empty => Json::Null,
+                        FieldBlock::Occupied(shape) => piece_json(shape),
+                        FieldBlock::Garbage => Json::string("G"),
+                    })
+                    .collect(),
+            )
+        })
+        .collect();
+    Json::object(vec![
+        ("type", Json::string("start")),
+        ("hold", hold.map(piece_json).unwrap_or(Json::Null)),
+        (
+            "queue",
+            Json::Array(queue.iter().map(|shape| piece_json(*shape)).collect()),
+        ),
+        ("combo", Json::Number(0.0)),
+        ("back_to_back", Json::Bool(false)),
+        ("board", Json::Array(board)),
+    ])
+}
+
+fn piece_json(shape: Shape) -> Json {
+    let mut letter = String::new();
+    letter.push(shape.letter());
+    Json::String(letter)
+}
+
+/// The piece a move places, and whether it's a spin
+fn parse_move(json: &Json) -> Option<(Tetromino, bool)> {
+    let location = json.get("location")?;
+    let mut letters = location.get("type")?.as_str()?.chars();
+    let shape = Shape::from_letter(letters.next()?)?;
+    let rotation = match location.get("orientation")?.as_str()? {
+        "north" => Rotations::Zero,
+        "east" => Rotations::One,
+        "south" => Rotations::Two,
+        "west" => Rotations::Three,
+        _ => return None,
+    };
+    let center = p(location.get("x")?.as_i32()?, location.get("y")?.as_i32()?);
+    let spin = match json.get("spin").and_then(|spin| spin.as_str()) {
+        Some("full") | Some("mini") => true,
+        _ => false,
+    };
+    Some((Tetromino::at_center(shape, rotation, center)?, spin))
+}
+
+/// The JSON for a move that locks `t`, in the form bots suggest them
+#[cfg(test)]
+fn move_json(t: Tetromino, spin: bool) -> Json {
+    let orientation = match t.rotation() {
+        Rotations::Zero => "north",
+        Rotations::One => "east",
+        Rotations::Two => "south",
+        Rotations::Three => "west",
+    };
+    let center = t.center();
+    Json::object(vec![
+        (
+            "location",
+            Json::object(vec![
+                ("type", piece_json(t.shape())),
+                ("orientation", Json::string(orientation)),
+                ("x", Json::Number(center.x as f64)),
+                ("y", Json::Number(center.y as f64)),
+            ]),
+        ),
+        ("spin", Json::string(if spin { "full" } else { "none" })),
+    ])
+}
+
+/// A bot running as a separate program, talking over its stdin and stdout
+#[cfg(not(target_arch = "wasm32"))]
+pub struct BotProcess {
+    child: std::process::Child,
+    stdin: std::process::ChildStdin,
+    messages: std::sync::mpsc::Receiver<Json>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl BotProcess {
+    /// Starts a bot from a command line of the program followed by its arguments
+    pub fn launch(command: &str) -> Option<BotProcess> {
+        use std::io::{BufRead, BufReader};
+        use std::process::{Command, Stdio};
+
+        let mut parts = command.split_whitespace();
+        let mut child = Command::new(parts.next()?)
+            .args(parts)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .ok()?;
+        let stdin = child.stdin.take()?;
+        let stdout = child.stdout.take()?;
+
+        // Reading blocks, so lines are passed back from another thread as they arrive
+        let (sender, receiver) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let message = match line.ok().and_then(|line| Json::parse(&line)) {
+                    Some(message) => message,
+                    None => continue,
+                };
+                if sender.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Some(BotProcess {
+            child: child,
+            stdin: stdin,
+            messages: receiver,
+        })
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl BotConnection for BotProcess {
+    fn send(&mut self, message: &Json) {
+        use std::io::Write;
+        // A bot that has gone away stops suggesting moves, which is handled the same way
+        let _ = writeln!(self.stdin, "{}", message).and_then(|()| self.stdin.flush());
+    }
+
+    fn receive(&mut self) -> Option<Json> {
+        self.messages.try_recv().ok()
+    }
+}
+
+/// How long a bot has to exit by itself after being told to quit
+#[cfg(not(target_arch = "wasm32"))]
+const QUIT_GRACE_PERIOD: core::time::Duration = core::time::Duration::from_millis(100);
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Drop for BotProcess {
+    fn drop(&mut self) {
+        use std::time::{Duration, Instant};
+
+        self.send(&Json::object(vec![("type", Json::string("quit"))]));
+        // Give the bot a moment to read the message and clean up before it is killed
+        let deadline = Instant::now() + QUIT_GRACE_PERIOD;
+        while let Ok(None) = self.child.try_wait() {
+            if Instant::now() >= deadline {
+                let _ = self.child.kill();
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        let _ = self.child.wait();
+    }
+}
+
+/// A bot to play the game, from `--bot <command>` on the command line
+#[cfg(not(target_arch = "wasm32"))]
+pub fn launch_bot() -> Option<BotPlayer> {
+    let mut args = std::env::args().skip_while(|arg| arg != "--bot");
+    args.next()?;
+    let bot = BotProcess::launch(&args.next()?)?;
+    Some(BotPlayer::new(Box::new(bot)))
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn launch_bot() -> Option<BotPlayer> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::field::CheckableField;
+    use crate::mode::GameMode;
+    use crate::settings::GameSettings;
+    use crate::time::GameTime;
+    use alloc::rc::Rc;
+    use core::cell::RefCell;
+    use core::time::Duration;
+
+    /// Answers like a bot would, with the moves for each suggestion from a script. Once the
+    /// script runs out it keeps thinking forever.
+    struct ScriptedBot {
+        sent: Rc<RefCell<Vec<Json>>>,
+        inbox: VecDeque<Json>,
+        suggestions: VecDeque<Vec<Json>>,
+    }
+
+    impl BotConnection for ScriptedBot {
+        fn send(&mut self, message: &Json) {
+            self.sent.borrow_mut().push(message.clone());
+            match message.get("type").and_then(|t| t.as_str()) {
+                Some("rules") => self.inbox.push_back(message_of_type("ready")),
+                Some("suggest") => {
+                    if let Some(moves) = self.suggestions.pop_front() {
+                        self.inbox.push_back(Json::object(vec![
+                            ("type", Json::string("suggestion")),
+                            ("moves", Json::Array(moves)),
+                        ]));
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        fn receive(&mut self) -> Option<Json> {
+            self.inbox.pop_front()
+        }
+    }
+
+    fn message_of_type(message_type: &str) -> Json {
+        Json::object(vec![("type", Json::string(message_type))])
+    }
+
+    fn scripted_bot(suggestions: Vec<Vec<Json>>) -> (BotPlayer, Rc<RefCell<Vec<Json>>>) {
+        let sent = Rc::new(RefCell::new(vec![]));
+        let info = Json::object(vec![
+            ("type", Json::string("info")),
+            ("name", Json::string("Scripted")),
+        ]);
+        let bot = ScriptedBot {
+            sent: sent.clone(),
+            inbox: vec![info].into_iter().collect(),
+            suggestions: suggestions.into_iter().collect(),
+        };
+        (BotPlayer::new(Box::new(bot)), sent)
+    }
+
+    fn sent_types(sent: &Rc<RefCell<Vec<Json>>>) -> Vec<String> {
+        sent.borrow_mut()
+            .drain(..)
+            .filter_map(|m| m.get("type").and_then(|t| t.as_str()).map(String::from))
+            .collect()
+    }
+
+    fn new_game() -> (GameState, GameTime) {
+        let mut settings = GameSettings::new();
+        settings.start_countdown = Duration::from_millis(0);
+        let (mut game, clock) = GameState::new(GameMode::Sprint { lines: 40 }, 11, &settings);
+        let now = clock.now();
+        game.update_with(vec![], now);
+        (game, now)
+    }
+
+    /// Lets the bot play until it has no more inputs for the game
+    fn play(bot: &mut BotPlayer, game: &mut GameState, now: GameTime) {
+        for _ in 0..10 {
+            let inputs = bot.update(game);
+            game.update_with(inputs, now);
+        }
+    }
+
+    #[test]
+    fn start() {
+        let (game, _) = new_game();
+        let (mut bot, sent) = scripted_bot(vec![]);
+        bot.update(&game);
+        assert_eq!(Some("Scripted"), bot.name());
+
+        let start = sent.borrow()[1].clone();
+        assert_eq!(vec!["rules", "start", "suggest"], sent_types(&sent));
+        let queue = start.get("queue").and_then(|q| q.as_array()).unwrap();
+        let current = game.current_piece().unwrap().shape();
+        assert_eq!(piece_json(current), queue[0]);
+        assert_eq!(1 + game.previews().len(), queue.len());
+        let board = start.get("board").and_then(|b| b.as_array()).unwrap();
+        assert_eq!(40, board.len());
+        assert_eq!(Some(10), board[0].as_array().map(|row| row.len()));
+        assert_eq!(Some(&Json::Null), start.get("hold"));
+    }
+
+    #[test]
+    fn plays_suggestions() {
+        let (mut game, now) = new_game();
+        let current = game.current_piece().unwrap().shape();
+        let target = placements(game.field(), current)
+            .into_iter()
+            .find(|placement| placement.tetromino.to_minos().contains(p(0, 0)))
+            .unwrap()
+            .tetromino;
+        let (mut bot, sent) = scripted_bot(vec![vec![move_json(target, false)]]);
+        play(&mut bot, &mut game, now);
+
+        for pos in target.to_minos().positions().iter() {
+            assert!(!game.field().is_open(*pos));
+        }
+        assert_eq!(
+            vec!["rules", "start", "suggest", "play", "new_piece", "suggest"],
+            sent_types(&sent)
+        );
+    }
+
+    #[test]
+    fn holds_for_the_other_piece() {
+        let (mut game, now) = new_game();
+        let next = game.previews()[0];
+        let target = placements(game.field(), next)[0].tetromino;
+        let (mut bot, sent) = scripted_bot(vec![vec![move_json(target, false)]]);
+        play(&mut bot, &mut game, now);
+
+        let mut expected = Field::new();
+        target.to_minos().apply_to_field(&mut expected);
+        assert_eq!(expected, *game.field());
+        assert!(game.hold_piece().is_some());
+        // The held piece and the piece after it were both known, so two previews are revealed
+        assert_eq!(
+            vec![
+                "rules",
+                "start",
+                "suggest",
+                "play",
+                "new_piece",
+                "new_piece",
+                "suggest"
+            ],
+            sent_types(&sent)
+        );
+    }
+
+    #[test]
+    fn restarts_after_changes() {
+        let (mut game, now) = new_game();
+        let (mut bot, sent) = scripted_bot(vec![vec![]]);
+        play(&mut bot, &mut game, now);
+        // The empty suggestion drops the piece, so the bot has to be told the new board
+        assert_eq!(
+            vec!["rules", "start", "suggest", "stop", "start", "suggest"],
+            sent_types(&sent)
+        );
+    }
+}
@@ -1,8 +1,10 @@
 use crate::field::CheckableField;
-use crate::position::{p, Pos, RotateDir, Rotations, ShiftDir};
+use crate::position::{p, Coord, Pos, RotateDir, Rotations, ShiftDir};
+use crate::savegame::{SaveReader, SaveWriter};
 use crate::shapes::{MinoSet, Shape, ShapeDef};
+use num_traits::FromPrimitive;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Tetromino {
     root_pos: Pos,
     shape: Shape,
@@ -22,10 +24,44 @@ impl Tetromino {
         }
     }
 
+    pub fn with_rotation(p: Pos, s: Shape, rotation: Rotations) -> Tetromino {
+        Tetromino {
+            root_pos: p,
+            shape: s,
+            rotation: rotation,
+        }
+    }
+
+    pub fn pos(&self) -> Pos {
+        self.root_pos
+    }
+
+    pub fn shape(&self) -> Shape {
+        self.shape
+    }
+
+    pub fn rotation(&self) -> Rotations {
+        self.rotation
+    }
+
     pub fn to_minos(&self) -> MinoSet {
         self.shape.to_minos(self.rotation, self.root_pos)
     }
 
+    /// The SRS rotation centre, which fumen and bots place pieces by instead of the root
+    pub fn center(&self) -> Pos {
+        let origin_minos = center_minos(self.shape, self.rotation, p(0, 0));
+        translation(&origin_minos, &self.to_minos().positions()).unwrap_or(p(0, 0))
+    }
+
+    /// The piece with its SRS rotation centre at `center`
+    pub fn at_center(shape: Shape, rotation: Rotations, center: Pos) -> Option<Tetromino> {
+        let minos = center_minos(shape, rotation, center);
+        let root_minos = shape.to_minos(rotation, p(0, 0)).positions();
+        let root = translation(&root_minos, &minos)?;
+        Some(Tetromino::with_rotation(root, shape, rotation))
+    }
+
     pub fn hard_drop(&self, field: &dyn CheckableField) -> Tetromino {
         let mut result = *self;
         loop {
@@ -71,6 +107,34 @@ impl Tetromino {
         None
     }
 
+    /// The three-corner rule: at least 3 of the cells diagonal to a T piece's centre are blocked.
+    pub fn is_t_spin_position(&self, field: &dyn CheckableField) -> bool {
+        if self.shape != Shape::T {
+            return false;
+        }
+        let center = self.root_pos + p(1, 2);
+        [p(-1, -1), p(1, -1), p(-1, 1), p(1, 1)]
+            .iter()
+            .filter(|corner| !field.is_open(center + **corner))
+            .count()
+            >= 3
+    }
+
+    pub fn save(&self, w: &mut SaveWriter) {
+        w.put_shape(Some(self.shape));
+        w.put(self.root_pos.x);
+        w.put(self.root_pos.y);
+        w.put(self.rotation as i32);
+    }
+
+    pub fn load(r: &mut SaveReader) -> Option<Tetromino> {
+        Some(Tetromino {
+            shape: r.get_shape()??,
+            root_pos: p(r.get::<Coord>()?, r.get()?),
+            rotation: Rotations::from_i32(r.get()?)?,
+        })
+    }
+
     fn if_valid(self, field: &dyn CheckableField) -> Option<Self> {
         if self.to_minos().is_valid(field) {
             Some(self)
@@ -80,9 +144,54 @@ impl Tetromino {
     }
 }
 
+/// The minos' offsets from the SRS rotation centre when spawned
+fn spawn_offsets(shape: Shape) -> [Pos; 4] {
+    match shape {
+        Shape::I => [p(0, 0), p(-1, 0), p(1, 0), p(2, 0)],
+        Shape::T => [p(0, 0), p(-1, 0), p(1, 0), p(0, 1)],
+        Shape::O => [p(0, 0), p(1, 0), p(0, 1), p(1, 1)],
+        Shape::L => [p(0, 0), p(-1, 0), p(1, 0), p(1, 1)],
+        Shape::J => [p(0, 0), p(-1, 0), p(1, 0), p(-1, 1)],
+        Shape::S => [p(0, 0), p(-1, 0), p(0, 1), p(1, 1)],
+        Shape::Z => [p(0, 0), p(1, 0), p(0, 1), p(-1, 1)],
+    }
+}
+
+fn center_minos(shape: Shape, rotation: Rotations, center: Pos) -> [Pos; 4] {
+    let mut minos = spawn_offsets(shape);
+    for o in minos.iter_mut() {
+        let rotated = match rotation {
+            Rotations::Zero => *o,
+            Rotations::One => p(o.y, -o.x),
+            Rotations::Two => p(-o.x, -o.y),
+            Rotations::Three => p(-o.y, o.x),
+        };
+        *o = center + rotated;
+    }
+    minos
+}
+
+/// The translation taking the minos `from` onto `to`, if they are the same shape
+fn translation(from: &[Pos], to: &[Pos]) -> Option<Pos> {
+    let min = |minos: &[Pos]| {
+        p(
+            minos.iter().map(|m| m.x).min().unwrap_or(0),
+            minos.iter().map(|m| m.y).min().unwrap_or(0),
+        )
+    };
+    let (a, b) = (min(from), min(to));
+    let offset = p(b.x - a.x, b.y - a.y);
+    if from.iter().all(|m| to.contains(&(*m + offset))) {
+        Some(offset)
+    } else {
+        None
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::field::Field;
     use hamcrest2::prelude::*;
 
     mock_trait!(MockCheckableField, is_open(Pos) -> bool);
@@ -114,4 +223,38 @@ mod tests {
         let right_result = t.try_shift(ShiftDir::Right, &mock_field);
         assert_eq!(right_result.expect("").root_pos, p(1, 0));
     }
+
+    #[test]
+    fn t_spin_corners() {
+        let mock_field = MockCheckableField::default();
+        mock_field.is_open.return_value_for(p(0, 1), false);
+        mock_field.is_open.return_value_for(p(2, 1), false);
+        mock_field.is_open.return_value(true);
+
+        let t = Tetromino::new(p(0, 0), Shape::T);
+        assert!(!t.is_t_spin_position(&mock_field));
+
+        mock_field.is_open.return_value_for(p(0, 3), false);
+        assert!(t.is_t_spin_position(&mock_field));
+        assert!(!Tetromino::new(p(0, 0), Shape::S).is_t_spin_position(&mock_field));
+    }
+
+    #[test]
+    fn kick_off_wall() {
+        let field = Field::from_ascii(
+            "
+            ..........
+            .....#####
+            ",
+        )
+        .unwrap();
+        // Pointing right against the left wall, so turning flat needs a kick to the right
+        let t = Tetromino::with_rotation(p(-1, 0), Shape::T, Rotations::One);
+        assert!(t.to_minos().is_valid(&field));
+
+        let kicked = t.try_rotate(RotateDir::CCW, &field).unwrap();
+        assert_eq!(p(0, 0), kicked.root_pos);
+        assert_eq!(Rotations::Zero, kicked.rotation);
+        assert!(kicked.try_rotate(RotateDir::CW, &field).is_some());
+    }
 }
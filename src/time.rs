@@ -1,3 +1,4 @@
+use alloc::string::String;
 use core::ops::{Add, AddAssign, Sub};
 use core::time::Duration;
 use instant::Instant;
@@ -9,17 +10,28 @@ pub struct GameTime {
 
 pub struct GameClock {
     start_time: Instant,
+    /// Game time that had already passed when the clock started, for continued games
+    offset: Duration,
 }
 
 pub struct PausedClock {
     orig_start_time: Instant,
     pause_time: Instant,
+    offset: Duration,
 }
 
 impl GameClock {
     pub fn new() -> GameClock {
+        GameClock::resumed_at(GameTime {
+            since_start: Duration::from_millis(0),
+        })
+    }
+
+    /// A clock which reads `now` immediately, for continuing a saved game
+    pub fn resumed_at(now: GameTime) -> GameClock {
         GameClock {
             start_time: Instant::now(),
+            offset: now.since_start,
         }
     }
 
@@ -27,24 +39,113 @@ impl GameClock {
         PausedClock {
             orig_start_time: self.start_time,
             pause_time: Instant::now(),
+            offset: self.offset,
         }
     }
 
     pub fn now(&self) -> GameTime {
         return GameTime {
-            since_start: Instant::now() - self.start_time,
+            since_start: Instant::now() - self.start_time + self.offset,
         };
     }
 }
 
+impl GameTime {
+    pub fn from_millis(millis: u64) -> GameTime {
+        GameTime {
+            since_start: Duration::from_millis(millis),
+        }
+    }
+
+    pub fn as_millis(&self) -> u64 {
+        self.since_start.as_millis() as u64
+    }
+}
+
 impl PausedClock {
     pub fn resume(self) -> GameClock {
         GameClock {
             start_time: self.orig_start_time + (Instant::now() - self.pause_time),
+            offset: self.offset,
         }
     }
 }
 
+/// Counts down whole seconds of real time, regardless of any game clock
+pub struct Countdown {
+    start: Instant,
+    seconds: u32,
+}
+
+impl Countdown {
+    pub fn new(seconds: u32) -> Countdown {
+        Countdown {
+            start: Instant::now(),
+            seconds: seconds,
+        }
+    }
+
+    /// Seconds left, rounded up, or zero once the countdown is over
+    pub fn remaining(&self) -> u32 {
+        seconds_remaining(self.seconds, Instant::now() - self.start)
+    }
+}
+
+fn seconds_remaining(seconds: u32, elapsed: Duration) -> u32 {
+    match Duration::from_secs(seconds as u64).checked_sub(elapsed) {
+        Some(left) => ((left.as_millis() + 999) / 1000) as u32,
+        None => 0,
+    }
+}
+
+/// Seconds since the Unix epoch according to the system clock
+#[cfg(not(target_arch = "wasm32"))]
+pub fn unix_time() -> u64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Seconds since the Unix epoch according to the browser
+#[cfg(target_arch = "wasm32")]
+pub fn unix_time() -> u64 {
+    (stdweb::web::Date::now() / 1000.0) as u64
+}
+
+/// Formats a Unix time as a `year-month-day` date in UTC
+pub fn format_date(unix_time: u64) -> String {
+    // Convert days since the epoch to a proleptic Gregorian date, counting years from March so
+    // the leap day falls at the end
+    let days = (unix_time / 86_400) as i64 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = if month_from_march < 10 {
+        month_from_march + 3
+    } else {
+        month_from_march - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{}-{:02}-{:02}", year, month, day)
+}
+
+/// Formats as `minutes:seconds.centiseconds`
+pub fn format_duration(d: Duration) -> String {
+    let centis = d.as_millis() / 10;
+    format!(
+        "{}:{:02}.{:02}",
+        centis / 6000,
+        (centis / 100) % 60,
+        centis % 100
+    )
+}
+
 impl Add<Duration> for GameTime {
     type Output = Self;
     fn add(self, other: Duration) -> Self {
@@ -87,4 +188,27 @@ mod test {
         assert_eq!(a, c);
         assert_eq!(a + Duration::from_secs(1), b);
     }
+
+    #[test]
+    fn countdown() {
+        assert_eq!(3, seconds_remaining(3, Duration::from_millis(0)));
+        assert_eq!(3, seconds_remaining(3, Duration::from_millis(999)));
+        assert_eq!(2, seconds_remaining(3, Duration::from_millis(1000)));
+        assert_eq!(1, seconds_remaining(3, Duration::from_millis(2500)));
+        assert_eq!(0, seconds_remaining(3, Duration::from_millis(3000)));
+        assert_eq!(0, seconds_remaining(3, Duration::from_millis(4000)));
+    }
+
+    #[test]
+    fn date() {
+        assert_eq!("1970-01-01", format_date(0));
+        assert_eq!("2000-02-29", format_date(951_782_400));
+        assert_eq!("2023-11-14", format_date(1_700_000_000));
+    }
+
+    #[test]
+    fn format() {
+        assert_eq!("0:00.00", format_duration(Duration::from_millis(0)));
+        assert_eq!("1:05.43", format_duration(Duration::from_millis(65_432)));
+    }
 }
@@ -0,0 +1,83 @@
+use crate::time::GameTime;
+use core::time::Duration;
+
+/// How locked blocks are hidden in the hidden stack modes. Only drawing changes; the field
+/// itself plays as normal.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum StackVisibility {
+    /// Blocks fade away a few seconds after locking
+    Fading,
+    /// Blocks vanish as soon as they lock
+    Invisible,
+}
+
+/// How long a block stays fully shown in fading mode
+const FADE_DELAY: Duration = Duration::from_millis(3000);
+const FADE_DURATION: Duration = Duration::from_millis(1000);
+
+impl StackVisibility {
+    pub const ALL: [StackVisibility; 2] = [StackVisibility::Fading, StackVisibility::Invisible];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            StackVisibility::Fading => "Fading",
+            StackVisibility::Invisible => "Invisible",
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            StackVisibility::Fading => "fading",
+            StackVisibility::Invisible => "invisible",
+        }
+    }
+
+    pub fn from_code(code: &str) -> Option<StackVisibility> {
+        StackVisibility::ALL
+            .iter()
+            .copied()
+            .find(|v| v.code() == code)
+    }
+
+    /// How much a block which locked at `locked` shows at `now`, from 0 for hidden to 1 for
+    /// fully shown. Blocks with no lock time, like those of a continued game, count as having
+    /// locked long ago.
+    pub fn opacity(&self, locked: Option<GameTime>, now: GameTime) -> f32 {
+        let age = match locked {
+            Some(locked) if locked <= now => now - locked,
+            Some(_) => Duration::from_millis(0),
+            None => return 0.0,
+        };
+        match self {
+            StackVisibility::Fading if age < FADE_DELAY => 1.0,
+            StackVisibility::Fading => {
+                let faded =
+                    (age - FADE_DELAY).as_millis() as f32 / FADE_DURATION.as_millis() as f32;
+                (1.0 - faded).max(0.0)
+            }
+            StackVisibility::Invisible => 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opacity() {
+        let locked = Some(GameTime::from_millis(1000));
+        let fading = StackVisibility::Fading;
+        assert_eq!(1.0, fading.opacity(locked, GameTime::from_millis(1000)));
+        assert_eq!(1.0, fading.opacity(locked, GameTime::from_millis(3500)));
+        assert_eq!(0.5, fading.opacity(locked, GameTime::from_millis(4500)));
+        assert_eq!(0.0, fading.opacity(locked, GameTime::from_millis(9000)));
+        assert_eq!(0.0, fading.opacity(None, GameTime::from_millis(0)));
+
+        let invisible = StackVisibility::Invisible;
+        assert_eq!(0.0, invisible.opacity(locked, GameTime::from_millis(1000)));
+        for v in StackVisibility::ALL.iter() {
+            assert_eq!(Some(*v), StackVisibility::from_code(v.code()));
+        }
+    }
+}
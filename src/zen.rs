@@ -0,0 +1,99 @@
+use crate::gamestate::Summary;
+use crate::savegame::{SaveError, SaveReader, SaveWriter, Storage};
+use alloc::string::String;
+use core::time::Duration;
+
+/// Totals over every zen game played, kept between sessions
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct ZenTotals {
+    pub games: u32,
+    pub pieces: u32,
+    pub lines: u32,
+    pub elapsed: Duration,
+}
+
+const FORMAT_VERSION: u32 = 1;
+const SAVE_PROFILE: &str = "zen";
+
+impl ZenTotals {
+    pub fn new() -> Self {
+        ZenTotals {
+            games: 0,
+            pieces: 0,
+            lines: 0,
+            elapsed: Duration::from_millis(0),
+        }
+    }
+
+    /// The saved totals, or none if they are missing or unreadable
+    pub fn load(storage: &dyn Storage) -> Self {
+        storage
+            .retrieve(SAVE_PROFILE)
+            .and_then(|text| ZenTotals::decode(&text))
+            .unwrap_or_else(ZenTotals::new)
+    }
+
+    pub fn save(&self, storage: &dyn Storage) -> Result<(), SaveError> {
+        storage.store(SAVE_PROFILE, &self.encode())
+    }
+
+    /// These totals with one more game added
+    pub fn with_game(&self, summary: &Summary) -> ZenTotals {
+        ZenTotals {
+            games: self.games + 1,
+            pieces: self.pieces + summary.stats.pieces,
+            lines: self.lines + summary.stats.lines as u32,
+            elapsed: self.elapsed + summary.elapsed,
+        }
+    }
+
+    fn encode(&self) -> String {
+        let mut w = SaveWriter::new();
+        w.label("blocks-zen");
+        w.put(FORMAT_VERSION);
+        w.put(self.games);
+        w.put(self.pieces);
+        w.put(self.lines);
+        w.put_duration(self.elapsed);
+        w.finish()
+    }
+
+    fn decode(text: &str) -> Option<ZenTotals> {
+        let mut r = SaveReader::new(text);
+        r.label("blocks-zen")?;
+        if r.get::<u32>()? != FORMAT_VERSION {
+            return None;
+        }
+        Some(ZenTotals {
+            games: r.get()?,
+            pieces: r.get()?,
+            lines: r.get()?,
+            elapsed: r.get_duration()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mode::GameMode;
+    use crate::stats::Statistics;
+
+    #[test]
+    fn encode_decode() {
+        let summary = Summary {
+            mode: GameMode::Zen { gravity: false },
+            seed: 0,
+            stats: Statistics {
+                pieces: 30,
+                lines: 12,
+                ..Statistics::default()
+            },
+            elapsed: Duration::from_millis(90_000),
+        };
+        let totals = ZenTotals::new().with_game(&summary).with_game(&summary);
+        assert_eq!((2, 60, 24), (totals.games, totals.pieces, totals.lines));
+        assert_eq!(Some(totals), ZenTotals::decode(&totals.encode()));
+        assert_eq!(None, ZenTotals::decode("blocks-zen 0 1 2 3 4"));
+    }
+}